@@ -0,0 +1,95 @@
+use std::fmt;
+use std::str::FromStr;
+use framebuffer::Color;
+
+/// Arbitrary output variables: per-pixel surface data rendered next to the color image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aov {
+    /// Unit normal in world space
+    Normal,
+    /// Unit normal in camera space, with z pointing towards the viewer
+    ViewNormal,
+    /// Interpolated texture coordinates
+    Uv,
+    /// Index of the mesh in the depth-first order of the scene graph
+    ObjectId,
+    /// Index of the face within its mesh
+    FaceId,
+    /// Texture color times material color, without lighting
+    Albedo,
+    /// The depth buffer
+    Depth,
+}
+
+impl Aov {
+
+    pub fn all() -> [Aov; 7] {
+        [Aov::Normal, Aov::ViewNormal, Aov::Uv, Aov::ObjectId, Aov::FaceId, Aov::Albedo, Aov::Depth]
+    }
+
+    /// Value of pixels not covered by any object
+    pub fn background(&self) -> Color {
+        match self {
+            Aov::ObjectId | Aov::FaceId => [-1., -1., -1., 1.],
+            _                           => [0., 0., 0., 1.],
+        }
+    }
+
+    /// Map a raw value to an 8-bit color for image formats. Normals are scaled from [-1, 1] to [0, 1]
+    /// and IDs are stored plus one as 24-bit little-endian integers, so uncovered pixels are black.
+    pub fn encode(&self, value: &Color) -> [u8; 3] {
+        let to_u8 = |c: f64| (c*255.).round().max(0.).min(255.) as u8;
+        match self {
+            Aov::Normal | Aov::ViewNormal => {
+                [to_u8(value[0]*0.5 + 0.5), to_u8(value[1]*0.5 + 0.5), to_u8(value[2]*0.5 + 0.5)]
+            },
+            Aov::ObjectId | Aov::FaceId => {
+                let id = (value[0] + 1.).max(0.) as u32;
+                [id as u8, (id >> 8) as u8, (id >> 16) as u8]
+            },
+            _ => [to_u8(value[0]), to_u8(value[1]), to_u8(value[2])],
+        }
+    }
+
+}
+
+impl FromStr for Aov {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Aov, String> {
+        Aov::all().iter().cloned().find(|aov| aov.to_string() == s)
+            .ok_or_else(|| format!("unknown output variable '{}'", s))
+    }
+}
+
+impl fmt::Display for Aov {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Aov::Normal     => "normal",
+            Aov::ViewNormal => "view-normal",
+            Aov::Uv         => "uv",
+            Aov::ObjectId   => "object-id",
+            Aov::FaceId     => "face-id",
+            Aov::Albedo     => "albedo",
+            Aov::Depth      => "depth",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_and_encoding() {
+        for aov in Aov::all().iter() {
+            assert_eq!(aov.to_string().parse(), Ok(*aov));
+        }
+        assert!("specular".parse::<Aov>().is_err());
+        assert_eq!(Aov::Normal.encode(&[0., -1., 1., 1.]), [128, 0, 255]);
+        assert_eq!(Aov::FaceId.encode(&[300., 300., 300., 1.]), [45, 1, 0]);
+        assert_eq!(Aov::ObjectId.encode(&Aov::ObjectId.background()), [0, 0, 0]);
+    }
+
+}
@@ -0,0 +1,90 @@
+use geo;
+use geo::Vector;
+
+/// Axis-aligned bounding box
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: geo::Vec3f,
+    pub max: geo::Vec3f,
+}
+
+/// Bounding sphere
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sphere {
+    pub center: geo::Vec3f,
+    pub radius: f64,
+}
+
+impl Aabb {
+
+    /// Smallest box containing all `points`, or None if there are none
+    pub fn from_points<'a, I>(points: I) -> Option<Aabb>
+        where I: IntoIterator<Item=&'a geo::Vec3f>
+    {
+        let mut points = points.into_iter();
+        let first = *points.next()?;
+        Some(points.fold(Aabb{min: first, max: first}, |aabb, p| aabb.union(&Aabb{min: *p, max: *p})))
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb{min: geo::Vec3f::new(self.min.x.min(other.min.x), self.min.y.min(other.min.y), self.min.z.min(other.min.z)),
+             max: geo::Vec3f::new(self.max.x.max(other.max.x), self.max.y.max(other.max.y), self.max.z.max(other.max.z))}
+    }
+
+    pub fn center(&self) -> geo::Vec3f {
+        &(&self.min + &self.max)*0.5
+    }
+
+    /// Extent along each axis
+    pub fn size(&self) -> geo::Vec3f {
+        &self.max - &self.min
+    }
+
+    pub fn corners(&self) -> [geo::Vec3f; 8] {
+        let (a, b) = (self.min, self.max);
+        [geo::Vec3f::new(a.x, a.y, a.z), geo::Vec3f::new(b.x, a.y, a.z), geo::Vec3f::new(a.x, b.y, a.z),
+         geo::Vec3f::new(b.x, b.y, a.z), geo::Vec3f::new(a.x, a.y, b.z), geo::Vec3f::new(b.x, a.y, b.z),
+         geo::Vec3f::new(a.x, b.y, b.z), geo::Vec3f::new(b.x, b.y, b.z)]
+    }
+
+    /// Box around this one after transforming it by `m`
+    pub fn transform(&self, m: &geo::Mat4) -> Aabb {
+        let corners: Vec<geo::Vec3f> = self.corners().iter().map(|c| m.transform_point(c)).collect();
+        Aabb::from_points(corners.iter()).unwrap()
+    }
+
+}
+
+impl Sphere {
+
+    /// Sphere around the center of the bounding box of `points`, just reaching the farthest one
+    pub fn from_points<'a, I>(points: I) -> Option<Sphere>
+        where I: IntoIterator<Item=&'a geo::Vec3f> + Clone
+    {
+        let center = Aabb::from_points(points.clone())?.center();
+        let radius = points.into_iter().map(|p| (p - &center).norm()).fold(0., f64::max);
+        Some(Sphere{center, radius})
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn boxes_and_spheres() {
+        let points = [geo::Vec3f::new(1., -2., 0.), geo::Vec3f::new(3., 2., 0.), geo::Vec3f::new(2., 0., 4.)];
+        let aabb = Aabb::from_points(points.iter()).unwrap();
+        assert_eq!(aabb, Aabb{min: geo::Vec3f::new(1., -2., 0.), max: geo::Vec3f::new(3., 2., 4.)});
+        assert_eq!(aabb.center(), geo::Vec3f::new(2., 0., 2.));
+        assert_eq!(aabb.size(), geo::Vec3f::new(2., 4., 4.));
+        let moved = aabb.transform(&geo::Mat4::translation(&geo::Vec3f::new(0., 0., -1.)));
+        assert_eq!(moved.min, geo::Vec3f::new(1., -2., -1.));
+        let sphere = Sphere::from_points(points.iter()).unwrap();
+        assert_eq!(sphere.center, geo::Vec3f::new(2., 0., 2.));
+        assert_eq!(sphere.radius, 3.);
+        assert!(Aabb::from_points([].iter()).is_none());
+    }
+
+}
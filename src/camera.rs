@@ -0,0 +1,63 @@
+use geo;
+use geo::Vector;
+use bounds::Sphere;
+
+/// Perspective camera looking from `eye` towards `center`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Camera {
+    pub eye: geo::Vec3f,
+    pub center: geo::Vec3f,
+    pub up: geo::Vec3f,
+    /// Half the width and height of the view in the plane through `center`
+    pub extent: f64,
+}
+
+impl Camera {
+
+    pub fn new(eye: geo::Vec3f, center: geo::Vec3f, up: geo::Vec3f) -> Camera {
+        Camera{eye, center, up, extent: 1.}
+    }
+
+    pub fn with_extent(mut self, extent: f64) -> Self {
+        self.extent = extent;
+        self
+    }
+
+    /// The camera moved and zoomed to look at the center of `sphere` from its current direction,
+    /// so the whole sphere is in view with `margin`, a fraction of the view, left free around it
+    pub fn framing(&self, sphere: &Sphere, margin: f64) -> Camera {
+        let direction = (&self.eye - &self.center).normalize();
+        let radius = sphere.radius.max(1e-9);
+        let distance = 3.*radius;
+        // the cone from the eye that touches the sphere, cut by the plane through its center
+        let extent = distance*radius/(distance*distance - radius*radius).sqrt();
+        Camera{eye: &sphere.center + &(&direction*distance), center: sphere.center, up: self.up,
+               extent: extent/(1. - margin)}
+    }
+
+    pub fn view(&self) -> geo::Mat4 {
+        geo::Mat4::look_at(&self.eye, &self.center, &self.up)
+    }
+
+    /// Central projection onto the plane through `center`, scaled so that `extent` maps to the
+    /// edges of the screen
+    pub fn projection(&self) -> geo::Mat4 {
+        let mut proj = geo::Mat4::identity();
+        proj.m[0][0] = 1./self.extent;
+        proj.m[1][1] = 1./self.extent;
+        proj.m[3][2] = -1./(&self.eye - &self.center).norm();
+        proj
+    }
+
+    /// Combined world-to-screen transform
+    pub fn transform(&self) -> geo::Mat4 {
+        &self.projection()*&self.view()
+    }
+
+}
+
+impl Default for Camera {
+    fn default() -> Camera {
+        Camera::new(geo::Vec3f::new(0., 0., 3.), geo::Vec3f::new(0., 0., 0.), geo::Vec3f::new(0., 1., 0.))
+    }
+}
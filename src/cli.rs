@@ -0,0 +1,409 @@
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use geo;
+use render::{Shading, Transparency};
+use texture::{Filter, Wrap};
+use aov::Aov;
+use turntable::Motion;
+use output::{AovFormat, DepthFormat, ImageFormat};
+use stl::StlFormat;
+
+pub const USAGE: &str = "\
+Usage: rustyrenderer [OPTIONS] <MESH>...
+       rustyrenderer --scene <FILE> [-o <PATH>] [--format <FORMAT>] [--depth <PATH>]
+                     [--aov <NAME=PATH>]... [--pick <X,Y>]...
+                     [--turntable <FRAMES> [--motion <MODE>] [--fps <N>]]
+
+Render one or more meshes, Wavefront OBJ, STL, PLY or glTF by extension, to an image.
+
+Options:
+      --scene <FILE>        Render a JSON scene description; only --output, --format, --depth,
+                            --aov, --pick and the turntable options may be combined with it
+  -t, --texture <PATH>      Diffuse texture; the n-th texture belongs to the n-th mesh
+      --filter <FILTER>     nearest, bilinear, trilinear or anisotropic[:2-16] texture filtering
+                            [default: trilinear]
+      --wrap <MODE>         repeat, clamp or mirror texture wrapping [default: repeat]
+      --alpha-cutoff <T>    Discard texels with alpha below T in [0, 1] instead of blending them
+  -o, --output <PATH>       Output image, format inferred from the extension unless --format is
+                            given [default: render.png, or the extension of --format]
+      --turntable <FRAMES>  Render a full turn in FRAMES frames about the vertical axis through the
+                            center of the meshes, as an animation for a .gif output and otherwise
+                            as numbered images, depth and output variables such as render_000.png
+      --motion <MODE>       orbit (the camera circles) or rotate (the meshes turn in front of the
+                            camera and lights) for --turntable [default: orbit]
+      --fps <N>             Frame rate of turntable animations [default: 25]
+      --format <FORMAT>     Write the image as ppm (binary P6), ppm-ascii (P3), pfm or hdr; pfm and
+                            hdr keep colors above one from a float framebuffer
+      --depth <PATH>        Also save the depth buffer: .png as normalized 16-bit grayscale, .pfm as
+                            floats, .raw or .f32 as bare little-endian floats, top row first
+      --aov <NAME=PATH>     Also save an output variable: normal, view-normal, uv, object-id, face-id,
+                            albedo or depth; .png encodes 8-bit values, .pfm keeps floats. May be
+                            repeated
+      --pick <X,Y>          Print the mesh, face and world position under pixel X,Y of the image,
+                            counted from the top left; may be repeated, but not with --turntable
+      --export <PATH>       Also write the single input mesh to PATH, an .obj or .stl file
+      --stl-encoding <ENC>  ascii or binary encoding of exported STL files [default: binary]
+  -s, --size <WxH>          Image resolution in pixels [default: 800x800]
+  -c, --camera <X,Y,Z>      Camera position [default: 0,0,3]
+      --look-at <X,Y,Z>     Point the camera looks at [default: 0,0,0]
+      --frame <MARGIN>      Move and zoom the camera to fit all meshes, keeping its direction and
+                            leaving MARGIN, a fraction of the view in [0, 1), free around them
+  -l, --light <X,Y,Z>       Direction a light shines in; may be repeated [default: 0,0,-1]
+      --shading <MODE>      wireframe, flat, gouraud, textured or toon[:2-16 bands] [default: flat]
+      --outline <COLOR>     Draw silhouette and crease outlines in COLOR, as R,G,B or #RRGGBB
+      --transparency <MODE> sorted (back to front) or weighted (order-independent) blending of
+                            translucent faces [default: sorted]
+  -b, --background <COLOR>  Background as R,G,B or #RRGGBB [default: 0,0,0]
+  -h, --help                Print this message
+";
+
+/// Options that take a value
+const VALUE_OPTIONS: &[&str] = &[
+    "--scene", "-t", "--texture", "--filter", "--wrap", "--alpha-cutoff", "-o", "--output",
+    "--format", "--turntable", "--motion", "--fps", "--depth", "--aov", "--pick", "--export", "--stl-encoding", "-s", "--size", "-c", "--camera", "--look-at", "--frame", "-l", "--light",
+    "--shading", "--transparency", "--outline", "-b", "--background",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+    pub scene: Option<String>,
+    pub meshes: Vec<String>,
+    pub textures: Vec<String>,
+    pub filter: Filter,
+    pub wrap: Wrap,
+    pub alpha_cutoff: Option<f64>,
+    pub output: Option<String>,
+    pub format: Option<ImageFormat>,
+    pub turntable: Option<usize>,
+    pub motion: Motion,
+    pub fps: f64,
+    pub depth: Option<String>,
+    pub aovs: Vec<(Aov, String)>,
+    /// Pixels to report the geometry under, counted from the top left
+    pub picks: Vec<(u32, u32)>,
+    pub export: Option<String>,
+    pub stl_format: StlFormat,
+    pub width: u32,
+    pub height: u32,
+    pub camera: geo::Vec3f,
+    pub look_at: geo::Vec3f,
+    pub frame: Option<f64>,
+    pub lights: Vec<geo::Vec3f>,
+    pub shading: Shading,
+    pub transparency: Transparency,
+    pub outline: Option<[u8; 3]>,
+    pub background: [u8; 3],
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options{scene: None, meshes: Vec::new(), textures: Vec::new(), filter: Filter::Trilinear,
+                wrap: Wrap::Repeat, alpha_cutoff: None, output: None, format: None, turntable: None,
+                motion: Motion::Orbit, fps: 25., depth: None, aovs: Vec::new(), picks: Vec::new(), export: None,
+                stl_format: StlFormat::Binary, width: 800, height: 800,
+                camera: geo::Vec3f::new(0., 0., 3.), look_at: geo::Vec3f::new(0., 0., 0.), frame: None,
+                lights: Vec::new(), shading: Shading::Flat,
+                transparency: Transparency::Sorted, outline: None, background: [0, 0, 0]}
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Render(Options),
+    Help,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArgError(pub String);
+
+impl fmt::Display for ArgError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Parse the command line, excluding the program name
+pub fn parse<I>(args: I) -> Result<Command, ArgError>
+    where I: IntoIterator<Item=String>
+{
+    let mut options = Options::default();
+    let mut scene_only = true;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if !arg.starts_with('-') || arg == "-" {
+            options.meshes.push(arg);
+            scene_only = false;
+            continue;
+        }
+        if arg == "-h" || arg == "--help" {
+            return Ok(Command::Help);
+        }
+        if !VALUE_OPTIONS.contains(&arg.as_str()) {
+            return Err(ArgError(format!("unknown option '{}'", arg)));
+        }
+        let value = args.next().ok_or_else(|| ArgError(format!("missing value for '{}'", arg)))?;
+        scene_only &= arg == "--scene" || arg == "-o" || arg == "--output" || arg == "--format"
+            || arg == "--turntable" || arg == "--motion" || arg == "--fps" || arg == "--depth" || arg == "--aov"
+            || arg == "--pick";
+        match arg.as_str() {
+            "--scene"             => options.scene = Some(value),
+            "-t" | "--texture"    => options.textures.push(value),
+            "--filter"            => options.filter = value.parse().map_err(ArgError)?,
+            "--wrap"              => options.wrap = value.parse().map_err(ArgError)?,
+            "--alpha-cutoff"      => options.alpha_cutoff = Some(parse_threshold(&value)?),
+            "-o" | "--output"     => options.output = Some(value),
+            "--format"            => options.format = Some(value.parse().map_err(ArgError)?),
+            "--turntable"         => options.turntable = Some(parse_frames(&value)?),
+            "--motion"            => options.motion = value.parse().map_err(ArgError)?,
+            "--fps"               => options.fps = parse_fps(&value)?,
+            "--depth"             => {
+                if DepthFormat::from_path(Path::new(&value)).is_none() {
+                    return Err(ArgError(format!("unsupported depth format '{}', expected .png, .pfm, .raw or .f32",
+                                                value)));
+                }
+                options.depth = Some(value);
+            },
+            "--aov"               => options.aovs.push(parse_aov(&value)?),
+            "--pick"              => options.picks.push(parse_pixel(&value)?),
+            "--export"            => {
+                if !is_export(&value) {
+                    return Err(ArgError(format!("unsupported export format '{}', expected .obj or .stl", value)));
+                }
+                options.export = Some(value);
+            },
+            "--stl-encoding"      => options.stl_format = value.parse().map_err(ArgError)?,
+            "-s" | "--size"       => {
+                let (width, height) = parse_size(&value)?;
+                options.width = width;
+                options.height = height;
+            },
+            "-c" | "--camera"     => options.camera = parse_vec3(&arg, &value)?,
+            "--look-at"           => options.look_at = parse_vec3(&arg, &value)?,
+            "--frame"             => options.frame = Some(parse_margin(&value)?),
+            "-l" | "--light"      => options.lights.push(parse_vec3(&arg, &value)?),
+            "--shading"           => options.shading = value.parse().map_err(ArgError)?,
+            "--transparency"      => options.transparency = value.parse().map_err(ArgError)?,
+            "--outline"           => options.outline = Some(parse_color(&value)?),
+            "-b" | "--background" => options.background = parse_color(&value)?,
+            _                     => unreachable!(),
+        }
+    }
+    if options.turntable.is_some() && !options.picks.is_empty() {
+        return Err(ArgError(String::from("--pick cannot be combined with --turntable")));
+    }
+    if options.scene.is_some() {
+        if !scene_only {
+            return Err(ArgError(String::from("--scene can only be combined with --output, --format, --depth, --aov, --pick and the turntable options")));
+        }
+        return Ok(Command::Render(options));
+    }
+    if options.meshes.is_empty() {
+        return Err(ArgError(String::from("no input mesh given")));
+    }
+    if options.export.is_some() && options.meshes.len() != 1 {
+        return Err(ArgError(format!("--export needs a single input mesh, {} given", options.meshes.len())));
+    }
+    if options.textures.len() > options.meshes.len() {
+        return Err(ArgError(format!("{} textures given for {} meshes",
+                                    options.textures.len(), options.meshes.len())));
+    }
+    if options.camera == options.look_at {
+        return Err(ArgError(String::from("camera position and look-at point coincide")));
+    }
+    if options.lights.is_empty() {
+        options.lights.push(geo::Vec3f::new(0., 0., -1.));
+    }
+    Ok(Command::Render(options))
+}
+
+/// An output variable and the file to save it to, as NAME=PATH
+fn parse_aov(s: &str) -> Result<(Aov, String), ArgError> {
+    let mut parts = s.splitn(2, '=');
+    let (name, path) = match (parts.next(), parts.next()) {
+        (Some(name), Some(path)) if !path.is_empty() => (name, path),
+        _ => return Err(ArgError(format!("invalid output variable '{}', expected NAME=PATH", s))),
+    };
+    let aov: Aov = name.parse().map_err(ArgError)?;
+    let supported = match aov {
+        Aov::Depth => DepthFormat::from_path(Path::new(path)).is_some(),
+        _          => AovFormat::from_path(Path::new(path)).is_some(),
+    };
+    if !supported {
+        return Err(ArgError(format!("unsupported format for {} output '{}'", aov, path)));
+    }
+    Ok((aov, String::from(path)))
+}
+
+fn is_export(path: &str) -> bool {
+    match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+        Some("obj") | Some("stl") => true,
+        _                         => false,
+    }
+}
+
+fn parse_pixel(s: &str) -> Result<(u32, u32), ArgError> {
+    let err = || ArgError(format!("invalid pixel '{}', expected X,Y", s));
+    let mut parts = s.splitn(2, ',');
+    let x = parts.next().and_then(|x| x.trim().parse::<u32>().ok()).ok_or_else(err)?;
+    let y = parts.next().and_then(|y| y.trim().parse::<u32>().ok()).ok_or_else(err)?;
+    Ok((x, y))
+}
+
+fn parse_size(s: &str) -> Result<(u32, u32), ArgError> {
+    let err = || ArgError(format!("invalid size '{}', expected WIDTHxHEIGHT", s));
+    let mut parts = s.splitn(2, 'x');
+    let width = parts.next().and_then(|w| w.parse::<u32>().ok()).ok_or_else(err)?;
+    let height = parts.next().and_then(|h| h.parse::<u32>().ok()).ok_or_else(err)?;
+    if width == 0 || height == 0 {
+        return Err(err());
+    }
+    Ok((width, height))
+}
+
+fn parse_threshold(s: &str) -> Result<f64, ArgError> {
+    match s.parse::<f64>() {
+        Ok(t) if t >= 0. && t <= 1. => Ok(t),
+        _ => Err(ArgError(format!("invalid alpha cutoff '{}', expected a number between 0 and 1", s))),
+    }
+}
+
+fn parse_frames(s: &str) -> Result<usize, ArgError> {
+    match s.parse::<usize>() {
+        Ok(frames) if frames > 0 => Ok(frames),
+        _ => Err(ArgError(format!("invalid number of frames '{}', expected a positive integer", s))),
+    }
+}
+
+fn parse_fps(s: &str) -> Result<f64, ArgError> {
+    match s.parse::<f64>() {
+        Ok(fps) if fps > 0. && fps.is_finite() => Ok(fps),
+        _ => Err(ArgError(format!("invalid frame rate '{}', expected a positive number", s))),
+    }
+}
+
+fn parse_margin(s: &str) -> Result<f64, ArgError> {
+    match s.parse::<f64>() {
+        Ok(m) if m >= 0. && m < 1. => Ok(m),
+        _ => Err(ArgError(format!("invalid margin '{}', expected a number from 0 up to 1", s))),
+    }
+}
+
+fn parse_vec3(option: &str, s: &str) -> Result<geo::Vec3f, ArgError> {
+    let values = parse_list::<f64>(s)
+        .ok_or_else(|| ArgError(format!("invalid vector '{}' for '{}', expected X,Y,Z", s, option)))?;
+    Ok(geo::Vec3f::new(values[0], values[1], values[2]))
+}
+
+fn parse_color(s: &str) -> Result<[u8; 3], ArgError> {
+    let err = || ArgError(format!("invalid color '{}', expected R,G,B or #RRGGBB", s));
+    if s.starts_with('#') {
+        let hex = &s[1..];
+        if hex.len() != 6 {
+            return Err(err());
+        }
+        let mut color = [0u8; 3];
+        for i in 0..3 {
+            color[i] = u8::from_str_radix(&hex[2*i..2*i + 2], 16).map_err(|_| err())?;
+        }
+        Ok(color)
+    } else {
+        let values = parse_list::<u8>(s).ok_or_else(err)?;
+        Ok([values[0], values[1], values[2]])
+    }
+}
+
+/// Parse exactly three comma-separated values
+fn parse_list<T: FromStr>(s: &str) -> Option<[T; 3]>
+    where T: Copy
+{
+    let values: Vec<T> = s.split(',').map(|v| v.trim().parse::<T>().ok()).collect::<Option<_>>()?;
+    if values.len() != 3 {
+        return None;
+    }
+    Some([values[0], values[1], values[2]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn parse_defaults() {
+        let options = match parse(args("head.obj")).unwrap() {
+            Command::Render(options) => options,
+            Command::Help            => panic!("expected render command"),
+        };
+        assert_eq!(options.meshes, vec!["head.obj"]);
+        assert_eq!((options.width, options.height), (800, 800));
+        assert_eq!(options.lights, vec![geo::Vec3f::new(0., 0., -1.)]);
+    }
+
+    #[test]
+    fn parse_all_options() {
+        let command = parse(args("a.obj -t a.tga b.obj -o out.png -s 640x480 -c 1,2,3 --look-at 0,1,0 --frame 0.1 \
+                                  -l 1,0,0 -l 0,-1,0 --shading toon:3 --outline 0,0,0 -b #ff8000 --filter nearest \
+                                  --wrap mirror --transparency weighted --alpha-cutoff 0.5 --format hdr \
+                                  --turntable 36 --motion rotate --fps 12.5")).unwrap();
+        let options = match command {
+            Command::Render(options) => options,
+            Command::Help            => panic!("expected render command"),
+        };
+        assert_eq!(options.meshes, vec!["a.obj", "b.obj"]);
+        assert_eq!(options.textures, vec!["a.tga"]);
+        assert_eq!((options.filter, options.wrap), (Filter::Nearest, Wrap::Mirror));
+        assert_eq!(options.alpha_cutoff, Some(0.5));
+        assert_eq!(options.output, Some(String::from("out.png")));
+        assert_eq!(options.format, Some(ImageFormat::Hdr));
+        assert_eq!((options.turntable, options.motion, options.fps), (Some(36), Motion::Rotate, 12.5));
+        assert_eq!((options.width, options.height), (640, 480));
+        assert_eq!(options.camera, geo::Vec3f::new(1., 2., 3.));
+        assert_eq!(options.look_at, geo::Vec3f::new(0., 1., 0.));
+        assert_eq!(options.frame, Some(0.1));
+        assert_eq!(options.lights.len(), 2);
+        assert_eq!(options.shading, Shading::Toon(3));
+        assert_eq!(options.outline, Some([0, 0, 0]));
+        assert_eq!(options.transparency, Transparency::Weighted);
+        assert_eq!(options.background, [255, 128, 0]);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(parse(args("")).is_err());
+        assert!(parse(args("a.obj --size 0x10")).is_err());
+        assert!(parse(args("a.obj --camera 1,2")).is_err());
+        assert!(parse(args("a.obj --format exr")).is_err());
+        assert!(parse(args("a.obj --turntable 0")).is_err());
+        assert!(parse(args("a.obj --turntable 36 --motion spin")).is_err());
+        assert!(parse(args("a.obj --shading phong")).is_err());
+        assert!(parse(args("a.obj --shading toon:1")).is_err());
+        assert!(parse(args("a.obj --alpha-cutoff 2")).is_err());
+        assert!(parse(args("a.obj --frame 1")).is_err());
+        assert_eq!(parse(args("a.obj --frobnicate")), Err(ArgError(String::from("unknown option '--frobnicate'"))));
+        assert!(parse(args("a.obj --output")).is_err());
+        assert!(parse(args("--scene s.json a.obj")).is_err());
+        assert!(parse(args("--scene s.json --shading flat")).is_err());
+        assert!(parse(args("--scene s.json -o out.png")).is_ok());
+        assert!(parse(args("--scene s.json -o spin.gif --turntable 24 --fps 12")).is_ok());
+        assert!(parse(args("--scene s.json --depth depth.pfm")).is_ok());
+        assert!(parse(args("a.obj --depth depth.exr")).is_err());
+        assert!(parse(args("a.obj --aov normal")).is_err());
+        assert!(parse(args("a.obj --aov normal=n.raw")).is_err());
+        assert!(parse(args("a.obj --aov depth=d.raw")).is_ok());
+        assert!(parse(args("--scene s.json --aov object-id=ids.png")).is_ok());
+        assert!(parse(args("--scene s.json --pick 10,20 --pick 0,0")).is_ok());
+        assert!(parse(args("a.obj --pick 10")).is_err());
+        assert!(parse(args("a.obj --pick 10,20 --turntable 4")).is_err());
+        assert!(parse(args("a.obj --export a.stl --stl-encoding ascii")).is_ok());
+        assert!(parse(args("a.stl --export a.obj")).is_ok());
+        assert!(parse(args("a.obj b.obj --export a.stl")).is_err());
+        assert!(parse(args("a.obj --export a.3ds")).is_err());
+        assert!(parse(args("a.obj --stl-encoding utf8")).is_err());
+        assert_eq!(parse(args("--help")).unwrap(), Command::Help);
+    }
+
+}
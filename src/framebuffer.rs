@@ -0,0 +1,486 @@
+use std::f64::INFINITY;
+use image;
+use tga;
+
+/// Linear RGBA color, nominally in [0, 1]
+pub type Color = [f64; 4];
+
+/// How the rasterizer combines a shaded pixel with the target
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Blend {
+    /// Overwrite the color and the depth
+    Replace,
+    /// Alpha-blend over the color, leaving the depth untouched
+    Over,
+    /// Add to the weighted-blended transparency buffers, leaving the depth untouched
+    Weighted,
+}
+
+/// Standard over operator: `src` with its alpha in front of `dst`
+pub fn over(src: &Color, dst: &Color) -> Color {
+    let a = src[3];
+    [src[0]*a + dst[0]*(1. - a), src[1]*a + dst[1]*(1. - a), src[2]*a + dst[2]*(1. - a), a + dst[3]*(1. - a)]
+}
+
+/// Anything the rasterizer can draw into. Coordinates outside the target are ignored.
+pub trait RenderTarget {
+    fn dimensions(&self) -> (u32, u32);
+
+    /// Stored depth at (x, y), larger is closer; None outside the target
+    fn depth(&self, x: u32, y: u32) -> Option<f64>;
+
+    fn set_depth(&mut self, x: u32, y: u32, depth: f64);
+
+    /// Color at (x, y); None outside the target
+    fn color(&self, x: u32, y: u32) -> Option<Color>;
+
+    fn set_color(&mut self, x: u32, y: u32, color: &Color);
+
+    /// Whether colors above one are stored as they are rather than clipped
+    fn high_dynamic_range(&self) -> bool {
+        false
+    }
+
+    /// Blend `color` over the stored color according to its alpha
+    fn blend_color(&mut self, x: u32, y: u32, color: &Color) {
+        if let Some(dst) = self.color(x, y) {
+            self.set_color(x, y, &over(color, &dst));
+        }
+    }
+
+    /// Add a transparent pixel at depth `depth` for order-independent blending. Targets without
+    /// the necessary buffers blend it over right away.
+    fn accumulate(&mut self, x: u32, y: u32, color: &Color, _depth: f64) {
+        self.blend_color(x, y, color);
+    }
+
+    /// Tag colors set from now on as coming from face `face` of object `object`
+    fn set_primitive(&mut self, _object: usize, _face: usize) {}
+}
+
+/// A width by height grid of values with bounds-checked access, row 0 at the bottom
+#[derive(Debug, Clone, PartialEq)]
+pub struct Buffer<T> {
+    width: u32,
+    height: u32,
+    data: Vec<T>,
+}
+
+impl<T: Copy> Buffer<T> {
+
+    pub fn new(width: u32, height: u32, value: T) -> Buffer<T> {
+        Buffer{width, height, data: vec![value; (width*height) as usize]}
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn index(&self, x: u32, y: u32) -> Option<usize> {
+        if x < self.width && y < self.height {
+            Some((x + y*self.width) as usize)
+        } else {
+            None
+        }
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> Option<T> {
+        self.index(x, y).map(|i| self.data[i])
+    }
+
+    /// Store `value` at (x, y); returns false, leaving the buffer untouched, if (x, y) is outside
+    pub fn set(&mut self, x: u32, y: u32, value: T) -> bool {
+        match self.index(x, y) {
+            Some(i) => { self.data[i] = value; true },
+            None    => false,
+        }
+    }
+
+    pub fn fill(&mut self, value: T) {
+        for v in self.data.iter_mut() {
+            *v = value;
+        }
+    }
+
+    /// All values, row by row from the bottom
+    pub fn values(&self) -> &[T] {
+        &self.data
+    }
+
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Rgb8,
+    Rgba8,
+    Rgb32F,
+}
+
+/// A color buffer in one of the supported formats
+#[derive(Clone)]
+pub enum Attachment {
+    Rgb8(Buffer<[u8; 3]>),
+    Rgba8(Buffer<[u8; 4]>),
+    Rgb32F(Buffer<[f32; 3]>),
+}
+
+/// Clamp a color component to 8 bits
+pub fn to_u8(c: f64) -> u8 {
+    (c*255.).round().max(0.).min(255.) as u8
+}
+
+impl Attachment {
+
+    pub fn new(format: Format, width: u32, height: u32) -> Attachment {
+        match format {
+            Format::Rgb8   => Attachment::Rgb8(Buffer::new(width, height, [0; 3])),
+            Format::Rgba8  => Attachment::Rgba8(Buffer::new(width, height, [0; 4])),
+            Format::Rgb32F => Attachment::Rgb32F(Buffer::new(width, height, [0.; 3])),
+        }
+    }
+
+    pub fn format(&self) -> Format {
+        match self {
+            Attachment::Rgb8(_)   => Format::Rgb8,
+            Attachment::Rgba8(_)  => Format::Rgba8,
+            Attachment::Rgb32F(_) => Format::Rgb32F,
+        }
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        match self {
+            Attachment::Rgb8(buf)   => buf.dimensions(),
+            Attachment::Rgba8(buf)  => buf.dimensions(),
+            Attachment::Rgb32F(buf) => buf.dimensions(),
+        }
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> Option<Color> {
+        match self {
+            Attachment::Rgb8(buf)   => buf.get(x, y).map(|c| [c[0] as f64/255., c[1] as f64/255.,
+                                                               c[2] as f64/255., 1.]),
+            Attachment::Rgba8(buf)  => buf.get(x, y).map(|c| [c[0] as f64/255., c[1] as f64/255.,
+                                                               c[2] as f64/255., c[3] as f64/255.]),
+            Attachment::Rgb32F(buf) => buf.get(x, y).map(|c| [c[0] as f64, c[1] as f64, c[2] as f64, 1.]),
+        }
+    }
+
+    /// Store `color`, clamped to the range of the format; false if (x, y) is outside
+    pub fn set(&mut self, x: u32, y: u32, color: &Color) -> bool {
+        match self {
+            Attachment::Rgb8(buf)   => buf.set(x, y, [to_u8(color[0]), to_u8(color[1]), to_u8(color[2])]),
+            Attachment::Rgba8(buf)  => buf.set(x, y, [to_u8(color[0]), to_u8(color[1]), to_u8(color[2]),
+                                                      to_u8(color[3])]),
+            Attachment::Rgb32F(buf) => buf.set(x, y, [color[0] as f32, color[1] as f32, color[2] as f32]),
+        }
+    }
+
+    pub fn fill(&mut self, color: &Color) {
+        let (width, height) = self.dimensions();
+        for y in 0..height {
+            for x in 0..width {
+                self.set(x, y, color);
+            }
+        }
+    }
+
+    /// The buffer as an image with the top row first; float colors are clamped to 8 bits
+    pub fn to_image(&self) -> image::DynamicImage {
+        let (width, height) = self.dimensions();
+        match self {
+            Attachment::Rgba8(buf) => {
+                image::ImageRgba8(image::RgbaImage::from_fn(width, height, |x, y| {
+                    image::Rgba(buf.get(x, height - 1 - y).unwrap())
+                }))
+            },
+            _ => {
+                image::ImageRgb8(image::RgbImage::from_fn(width, height, |x, y| {
+                    let c = self.get(x, height - 1 - y).unwrap();
+                    image::Rgb([to_u8(c[0]), to_u8(c[1]), to_u8(c[2])])
+                }))
+            },
+        }
+    }
+
+    /// The buffer as a TGA image, which keeps the bottom row first; float colors are clamped to 8 bits
+    pub fn to_tga(&self) -> tga::Image {
+        let (width, height) = self.dimensions();
+        let channels = if self.format() == Format::Rgba8 { 4 } else { 3 };
+        let mut img = tga::Image::new(width, height, channels);
+        for y in 0..height {
+            for x in 0..width {
+                let c = self.get(x, y).unwrap();
+                for (i, byte) in img.pixel_mut(x, y).iter_mut().enumerate() {
+                    *byte = to_u8(c[i]);
+                }
+            }
+        }
+        img
+    }
+
+}
+
+/// Color attachments with a shared depth buffer, an optional stencil buffer and an optional buffer of
+/// primitive IDs. The first color attachment receives everything the rasterizer draws.
+#[derive(Clone)]
+pub struct Framebuffer {
+    width: u32,
+    height: u32,
+    colors: Vec<Attachment>,
+    depth: Buffer<f64>,
+    stencil: Option<Buffer<u8>>,
+    ids: Option<Buffer<Option<(usize, usize)>>>,
+    primitive: (usize, usize),
+    /// Weighted sum of premultiplied colors and of weights, and the product of transmittances
+    oit: Option<(Buffer<[f64; 4]>, Buffer<f64>)>,
+}
+
+impl Framebuffer {
+
+    pub fn new(width: u32, height: u32, format: Format) -> Framebuffer {
+        Framebuffer{width, height, colors: vec![Attachment::new(format, width, height)],
+                    depth: Buffer::new(width, height, -INFINITY), stencil: None,
+                    ids: None, primitive: (0, 0), oit: None}
+    }
+
+    /// Add another color attachment of the same size
+    pub fn with_attachment(mut self, format: Format) -> Self {
+        self.colors.push(Attachment::new(format, self.width, self.height));
+        self
+    }
+
+    /// Replace the first color attachment with an empty one in `format`
+    pub fn with_color_format(mut self, format: Format) -> Self {
+        self.colors[0] = Attachment::new(format, self.width, self.height);
+        self
+    }
+
+    pub fn with_stencil(mut self) -> Self {
+        self.stencil = Some(Buffer::new(self.width, self.height, 0));
+        self
+    }
+
+    /// Record the object and face index of the primitive that last set each pixel
+    pub fn with_ids(mut self) -> Self {
+        if self.ids.is_none() {
+            self.ids = Some(Buffer::new(self.width, self.height, None));
+        }
+        self
+    }
+
+    /// Add the buffers for weighted-blended order-independent transparency
+    pub fn with_oit(mut self) -> Self {
+        if self.oit.is_none() {
+            self.oit = Some((Buffer::new(self.width, self.height, [0.; 4]), Buffer::new(self.width, self.height, 1.)));
+        }
+        self
+    }
+
+    /// Composite the accumulated transparent pixels over the first color attachment, then reset them
+    pub fn resolve_oit(&mut self) {
+        let (accum, revealage) = match self.oit {
+            Some((ref mut accum, ref mut revealage)) => (accum, revealage),
+            None                                     => return,
+        };
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let (sum, reveal) = (accum.get(x, y).unwrap(), revealage.get(x, y).unwrap());
+                if reveal >= 1. {
+                    continue;
+                }
+                let dst = self.colors[0].get(x, y).unwrap();
+                let weight = sum[3].max(1e-5);
+                let src = [sum[0]/weight, sum[1]/weight, sum[2]/weight, 1. - reveal];
+                self.colors[0].set(x, y, &over(&src, &dst));
+            }
+        }
+        accum.fill([0.; 4]);
+        revealage.fill(1.);
+    }
+
+    pub fn color(&self, index: usize) -> Option<&Attachment> {
+        self.colors.get(index)
+    }
+
+    pub fn color_mut(&mut self, index: usize) -> Option<&mut Attachment> {
+        self.colors.get_mut(index)
+    }
+
+    pub fn depth_buffer(&self) -> &Buffer<f64> {
+        &self.depth
+    }
+
+    /// Object and face index per pixel, None where nothing was drawn
+    pub fn ids(&self) -> Option<&Buffer<Option<(usize, usize)>>> {
+        self.ids.as_ref()
+    }
+
+    pub fn stencil(&self) -> Option<&Buffer<u8>> {
+        self.stencil.as_ref()
+    }
+
+    pub fn stencil_mut(&mut self) -> Option<&mut Buffer<u8>> {
+        self.stencil.as_mut()
+    }
+
+    /// Fill all color attachments with `color`, reset depth to infinitely far, stencil to 0 and IDs
+    /// to none
+    pub fn clear(&mut self, color: &Color) {
+        for attachment in self.colors.iter_mut() {
+            attachment.fill(color);
+        }
+        self.depth.fill(-INFINITY);
+        if let Some(ref mut stencil) = self.stencil {
+            stencil.fill(0);
+        }
+        if let Some(ref mut ids) = self.ids {
+            ids.fill(None);
+        }
+        if let Some((ref mut accum, ref mut revealage)) = self.oit {
+            accum.fill([0.; 4]);
+            revealage.fill(1.);
+        }
+    }
+
+    /// The first color attachment as an image with the top row first
+    pub fn to_image(&self) -> image::DynamicImage {
+        self.colors[0].to_image()
+    }
+
+    /// The first color attachment as a TGA image with the bottom row first
+    pub fn to_tga(&self) -> tga::Image {
+        self.colors[0].to_tga()
+    }
+
+}
+
+impl RenderTarget for Framebuffer {
+
+    fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn depth(&self, x: u32, y: u32) -> Option<f64> {
+        self.depth.get(x, y)
+    }
+
+    fn set_depth(&mut self, x: u32, y: u32, depth: f64) {
+        self.depth.set(x, y, depth);
+    }
+
+    fn color(&self, x: u32, y: u32) -> Option<Color> {
+        self.colors[0].get(x, y)
+    }
+
+    fn set_color(&mut self, x: u32, y: u32, color: &Color) {
+        self.colors[0].set(x, y, color);
+        if let Some(ref mut ids) = self.ids {
+            ids.set(x, y, Some(self.primitive));
+        }
+    }
+
+    fn high_dynamic_range(&self) -> bool {
+        self.colors[0].format() == Format::Rgb32F
+    }
+
+    fn set_primitive(&mut self, object: usize, face: usize) {
+        self.primitive = (object, face);
+    }
+
+    /// Weighted-blended order-independent transparency after McGuire and Bavoil (2013), with
+    /// weights growing for closer pixels
+    fn accumulate(&mut self, x: u32, y: u32, color: &Color, depth: f64) {
+        let (accum, revealage) = match self.oit {
+            Some((ref mut accum, ref mut revealage)) => (accum, revealage),
+            None => {
+                self.blend_color(x, y, color);
+                return;
+            },
+        };
+        let (sum, reveal) = match (accum.get(x, y), revealage.get(x, y)) {
+            (Some(sum), Some(reveal)) => (sum, reveal),
+            _                         => return,
+        };
+        let a = color[3];
+        let weight = a*(2.*depth).exp().max(1e-2).min(3e3);
+        accum.set(x, y, [sum[0] + color[0]*weight, sum[1] + color[1]*weight, sum[2] + color[2]*weight,
+                         sum[3] + weight]);
+        revealage.set(x, y, reveal*(1. - a));
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::GenericImage;
+
+    #[test]
+    fn bounds_checked() {
+        let mut fb = Framebuffer::new(4, 2, Format::Rgb8).with_stencil();
+        assert_eq!(fb.depth(3, 1), Some(-INFINITY));
+        assert_eq!(fb.depth(4, 0), None);
+        assert_eq!(fb.depth(0, 2), None);
+        fb.set_depth(10, 10, 1.);
+        fb.set_color(10, 10, &[1., 1., 1., 1.]);
+        assert!(fb.stencil_mut().unwrap().set(3, 1, 7));
+        assert!(!fb.stencil_mut().unwrap().set(4, 1, 7));
+        assert_eq!(fb.stencil().unwrap().get(3, 1), Some(7));
+        fb.clear(&[0., 0., 0., 1.]);
+        assert_eq!(fb.stencil().unwrap().get(3, 1), Some(0));
+
+        let mut fb = fb.with_ids();
+        fb.set_primitive(2, 5);
+        fb.set_color(1, 1, &[1., 1., 1., 1.]);
+        assert_eq!(fb.ids().unwrap().get(1, 1), Some(Some((2, 5))));
+        assert_eq!(fb.ids().unwrap().get(0, 1), Some(None));
+    }
+
+    #[test]
+    fn formats() {
+        let color = [2., 0.5, -1., 0.25];
+        let mut fb = Framebuffer::new(2, 2, Format::Rgb8).with_attachment(Format::Rgba8)
+            .with_attachment(Format::Rgb32F);
+        for i in 0..3 {
+            fb.color_mut(i).unwrap().set(0, 0, &color);
+        }
+        assert_eq!(fb.color(0).unwrap().get(0, 0), Some([1., 128./255., 0., 1.]));
+        assert_eq!(fb.color(1).unwrap().get(0, 0), Some([1., 128./255., 0., 64./255.]));
+        assert_eq!(fb.color(2).unwrap().get(0, 0), Some([2., 0.5, -1., 1.]));
+        assert_eq!(fb.color(2).unwrap().format(), Format::Rgb32F);
+        assert!(fb.color(3).is_none());
+        // row 0 is the bottom of the framebuffer but the top of the image
+        assert_eq!(fb.to_image().get_pixel(0, 1).data, [255, 128, 0, 255]);
+    }
+
+    #[test]
+    fn blending() {
+        let mut fb = Framebuffer::new(1, 1, Format::Rgb32F).with_oit();
+        let (red, blue) = ([1., 0., 0., 0.5], [0., 0., 1., 0.5]);
+        fb.clear(&[0., 0., 0., 1.]);
+        fb.blend_color(0, 0, &red);
+        fb.blend_color(0, 0, &blue);
+        assert_eq!(RenderTarget::color(&fb, 0, 0), Some([0.25, 0., 0.5, 1.]));
+
+        // equal depths weigh the same whatever the drawing order
+        let mut resolved = Vec::new();
+        for order in [[red, blue], [blue, red]].iter() {
+            fb.clear(&[0., 0., 0., 1.]);
+            for color in order.iter() {
+                fb.accumulate(0, 0, color, 0.);
+            }
+            fb.resolve_oit();
+            resolved.push(RenderTarget::color(&fb, 0, 0).unwrap());
+        }
+        assert_eq!(resolved[0], resolved[1]);
+        assert!((resolved[0][0] - 0.375).abs() < 1e-6 && (resolved[0][2] - 0.375).abs() < 1e-6);
+        // the closer layer dominates
+        fb.clear(&[0., 0., 0., 1.]);
+        fb.accumulate(0, 0, &red, 1.);
+        fb.accumulate(0, 0, &blue, -1.);
+        fb.resolve_oit();
+        let color = RenderTarget::color(&fb, 0, 0).unwrap();
+        assert!(color[0] > 0.6 && color[2] < 0.2);
+    }
+
+}
@@ -235,6 +235,166 @@ impl<T> Into<[T; 3]> for Vec3<T>
     }
 }
 
+// Mat4 impl
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mat4 {
+    pub m: [[f64; 4]; 4],
+}
+
+impl Mat4 {
+    pub fn identity() -> Mat4 {
+        let mut m = [[0.; 4]; 4];
+        for i in 0..4 {
+            m[i][i] = 1.;
+        }
+        Mat4{m}
+    }
+
+    pub fn translation(t: &Vec3f) -> Mat4 {
+        let mut mat = Mat4::identity();
+        mat.m[0][3] = t.x;
+        mat.m[1][3] = t.y;
+        mat.m[2][3] = t.z;
+        mat
+    }
+
+    pub fn scale(s: &Vec3f) -> Mat4 {
+        let mut mat = Mat4::identity();
+        mat.m[0][0] = s.x;
+        mat.m[1][1] = s.y;
+        mat.m[2][2] = s.z;
+        mat
+    }
+
+    /// Rotation about the x-axis, angle in radians
+    pub fn rotation_x(angle: f64) -> Mat4 {
+        let (sin, cos) = angle.sin_cos();
+        let mut mat = Mat4::identity();
+        mat.m[1][1] = cos; mat.m[1][2] = -sin;
+        mat.m[2][1] = sin; mat.m[2][2] = cos;
+        mat
+    }
+
+    /// Rotation about the y-axis, angle in radians
+    pub fn rotation_y(angle: f64) -> Mat4 {
+        let (sin, cos) = angle.sin_cos();
+        let mut mat = Mat4::identity();
+        mat.m[0][0] = cos; mat.m[0][2] = sin;
+        mat.m[2][0] = -sin; mat.m[2][2] = cos;
+        mat
+    }
+
+    /// Rotation about the z-axis, angle in radians
+    pub fn rotation_z(angle: f64) -> Mat4 {
+        let (sin, cos) = angle.sin_cos();
+        let mut mat = Mat4::identity();
+        mat.m[0][0] = cos; mat.m[0][1] = -sin;
+        mat.m[1][0] = sin; mat.m[1][1] = cos;
+        mat
+    }
+
+    /// View matrix looking from `eye` towards `center`; `center` ends up at the origin.
+    pub fn look_at(eye: &Vec3f, center: &Vec3f, up: &Vec3f) -> Mat4 {
+        let z = (eye - center).normalize();
+        let x = up.cross(&z).normalize();
+        let y = z.cross(&x).normalize();
+        let mut rot = Mat4::identity();
+        for (i, axis) in [x, y, z].iter().enumerate() {
+            rot.m[i][0] = axis.x;
+            rot.m[i][1] = axis.y;
+            rot.m[i][2] = axis.z;
+        }
+        &rot*&Mat4::translation(&(center*(-1.)))
+    }
+
+    pub fn transpose(&self) -> Mat4 {
+        let mut m = [[0.; 4]; 4];
+        for i in 0..4 {
+            for j in 0..4 {
+                m[i][j] = self.m[j][i];
+            }
+        }
+        Mat4{m}
+    }
+
+    /// Inverse by Gauss-Jordan elimination; None if the matrix is singular
+    pub fn inverse(&self) -> Option<Mat4> {
+        let mut a = self.m;
+        let mut inv = Mat4::identity().m;
+        for col in 0..4 {
+            let pivot = (col..4).max_by(|&i, &j| a[i][col].abs().partial_cmp(&a[j][col].abs()).unwrap())?;
+            if a[pivot][col].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+            let scale = 1./a[col][col];
+            for j in 0..4 {
+                a[col][j] *= scale;
+                inv[col][j] *= scale;
+            }
+            for row in 0..4 {
+                if row == col {
+                    continue;
+                }
+                let factor = a[row][col];
+                for j in 0..4 {
+                    a[row][j] -= factor*a[col][j];
+                    inv[row][j] -= factor*inv[col][j];
+                }
+            }
+        }
+        Some(Mat4{m: inv})
+    }
+
+    pub fn transform_point(&self, p: &Vec3f) -> Vec3f {
+        let [x, y, z, _] = self.transform_homogeneous(p, 1.);
+        Vec3f::new(x, y, z)
+    }
+
+    pub fn transform_vector(&self, v: &Vec3f) -> Vec3f {
+        let [x, y, z, _] = self.transform_homogeneous(v, 0.);
+        Vec3f::new(x, y, z)
+    }
+
+    /// Transform a point and divide by w; None if the point lies behind the projection center.
+    pub fn project(&self, p: &Vec3f) -> Option<Vec3f> {
+        let [x, y, z, w] = self.transform_homogeneous(p, 1.);
+        if w <= 0. {
+            return None;
+        }
+        Some(Vec3f::new(x/w, y/w, z/w))
+    }
+
+    fn transform_homogeneous(&self, v: &Vec3f, w: f64) -> [f64; 4] {
+        let v = [v.x, v.y, v.z, w];
+        let mut out = [0.; 4];
+        for i in 0..4 {
+            for j in 0..4 {
+                out[i] += self.m[i][j]*v[j];
+            }
+        }
+        out
+    }
+
+}
+
+impl<'a, 'b> ops::Mul<&'b Mat4> for &'a Mat4 {
+    type Output = Mat4;
+    fn mul(self, other: &'b Mat4) -> Mat4 {
+        let mut m = [[0.; 4]; 4];
+        for i in 0..4 {
+            for j in 0..4 {
+                for k in 0..4 {
+                    m[i][j] += self.m[i][k]*other.m[k][j];
+                }
+            }
+        }
+        Mat4{m}
+    }
+}
+
 // typedefs
 pub type Vec3f = Vec3<f64>;
 pub type Vec2f = Vec2<f64>;
@@ -297,5 +457,34 @@ mod test {
         assert_eq!(first.cross(&second).dot(&second), 0 as f64);
         assert_eq!(second.cross(&first), (&first.cross(&second))*(-1 as f64));
     }
+
+    #[test]
+    fn mat4_transform() {
+        let mat = &Mat4::translation(&Vec3f::new(1., 2., 3.))*&Mat4::scale(&Vec3f::new(2., 2., 2.));
+        assert_eq!(mat.transform_point(&Vec3f::new(1., 1., 1.)), Vec3f::new(3., 4., 5.));
+        assert_eq!(mat.transform_vector(&Vec3f::new(1., 1., 1.)), Vec3f::new(2., 2., 2.));
+    }
+
+    #[test]
+    fn mat4_inverse() {
+        let mat = &(&Mat4::translation(&Vec3f::new(1., 2., 3.))*&Mat4::rotation_y(0.3))
+            *&Mat4::scale(&Vec3f::new(2., 3., 4.));
+        let prod = &mat*&mat.inverse().unwrap();
+        for i in 0..4 {
+            for j in 0..4 {
+                assert!((prod.m[i][j] - Mat4::identity().m[i][j]).abs() < 1e-12);
+            }
+        }
+        assert_eq!(Mat4::scale(&Vec3f::new(1., 0., 1.)).inverse(), None);
+    }
+
+    #[test]
+    fn mat4_look_at() {
+        let view = Mat4::look_at(&Vec3f::new(0., 0., 3.), &Vec3f::new(0., 0., 0.), &Vec3f::new(0., 1., 0.));
+        assert_eq!(view, Mat4::identity());
+        let view = Mat4::look_at(&Vec3f::new(3., 0., 0.), &Vec3f::new(0., 0., 0.), &Vec3f::new(0., 1., 0.));
+        let p = view.transform_point(&Vec3f::new(1., 0., 0.));
+        assert!((&p - &Vec3f::new(0., 0., 1.)).norm() < 1e-12);
+    }
 }
 
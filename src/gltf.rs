@@ -0,0 +1,630 @@
+//! glTF 2.0 import.
+//!
+//! Meshes, the node hierarchy of the default scene and the base color part of the
+//! metallic-roughness materials are read from `.gltf` files with embedded or external buffers and
+//! from binary `.glb` files. Animations, skins, morph targets, cameras and the metallic and
+//! roughness terms, which the shading model has no use for, are skipped.
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::str;
+use image;
+use image::GenericImage;
+use geo;
+use json;
+use json::Value;
+use obj;
+use obj::Obj;
+use graph::Node;
+use texture;
+use texture::{Filter, Wrap};
+use transform::Transform;
+
+/// Whether `path` names a glTF file by its extension
+pub fn is_gltf(path: &Path) -> bool {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("gltf") | Some("glb") => true,
+        _                          => false,
+    }
+}
+
+/// Load the default scene of a glTF file as a node whose children are its root nodes
+pub fn load(path: &Path) -> io::Result<Node> {
+    let mut bytes = Vec::new();
+    File::open(path)?.read_to_end(&mut bytes)?;
+    let base = path.parent().unwrap_or(Path::new(""));
+    parse(&bytes, base).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), err)))
+}
+
+/// Parse a glTF document or GLB container, resolving external files against `base`
+pub fn parse(bytes: &[u8], base: &Path) -> Result<Node, String> {
+    let (text, binary) = if bytes.starts_with(b"glTF") {
+        split_glb(bytes)?
+    } else {
+        (str::from_utf8(bytes).map_err(|_| String::from("neither GLB nor UTF-8 JSON"))?, None)
+    };
+    let doc = json::parse(text).map_err(|err| format!("invalid JSON at {}", err))?;
+    let version = doc.get("asset").and_then(|asset| asset.get("version")).and_then(|v| v.as_str())
+        .ok_or_else(|| String::from("asset.version: missing"))?;
+    if !version.starts_with("2.") {
+        return Err(format!("asset.version: unsupported glTF version {}", version));
+    }
+    if let Some(required) = doc.get("extensionsRequired").and_then(|list| list.as_array()) {
+        if !required.is_empty() {
+            let names: Vec<&str> = required.iter().filter_map(|name| name.as_str()).collect();
+            return Err(format!("extensionsRequired: unsupported extensions {}", names.join(", ")));
+        }
+    }
+    let mut buffers = Vec::new();
+    for (i, buffer) in list(&doc, "buffers").iter().enumerate() {
+        let path = format!("buffers[{}]", i);
+        let data = match string(buffer, "uri", &path)? {
+            Some(uri) => read_uri(uri, base).map_err(|err| format!("{}.uri: {}", path, err))?,
+            None if i == 0 => binary.map(|b| b.to_vec())
+                .ok_or_else(|| format!("{}: no uri and no binary chunk", path))?,
+            None => return Err(format!("{}.uri: missing", path)),
+        };
+        if data.len() < required(buffer, "byteLength", &path)? {
+            return Err(format!("{}: shorter than its byteLength", path));
+        }
+        buffers.push(data);
+    }
+    let mut gltf = Gltf{doc, base: base.to_path_buf(), buffers, images: Vec::new()};
+    for i in 0..list(&gltf.doc, "images").len() {
+        let image = gltf.image(i)?;
+        gltf.images.push(image);
+    }
+    let doc = &gltf.doc;
+    let scene = match index(doc, "scene", "")? {
+        Some(i) => Some(entry(doc, "scenes", i)?),
+        None    => list(doc, "scenes").first(),
+    };
+    let roots = match scene {
+        Some(scene) => {
+            let mut roots = Vec::new();
+            for (k, node) in list(scene, "nodes").iter().enumerate() {
+                roots.push(as_index(node).ok_or_else(|| format!("scene.nodes[{}]: expected an index", k))?);
+            }
+            roots
+        },
+        // without scenes, show every node that is not the child of another
+        None => {
+            let mut children = Vec::new();
+            for node in list(doc, "nodes") {
+                children.extend(list(node, "children").iter().filter_map(as_index));
+            }
+            (0..list(doc, "nodes").len()).filter(|i| !children.contains(i)).collect()
+        },
+    };
+    let name = scene.and_then(|scene| scene.get("name")).and_then(|name| name.as_str()).unwrap_or("");
+    let mut meshes = vec![None; list(doc, "meshes").len()];
+    let mut root = Node::new(name);
+    for i in roots {
+        root = root.with_child(gltf.node(i, 0, &mut meshes)?);
+    }
+    Ok(root)
+}
+
+struct Gltf {
+    doc: Value,
+    /// Directory external files are found in
+    base: PathBuf,
+    buffers: Vec<Vec<u8>>,
+    images: Vec<image::DynamicImage>,
+}
+
+/// Primitives of each mesh once built, shared by all nodes that use the mesh
+type Meshes = Vec<Option<Vec<Rc<Obj>>>>;
+
+impl Gltf {
+
+    fn node(&self, i: usize, depth: usize, meshes: &mut Meshes) -> Result<Node, String> {
+        let path = format!("nodes[{}]", i);
+        if depth > list(&self.doc, "nodes").len() {
+            return Err(format!("{}: node hierarchy contains a cycle", path));
+        }
+        let value = entry(&self.doc, "nodes", i)?;
+        let name = string(value, "name", &path)?.unwrap_or("");
+        let matrix = match numbers(value, "matrix", 16, &path)? {
+            Some(m) => {
+                // column-major
+                let mut matrix = geo::Mat4::identity();
+                for (k, x) in m.iter().enumerate() {
+                    matrix.m[k % 4][k/4] = *x;
+                }
+                matrix
+            },
+            None => {
+                let t = numbers(value, "translation", 3, &path)?.unwrap_or(vec![0., 0., 0.]);
+                let q = numbers(value, "rotation", 4, &path)?.unwrap_or(vec![0., 0., 0., 1.]);
+                let s = numbers(value, "scale", 3, &path)?.unwrap_or(vec![1., 1., 1.]);
+                let translation = geo::Mat4::translation(&geo::Vec3f::new(t[0], t[1], t[2]));
+                let scale = geo::Mat4::scale(&geo::Vec3f::new(s[0], s[1], s[2]));
+                &(&translation*&rotation(&q))*&scale
+            },
+        };
+        let mut node = Node::new(name).with_transform(Transform::from_matrix(&matrix));
+        if let Some(mesh) = index(value, "mesh", &path)? {
+            for primitive in self.mesh(mesh, meshes)? {
+                node = node.with_mesh(primitive);
+            }
+        }
+        for (k, child) in list(value, "children").iter().enumerate() {
+            let child = as_index(child).ok_or_else(|| format!("{}.children[{}]: expected an index", path, k))?;
+            node = node.with_child(self.node(child, depth + 1, meshes)?);
+        }
+        Ok(node)
+    }
+
+    fn mesh(&self, i: usize, meshes: &mut Meshes) -> Result<Vec<Rc<Obj>>, String> {
+        if let Some(Some(ref primitives)) = meshes.get(i) {
+            return Ok(primitives.clone());
+        }
+        let value = entry(&self.doc, "meshes", i)?;
+        let mut primitives = Vec::new();
+        for (k, primitive) in list(value, "primitives").iter().enumerate() {
+            let path = format!("meshes[{}].primitives[{}]", i, k);
+            if let Some(obj) = self.primitive(primitive, &path)? {
+                primitives.push(Rc::new(obj));
+            }
+        }
+        meshes[i] = Some(primitives.clone());
+        Ok(primitives)
+    }
+
+    /// Triangles of a primitive with its material, None for points and lines
+    fn primitive(&self, value: &Value, path: &str) -> Result<Option<Obj>, String> {
+        let mode = index(value, "mode", path)?.unwrap_or(4);
+        if mode < 4 {
+            return Ok(None);
+        }
+        let attributes = value.get("attributes").ok_or_else(|| format!("{}.attributes: missing", path))?;
+        let attribute = |name: &str, sizes: &[usize]| -> Result<Option<(Vec<f64>, usize)>, String> {
+            let key = format!("{}.attributes", path);
+            match index(attributes, name, &key)? {
+                Some(i) => {
+                    let (values, size) = self.accessor(i)?;
+                    if !sizes.contains(&size) {
+                        return Err(format!("{}.{}: accessor with {} components", key, name, size));
+                    }
+                    Ok(Some((values, size)))
+                },
+                None => Ok(None),
+            }
+        };
+        let (positions, _) = attribute("POSITION", &[3])?
+            .ok_or_else(|| format!("{}.attributes.POSITION: missing", path))?;
+        let count = positions.len()/3;
+        let vertices: Vec<geo::Vec3f> = positions.chunks(3).map(|p| geo::Vec3f::new(p[0], p[1], p[2])).collect();
+        let indices: Vec<usize> = match index(value, "indices", path)? {
+            Some(i) => {
+                let (values, _) = self.accessor(i)?;
+                values.into_iter().map(|v| v as usize).collect()
+            },
+            None => (0..count).collect(),
+        };
+        if indices.iter().any(|&v| v >= count) {
+            return Err(format!("{}.indices: index out of range", path));
+        }
+        let triangles: Vec<[usize; 3]> = match mode {
+            4 => indices.chunks(3).filter(|t| t.len() == 3).map(|t| [t[0], t[1], t[2]]).collect(),
+            // strips alternate their winding, fans share the first vertex
+            5 => (2..indices.len()).map(|k| if k % 2 == 0 { [indices[k - 2], indices[k - 1], indices[k]] }
+                                               else { [indices[k - 1], indices[k - 2], indices[k]] }).collect(),
+            6 => (2..indices.len()).map(|k| [indices[0], indices[k - 1], indices[k]]).collect(),
+            _ => return Err(format!("{}.mode: unknown primitive mode {}", path, mode)),
+        };
+        let faces: Vec<geo::Vec3i> = triangles.iter().map(|t| geo::Vec3i::new(t[0] as i32, t[1] as i32, t[2] as i32))
+            .collect();
+        let mut obj = Obj::new(vertices, faces.clone());
+        let per_vertex = |name: &str, values: &Vec<f64>, size: usize| {
+            if values.len() != count*size {
+                Err(format!("{}.attributes.{}: {} values, expected {}", path, name, values.len()/size, count))
+            } else {
+                Ok(())
+            }
+        };
+        if let Some((normals, size)) = attribute("NORMAL", &[3])? {
+            per_vertex("NORMAL", &normals, size)?;
+            obj.normals = normals.chunks(3).map(|n| geo::Vec3f::new(n[0], n[1], n[2])).collect();
+            obj.face_normals = faces.clone();
+        } else {
+            obj.compute_normals(obj::NormalWeighting::Angle, obj::DEFAULT_CREASE_ANGLE);
+        }
+        if let Some((uvs, size)) = attribute("TEXCOORD_0", &[2])? {
+            per_vertex("TEXCOORD_0", &uvs, size)?;
+            // glTF puts v = 0 at the top of the image
+            obj.textures = uvs.chunks(2).map(|t| geo::Vec3f::new(t[0], 1. - t[1], 0.)).collect();
+            obj.face_textures = faces.clone();
+        }
+        if let Some((colors, size)) = attribute("COLOR_0", &[3, 4])? {
+            per_vertex("COLOR_0", &colors, size)?;
+            obj.colors = colors.chunks(size).map(|c| geo::Vec3f::new(c[0], c[1], c[2])).collect();
+        }
+        match index(value, "material", path)? {
+            Some(i) => self.material(obj, i).map(Some),
+            None    => Ok(Some(obj)),
+        }
+    }
+
+    fn material(&self, mut obj: Obj, i: usize) -> Result<Obj, String> {
+        let path = format!("materials[{}]", i);
+        let value = entry(&self.doc, "materials", i)?;
+        let mut factor = vec![1., 1., 1., 1.];
+        if let Some(pbr) = value.get("pbrMetallicRoughness") {
+            let path = format!("{}.pbrMetallicRoughness", path);
+            if let Some(f) = numbers(pbr, "baseColorFactor", 4, &path)? {
+                factor = f;
+            }
+            if let Some(info) = pbr.get("baseColorTexture") {
+                let texture = required(info, "index", &format!("{}.baseColorTexture", path))?;
+                obj = obj.with_texture(self.texture(texture)?);
+            }
+        }
+        let channel = |c: f64| (c.max(0.).min(1.)*255.).round() as u8;
+        obj = obj.with_color([channel(factor[0]), channel(factor[1]), channel(factor[2])]);
+        Ok(match string(value, "alphaMode", &path)?.unwrap_or("OPAQUE") {
+            // a zero cutoff discards no texel but draws them all opaque, whatever their alpha
+            "OPAQUE" => obj.with_alpha_cutoff(0.),
+            "MASK"   => {
+                let cutoff = value.get("alphaCutoff").and_then(|c| c.as_f64()).unwrap_or(0.5);
+                obj.with_alpha_cutoff(cutoff)
+            },
+            "BLEND"  => obj.with_opacity(factor[3]),
+            mode     => return Err(format!("{}.alphaMode: unknown mode '{}'", path, mode)),
+        })
+    }
+
+    fn texture(&self, i: usize) -> Result<texture::Texture, String> {
+        let path = format!("textures[{}]", i);
+        let value = entry(&self.doc, "textures", i)?;
+        let source = required(value, "source", &path)?;
+        let image = self.images.get(source).ok_or_else(|| format!("images[{}]: missing", source))?;
+        if image.width() == 0 || image.height() == 0 {
+            return Err(format!("images[{}]: empty image", source));
+        }
+        let mut texture = texture::Texture::new(image);
+        if let Some(sampler) = index(value, "sampler", &path)? {
+            let path = format!("samplers[{}]", sampler);
+            let sampler = entry(&self.doc, "samplers", sampler)?;
+            texture = texture.with_wrap(match index(sampler, "wrapS", &path)?.unwrap_or(10497) {
+                33071 => Wrap::Clamp,
+                33648 => Wrap::Mirror,
+                _     => Wrap::Repeat,
+            });
+            if index(sampler, "magFilter", &path)? == Some(9728) {
+                texture = texture.with_filter(Filter::Nearest);
+            }
+        }
+        Ok(texture)
+    }
+
+    fn image(&self, i: usize) -> Result<image::DynamicImage, String> {
+        let path = format!("images[{}]", i);
+        let value = entry(&self.doc, "images", i)?;
+        let bytes = match (string(value, "uri", &path)?, index(value, "bufferView", &path)?) {
+            (Some(uri), _)     => read_uri(uri, &self.base).map_err(|err| format!("{}.uri: {}", path, err))?,
+            (None, Some(view)) => self.view(view)?.to_vec(),
+            (None, None)       => return Err(format!("{}: neither uri nor bufferView", path)),
+        };
+        image::load_from_memory(&bytes).map_err(|err| format!("{}: {}", path, err))
+    }
+
+    fn view(&self, i: usize) -> Result<&[u8], String> {
+        let path = format!("bufferViews[{}]", i);
+        let value = entry(&self.doc, "bufferViews", i)?;
+        let buffer = required(value, "buffer", &path)?;
+        let buffer = self.buffers.get(buffer).ok_or_else(|| format!("buffers[{}]: missing", buffer))?;
+        let offset = index(value, "byteOffset", &path)?.unwrap_or(0);
+        let length = required(value, "byteLength", &path)?;
+        offset.checked_add(length).and_then(|end| buffer.get(offset..end))
+            .ok_or_else(|| format!("{}: exceeds its buffer", path))
+    }
+
+    /// Elements of an accessor flattened into one list, with the number of components per element.
+    /// Normalized integers are mapped to [0, 1] or [-1, 1].
+    fn accessor(&self, i: usize) -> Result<(Vec<f64>, usize), String> {
+        let path = format!("accessors[{}]", i);
+        let value = entry(&self.doc, "accessors", i)?;
+        if value.get("sparse").is_some() {
+            return Err(format!("{}.sparse: sparse accessors are not supported", path));
+        }
+        let count = required(value, "count", &path)?;
+        let components = match string(value, "type", &path)? {
+            Some("SCALAR") => 1,
+            Some("VEC2")   => 2,
+            Some("VEC3")   => 3,
+            Some("VEC4")   => 4,
+            Some(other)    => return Err(format!("{}.type: unsupported type '{}'", path, other)),
+            None           => return Err(format!("{}.type: missing", path)),
+        };
+        let component_type = required(value, "componentType", &path)?;
+        let size = match component_type {
+            5120 | 5121 => 1,
+            5122 | 5123 => 2,
+            5125 | 5126 => 4,
+            other       => return Err(format!("{}.componentType: unknown type {}", path, other)),
+        };
+        let normalized = value.get("normalized") == Some(&Value::Bool(true));
+        let view = match index(value, "bufferView", &path)? {
+            Some(view) => {
+                let stride = index(entry(&self.doc, "bufferViews", view)?, "byteStride", &path)?
+                    .unwrap_or(size*components);
+                let data = self.view(view)?;
+                let offset = index(value, "byteOffset", &path)?.unwrap_or(0);
+                // the last element must end inside the view, checked before anything is allocated
+                let end = match count.checked_sub(1) {
+                    Some(last) => last.checked_mul(stride)
+                        .and_then(|at| at.checked_add(offset))
+                        .and_then(|at| at.checked_add(size*components)),
+                    None       => Some(0),
+                };
+                if end.map_or(true, |end| end > data.len()) {
+                    return Err(format!("{}: exceeds its buffer view", path));
+                }
+                Some((data, offset, stride))
+            },
+            None if count > MAX_ZERO_ELEMENTS => {
+                return Err(format!("{}: {} elements without a buffer view", path, count));
+            },
+            None => None,
+        };
+        let mut values = vec![0.; count*components];
+        // without a buffer view all values are zero
+        if let Some((data, offset, stride)) = view {
+            for e in 0..count {
+                for c in 0..components {
+                    let at = offset + e*stride + c*size;
+                    values[e*components + c] = decode(component_type, &data[at..at + size], normalized);
+                }
+            }
+        }
+        Ok((values, components))
+    }
+
+}
+
+/// Most elements an accessor without a buffer view may have; its zeros take no space in the file
+const MAX_ZERO_ELEMENTS: usize = 1 << 24;
+
+/// The JSON and binary chunks of a GLB container
+fn split_glb(bytes: &[u8]) -> Result<(&str, Option<&[u8]>), String> {
+    let u32_at = |at: usize| -> Option<usize> {
+        let b = bytes.get(at..at + 4)?;
+        Some((b[0] as u32 | (b[1] as u32) << 8 | (b[2] as u32) << 16 | (b[3] as u32) << 24) as usize)
+    };
+    let truncated = || String::from("truncated GLB file");
+    if u32_at(4).ok_or_else(truncated)? != 2 {
+        return Err(String::from("unsupported GLB version"));
+    }
+    let length = u32_at(8).ok_or_else(truncated)?.min(bytes.len());
+    let (mut text, mut binary) = (None, None);
+    let mut at = 12;
+    while at + 8 <= length {
+        let chunk_length = u32_at(at).ok_or_else(truncated)?;
+        let chunk = bytes.get(at + 8..at + 8 + chunk_length).ok_or_else(truncated)?;
+        match &bytes[at + 4..at + 8] {
+            b"JSON" if text.is_none()   => text = Some(str::from_utf8(chunk).map_err(|_| String::from("JSON chunk is not UTF-8"))?),
+            b"BIN\0" if binary.is_none() => binary = Some(chunk),
+            // unknown chunks must be ignored
+            _ => (),
+        }
+        at += 8 + chunk_length;
+    }
+    Ok((text.ok_or_else(|| String::from("GLB file without a JSON chunk"))?, binary))
+}
+
+/// Contents of a data URI or of a file relative to `base`
+fn read_uri(uri: &str, base: &Path) -> Result<Vec<u8>, String> {
+    if uri.starts_with("data:") {
+        let comma = uri.find(',').ok_or_else(|| String::from("data URI without data"))?;
+        if !uri[..comma].ends_with(";base64") {
+            return Err(String::from("data URI is not base64 encoded"));
+        }
+        return base64(&uri[comma + 1..]);
+    }
+    let mut bytes = Vec::new();
+    File::open(base.join(uri)).and_then(|mut f| f.read_to_end(&mut bytes))
+        .map_err(|err| format!("'{}': {}", uri, err))?;
+    Ok(bytes)
+}
+
+fn base64(text: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::with_capacity(text.len()*3/4);
+    let (mut bits, mut count) = (0u32, 0);
+    for c in text.bytes().take_while(|&c| c != b'=') {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            _ => return Err(format!("invalid base64 character '{}'", c as char)),
+        };
+        bits = bits << 6 | value as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            bytes.push((bits >> count) as u8);
+        }
+    }
+    Ok(bytes)
+}
+
+fn decode(component_type: usize, bytes: &[u8], normalized: bool) -> f64 {
+    let bits = bytes.iter().rev().fold(0u32, |bits, &b| bits << 8 | b as u32);
+    match (component_type, normalized) {
+        (5120, false) => bits as u8 as i8 as f64,
+        (5120, true)  => (bits as u8 as i8 as f64/127.).max(-1.),
+        (5121, false) => bits as f64,
+        (5121, true)  => bits as f64/255.,
+        (5122, false) => bits as u16 as i16 as f64,
+        (5122, true)  => (bits as u16 as i16 as f64/32767.).max(-1.),
+        (5123, false) => bits as f64,
+        (5123, true)  => bits as f64/65535.,
+        (5125, _)     => bits as f64,
+        _             => f32::from_bits(bits) as f64,
+    }
+}
+
+/// Rotation matrix of a unit quaternion given as x, y, z, w
+fn rotation(q: &[f64]) -> geo::Mat4 {
+    let (x, y, z, w) = (q[0], q[1], q[2], q[3]);
+    let mut m = geo::Mat4::identity();
+    m.m[0][0] = 1. - 2.*(y*y + z*z); m.m[0][1] = 2.*(x*y - z*w);      m.m[0][2] = 2.*(x*z + y*w);
+    m.m[1][0] = 2.*(x*y + z*w);      m.m[1][1] = 1. - 2.*(x*x + z*z); m.m[1][2] = 2.*(y*z - x*w);
+    m.m[2][0] = 2.*(x*z - y*w);      m.m[2][1] = 2.*(y*z + x*w);      m.m[2][2] = 1. - 2.*(x*x + y*y);
+    m
+}
+
+fn list<'v>(value: &'v Value, key: &str) -> &'v [Value] {
+    value.get(key).and_then(|list| list.as_array()).unwrap_or(&[])
+}
+
+fn entry<'v>(doc: &'v Value, key: &str, i: usize) -> Result<&'v Value, String> {
+    list(doc, key).get(i).ok_or_else(|| format!("{}[{}]: missing", key, i))
+}
+
+fn as_index(value: &Value) -> Option<usize> {
+    value.as_f64().and_then(|n| if n >= 0. && n.fract() == 0. { Some(n as usize) } else { None })
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() { String::from(key) } else { format!("{}.{}", path, key) }
+}
+
+/// Optional non-negative integer member `key` of `value`
+fn index(value: &Value, key: &str, path: &str) -> Result<Option<usize>, String> {
+    match value.get(key) {
+        Some(v) => as_index(v).map(Some).ok_or_else(|| format!("{}: expected a non-negative integer", join(path, key))),
+        None    => Ok(None),
+    }
+}
+
+fn required(value: &Value, key: &str, path: &str) -> Result<usize, String> {
+    index(value, key, path)?.ok_or_else(|| format!("{}: missing", join(path, key)))
+}
+
+fn string<'v>(value: &'v Value, key: &str, path: &str) -> Result<Option<&'v str>, String> {
+    match value.get(key) {
+        Some(v) => v.as_str().map(Some).ok_or_else(|| format!("{}: expected a string", join(path, key))),
+        None    => Ok(None),
+    }
+}
+
+fn numbers(value: &Value, key: &str, len: usize, path: &str) -> Result<Option<Vec<f64>>, String> {
+    let values = match value.get(key) {
+        Some(v) => v.as_array().ok_or_else(|| format!("{}: expected an array", join(path, key)))?,
+        None    => return Ok(None),
+    };
+    let numbers: Vec<f64> = values.iter().filter_map(|v| v.as_f64()).collect();
+    if numbers.len() != len || values.len() != len {
+        return Err(format!("{}: expected {} numbers", join(path, key), len));
+    }
+    Ok(Some(numbers))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo::Vector;
+
+    /// A unit quad in the xy-plane: four float positions, then six unsigned short indices
+    const QUAD: &str = "AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAACAPwAAgD8AAAAAAAAAAAAAgD8AAAAAAAABAAIAAAACAAMA";
+
+    /// A document placing the quad twice, the second time under a turned child; `{}` is the buffer
+    const DOCUMENT: &str = r#"{
+        "asset": {"version": "2.0"},
+        "scene": 0,
+        "scenes": [{"name": "quads", "nodes": [0]}],
+        "nodes": [{"name": "parent", "translation": [1, 0, 0], "mesh": 0, "children": [1]},
+                  {"name": "child", "rotation": [0, 0, 0.7071067811865476, 0.7071067811865476], "mesh": 0}],
+        "meshes": [{"primitives": [{"attributes": {"POSITION": 0}, "indices": 1, "material": 0},
+                                   {"attributes": {"POSITION": 0}, "mode": 1}]}],
+        "materials": [{"pbrMetallicRoughness": {"baseColorFactor": [1, 0.5, 0, 0.25], "metallicFactor": 0},
+                       "alphaMode": "BLEND"}],
+        "buffers": [{}],
+        "bufferViews": [{"buffer": 0, "byteLength": 48}, {"buffer": 0, "byteOffset": 48, "byteLength": 12}],
+        "accessors": [{"bufferView": 0, "componentType": 5126, "count": 4, "type": "VEC3"},
+                      {"bufferView": 1, "componentType": 5123, "count": 6, "type": "SCALAR"}]
+    }"#;
+
+    fn assert_quads(root: &Node) {
+        assert_eq!(root.name, "quads");
+        let meshes = root.meshes();
+        // the line primitive is skipped and both nodes share the triangles
+        assert_eq!(meshes.len(), 2);
+        assert!(Rc::ptr_eq(&meshes[0].0, &meshes[1].0));
+        let quad = &meshes[0].0;
+        assert_eq!((quad.vertices.len(), quad.faces.len()), (4, 2));
+        assert_eq!(quad.faces[1], geo::Vec3i::new(0, 2, 3));
+        assert_eq!(quad.color(), [255, 128, 0]);
+        assert_eq!(quad.opacity(), 0.25);
+        // computed normals face the front of the counter-clockwise quad
+        assert_eq!(quad.normals[quad.face_normals[0].x as usize], geo::Vec3f::new(0., 0., 1.));
+        let corner = geo::Vec3f::new(1., 0., 0.);
+        let world = root.world_transform("child").unwrap().transform_point(&corner);
+        assert!((&world - &geo::Vec3f::new(1., 1., 0.)).norm() < 1e-12);
+    }
+
+    #[test]
+    fn embedded_buffers() {
+        let uri = format!(r#""uri": "data:application/octet-stream;base64,{}", "byteLength": 60"#, QUAD);
+        let text = DOCUMENT.replace("{}", &format!("{{{}}}", uri));
+        assert_quads(&parse(text.as_bytes(), Path::new("")).unwrap());
+
+        let broken = text.replace(r#""count": 6"#, r#""count": 7"#);
+        assert_eq!(parse(broken.as_bytes(), Path::new("")).err(),
+                   Some(String::from("accessors[1]: exceeds its buffer view")));
+        // sizes that overflow are rejected before anything is allocated
+        let broken = text.replace(r#""count": 4"#, r#""count": 4611686018427387904"#);
+        assert_eq!(parse(broken.as_bytes(), Path::new("")).err(),
+                   Some(String::from("accessors[0]: exceeds its buffer view")));
+        let broken = text.replace(r#""byteOffset": 48, "byteLength": 12"#,
+                                  r#""byteOffset": 48, "byteLength": 18446744073709551615"#);
+        assert_eq!(parse(broken.as_bytes(), Path::new("")).err(),
+                   Some(String::from("bufferViews[1]: exceeds its buffer")));
+        let broken = text.replace(r#""bufferView": 0, "componentType": 5126, "count": 4"#,
+                                  r#""componentType": 5126, "count": 1000000000000"#);
+        assert_eq!(parse(broken.as_bytes(), Path::new("")).err(),
+                   Some(String::from("accessors[0]: 1000000000000 elements without a buffer view")));
+        let broken = text.replace(r#""mesh": 0}"#, r#""mesh": 1}"#);
+        assert_eq!(parse(broken.as_bytes(), Path::new("")).err(), Some(String::from("meshes[1]: missing")));
+        assert!(parse(text.replace("2.0", "1.0").as_bytes(), Path::new("")).is_err());
+    }
+
+    #[test]
+    fn binary_container() {
+        let pad = |mut chunk: Vec<u8>, fill: u8| {
+            while chunk.len() % 4 != 0 {
+                chunk.push(fill);
+            }
+            chunk
+        };
+        let text = pad(DOCUMENT.replace("{}", r#"{"byteLength": 60}"#).into_bytes(), b' ');
+        let data = pad(base64(QUAD).unwrap(), 0);
+        let mut glb = b"glTF".to_vec();
+        let push = |glb: &mut Vec<u8>, n: usize| glb.extend(&[n as u8, (n >> 8) as u8, (n >> 16) as u8, 0]);
+        push(&mut glb, 2);
+        push(&mut glb, 12 + 8 + text.len() + 8 + data.len());
+        push(&mut glb, text.len());
+        glb.extend(b"JSON");
+        glb.extend(&text);
+        push(&mut glb, data.len());
+        glb.extend(b"BIN\0");
+        glb.extend(&data);
+        assert_quads(&parse(&glb, Path::new("")).unwrap());
+        assert!(parse(&glb[..30], Path::new("")).is_err());
+    }
+
+    #[test]
+    fn decode_base64() {
+        assert_eq!(base64("TWFu").unwrap(), b"Man");
+        assert_eq!(base64("TWE=").unwrap(), b"Ma");
+        assert_eq!(base64("TQ==").unwrap(), b"M");
+        assert!(base64("T*==").is_err());
+    }
+
+}
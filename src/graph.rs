@@ -0,0 +1,193 @@
+use std::rc::Rc;
+use geo;
+use obj;
+use camera::Camera;
+use transform::Transform;
+
+/// Something attached to a node, placed by the node's world transform
+#[derive(Clone)]
+pub enum Content {
+    Mesh(Rc<obj::Obj>),
+    /// Directional light shining along the given direction in node space
+    Light(geo::Vec3f),
+    Camera(Camera),
+}
+
+/// A node in the scene graph. Its transform is relative to its parent.
+#[derive(Clone)]
+pub struct Node {
+    pub name: String,
+    pub transform: Transform,
+    pub contents: Vec<Content>,
+    pub children: Vec<Node>,
+}
+
+impl Node {
+
+    pub fn new(name: &str) -> Node {
+        Node{name: String::from(name), transform: Transform::identity(), contents: Vec::new(), children: Vec::new()}
+    }
+
+    pub fn with_transform(mut self, transform: Transform) -> Self {
+        self.transform = transform;
+        self
+    }
+
+    pub fn with_mesh(mut self, mesh: Rc<obj::Obj>) -> Self {
+        self.contents.push(Content::Mesh(mesh));
+        self
+    }
+
+    pub fn with_light(mut self, direction: geo::Vec3f) -> Self {
+        self.contents.push(Content::Light(direction));
+        self
+    }
+
+    pub fn with_camera(mut self, camera: Camera) -> Self {
+        self.contents.push(Content::Camera(camera));
+        self
+    }
+
+    pub fn with_child(mut self, child: Node) -> Self {
+        self.children.push(child);
+        self
+    }
+
+    /// Append a child and return a reference to it
+    pub fn add_child(&mut self, child: Node) -> &mut Node {
+        self.children.push(child);
+        self.children.last_mut().unwrap()
+    }
+
+    /// First node named `name` in depth-first order, including this node
+    pub fn find(&self, name: &str) -> Option<&Node> {
+        if self.name == name {
+            return Some(self);
+        }
+        self.children.iter().filter_map(|child| child.find(name)).next()
+    }
+
+    pub fn find_mut(&mut self, name: &str) -> Option<&mut Node> {
+        if self.name == name {
+            return Some(self);
+        }
+        self.children.iter_mut().filter_map(|child| child.find_mut(name)).next()
+    }
+
+    /// World matrix of the first node named `name`, with this node as the root
+    pub fn world_transform(&self, name: &str) -> Option<geo::Mat4> {
+        self.iter().find(|(node, _)| node.name == name).map(|(_, world)| world)
+    }
+
+    /// Depth-first, pre-order iterator over all nodes with their world matrices
+    pub fn iter(&self) -> NodeIter {
+        NodeIter{stack: vec![(self, self.transform.matrix())]}
+    }
+
+    /// All meshes in the tree with their world matrices, in depth-first order
+    pub fn meshes(&self) -> Vec<(Rc<obj::Obj>, geo::Mat4)> {
+        let mut meshes = Vec::new();
+        for (node, world) in self.iter() {
+            for content in node.contents.iter() {
+                if let Content::Mesh(ref mesh) = content {
+                    meshes.push((Rc::clone(mesh), world));
+                }
+            }
+        }
+        meshes
+    }
+
+    /// All light directions in world space
+    pub fn lights(&self) -> Vec<geo::Vec3f> {
+        let mut lights = Vec::new();
+        for (node, world) in self.iter() {
+            for content in node.contents.iter() {
+                if let Content::Light(ref direction) = content {
+                    lights.push(world.transform_vector(direction));
+                }
+            }
+        }
+        lights
+    }
+
+    /// The first camera attached to the node named `name`, moved into world space
+    pub fn camera(&self, name: &str) -> Option<Camera> {
+        let (node, world) = self.iter().find(|(node, _)| node.name == name)?;
+        node.contents.iter().filter_map(|content| match content {
+            Content::Camera(camera) => Some(Camera::new(world.transform_point(&camera.eye),
+                                                        world.transform_point(&camera.center),
+                                                        world.transform_vector(&camera.up))
+                                        .with_extent(camera.extent)),
+            _ => None,
+        }).next()
+    }
+
+}
+
+pub struct NodeIter<'a> {
+    stack: Vec<(&'a Node, geo::Mat4)>,
+}
+
+impl<'a> Iterator for NodeIter<'a> {
+    type Item = (&'a Node, geo::Mat4);
+
+    fn next(&mut self) -> Option<(&'a Node, geo::Mat4)> {
+        let (node, world) = self.stack.pop()?;
+        for child in node.children.iter().rev() {
+            self.stack.push((child, &world*&child.transform.matrix()));
+        }
+        Some((node, world))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+    use geo::Vector;
+
+    fn tank() -> Node {
+        let turret = Node::new("turret")
+            .with_transform(Transform::new(geo::Vec3f::new(0., 1., 0.), geo::Vec3f::new(0., PI/2., 0.),
+                                           geo::Vec3f::new(1., 1., 1.)))
+            .with_child(Node::new("barrel")
+                .with_transform(Transform::new(geo::Vec3f::new(0., 0., 2.), geo::Vec3f::new(0., 0., 0.),
+                                               geo::Vec3f::new(1., 1., 1.))));
+        Node::new("tank")
+            .with_transform(Transform::new(geo::Vec3f::new(5., 0., 0.), geo::Vec3f::new(0., 0., 0.),
+                                           geo::Vec3f::new(1., 1., 1.)))
+            .with_child(Node::new("hull"))
+            .with_child(turret)
+    }
+
+    #[test]
+    fn traversal_order() {
+        let names: Vec<String> = tank().iter().map(|(node, _)| node.name.clone()).collect();
+        assert_eq!(names, vec!["tank", "hull", "turret", "barrel"]);
+    }
+
+    #[test]
+    fn world_transform_composes() {
+        let mut tank = tank();
+        let barrel = tank.world_transform("barrel").unwrap().transform_point(&geo::Vec3f::new(0., 0., 0.));
+        assert!((&barrel - &geo::Vec3f::new(7., 1., 0.)).norm() < 1e-12);
+
+        tank.find_mut("turret").unwrap().transform.rotation.y = 0.;
+        let barrel = tank.world_transform("barrel").unwrap().transform_point(&geo::Vec3f::new(0., 0., 0.));
+        assert!((&barrel - &geo::Vec3f::new(5., 1., 2.)).norm() < 1e-12);
+        assert!(tank.world_transform("wheel").is_none());
+    }
+
+    #[test]
+    fn lights_and_cameras() {
+        let mut tank = tank();
+        tank.find_mut("turret").unwrap().contents.push(Content::Light(geo::Vec3f::new(0., 0., 1.)));
+        tank.find_mut("barrel").unwrap().contents.push(Content::Camera(Camera::default()));
+        let lights = tank.lights();
+        assert!((&lights[0] - &geo::Vec3f::new(1., 0., 0.)).norm() < 1e-12);
+        let camera = tank.camera("barrel").unwrap();
+        assert!((&camera.center - &geo::Vec3f::new(7., 1., 0.)).norm() < 1e-12);
+        assert!(tank.camera("hull").is_none());
+    }
+
+}
@@ -1,21 +1,214 @@
+mod aov;
+mod bounds;
+mod camera;
+mod cli;
+mod framebuffer;
 mod geo;
+mod gltf;
+mod graph;
+mod json;
+mod mesh;
 mod model;
+mod mtl;
 mod obj;
+mod objparse;
+mod output;
+mod ply;
 mod render;
+mod scenefile;
+mod stl;
+mod texture;
+mod tga;
+mod transform;
+mod turntable;
+use std::env;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::process;
+use std::rc::Rc;
 use std::vec::{Vec};
+extern crate color_quant;
+extern crate gif;
 extern crate image;
 
 fn main() {
-    let imgx = 800;
-    let imgy = 800;
+    let options = match cli::parse(env::args().skip(1)) {
+        Ok(cli::Command::Render(options)) => options,
+        Ok(cli::Command::Help) => {
+            print!("{}", cli::USAGE);
+            return;
+        },
+        Err(err) => {
+            eprintln!("error: {}\n\n{}", err, cli::USAGE);
+            process::exit(2);
+        },
+    };
+    if let Err(err) = run(&options) {
+        eprintln!("error: {}", err);
+        process::exit(1);
+    }
+}
+
+fn run(options: &cli::Options) -> Result<(), String> {
+    if let Some(ref path) = options.scene {
+        return run_scene(path, options);
+    }
+    let framebuffer = framebuffer::Framebuffer::new(options.width, options.height, framebuffer::Format::Rgb8);
+    let mut scene = render::Scene::new(Vec::<obj::Obj>::new(), framebuffer);
+    for (i, path) in options.meshes.iter().enumerate() {
+        if gltf::is_gltf(Path::new(path)) {
+            if options.export.is_some() {
+                return Err(format!("cannot export the glTF scene '{}'", path));
+            }
+            // glTF files bring their own hierarchy and materials
+            let node = gltf::load(Path::new(path))
+                .map_err(|err| format!("failed to load scene '{}': {}", path, err))?;
+            scene.add_node(node);
+            continue;
+        }
+        let mut obj = mesh::load(Path::new(path))
+            .map_err(|err| format!("failed to load mesh '{}': {}", path, err))?;
+        for warning in obj.warnings.iter() {
+            eprintln!("warning: {}: {}", path, warning);
+        }
+        if let Some(texture) = options.textures.get(i) {
+            let texture = texture::Texture::load(Path::new(texture))
+                .map_err(|err| format!("failed to load texture '{}': {}", texture, err))?;
+            obj = obj.with_texture(texture.with_filter(options.filter).with_wrap(options.wrap));
+        }
+        if let Some(ref export) = options.export {
+            let path = Path::new(export);
+            match path.extension().and_then(|ext| ext.to_str()) {
+                Some("stl") => stl::save(&obj, path, options.stl_format),
+                _           => obj.save(path),
+            }.map_err(|err| format!("failed to export '{}': {}", export, err))?;
+        }
+        if let Some(threshold) = options.alpha_cutoff {
+            obj = obj.with_alpha_cutoff(threshold);
+        }
+        scene.add_object(obj);
+    }
+    scene.clear_lights();
+    for light in options.lights.iter() {
+        scene.add_light(light.x, light.y, light.z);
+    }
+    scene.set_camera(camera::Camera::new(options.camera, options.look_at, geo::Vec3f::new(0., 1., 0.)));
+    if let Some(margin) = options.frame {
+        scene.frame_all(margin);
+    }
+    scene.set_shading(options.shading);
+    scene.set_transparency(options.transparency);
+    scene.set_outlines(options.outline.map(render::Outlines::new));
+    scene.set_background(options.background);
+    draw(scene, &default_output(options), options)
+}
 
-    let mut imgbuf = image::RgbImage::new(imgx, imgy);
+fn run_scene(path: &str, options: &cli::Options) -> Result<(), String> {
+    let desc = scenefile::load(Path::new(path))
+        .map_err(|err| format!("invalid scene '{}': {}", path, err))?;
+    let output = match (&options.output, &desc.output) {
+        (Some(output), _)    => output.clone(),
+        (None, Some(output)) => output.to_string_lossy().into_owned(),
+        (None, None)         => default_output(options),
+    };
+    let scene = desc.build().map_err(|err| err.to_string())?;
+    let meshes = scene.root().meshes();
+    for (i, &(ref mesh, _)) in meshes.iter().enumerate() {
+        // instances share their mesh and its warnings
+        if meshes[..i].iter().all(|&(ref other, _)| !Rc::ptr_eq(mesh, other)) {
+            for warning in mesh.warnings.iter() {
+                eprintln!("warning: {}: {}", path, warning);
+            }
+        }
+    }
+    draw(scene, &output, options)
+}
 
-    let mut scene = render::Scene::new(Vec::<obj::Obj>::new(), &mut imgbuf);
-    scene.add_object(obj::Obj::from_file("obj/diablo3_pose.obj").unwrap()
-        .load_texture("obj/textures/diablo3_pose_diffuse.tga"));
-    scene.light_direction(0., 0., -1.);
-    scene.draw();
-    scene.save("test.png").expect("Failed to save image");
+/// Draw the scene once, or every frame of a turntable, and save the results
+fn draw(mut scene: render::Scene, output: &str, options: &cli::Options) -> Result<(), String> {
+    for &(aov, _) in options.aovs.iter() {
+        scene.enable_aov(aov);
+    }
+    if options.format.map_or(false, |format| format.is_float()) {
+        scene.set_color_format(framebuffer::Format::Rgb32F);
+    }
+    let frames = match options.turntable {
+        Some(frames) => frames,
+        None         => {
+            scene.draw();
+            print_picks(&scene, options);
+            return save(&scene, output, None, options);
+        },
+    };
+    let turntable = turntable::Turntable::new(frames, options.motion);
+    if options.format.is_some() || !output.to_lowercase().ends_with(".gif") {
+        return turntable.render(&mut scene, |i, scene| save(scene, output, Some((i, frames)), options));
+    }
+    // all frames go into one animation, other outputs are still numbered
+    let error = |err: io::Error| format!("failed to save '{}': {}", output, err);
+    let mut file = BufWriter::new(File::create(output).map_err(error)?);
+    {
+        let (width, height) = scene.framebuffer().color(0).unwrap().dimensions();
+        let mut gif = output::GifEncoder::new(&mut file, width, height, options.fps).map_err(error)?;
+        turntable.render(&mut scene, |i, scene| {
+            gif.add_frame(scene.framebuffer().color(0).unwrap()).map_err(error)?;
+            save_layers(scene, Some((i, frames)), options)
+        })?;
+    }
+    file.flush().map_err(error)
 }
 
+/// Report what the last draw put under each pixel given with --pick
+fn print_picks(scene: &render::Scene, options: &cli::Options) {
+    for &(x, y) in options.picks.iter() {
+        match scene.pick(x, y) {
+            Some(pick) => println!("{},{}: object {}, face {}, position {},{},{}", x, y, pick.object, pick.face,
+                                   pick.position.x, pick.position.y, pick.position.z),
+            None       => println!("{},{}: background", x, y),
+        }
+    }
+}
+
+/// The output path given on the command line, or render.png with the extension of --format
+fn default_output(options: &cli::Options) -> String {
+    match (&options.output, options.format) {
+        (Some(output), _)    => output.clone(),
+        (None, Some(format)) => format!("render.{}", format.extension()),
+        (None, None)         => String::from("render.png"),
+    }
+}
+
+/// Save the image, depth and output variables, numbered if `frame` is the index and count of a
+/// turntable frame
+fn save(scene: &render::Scene, output: &str, frame: Option<(usize, usize)>, options: &cli::Options)
+        -> Result<(), String> {
+    let output = numbered(output, frame);
+    match options.format {
+        Some(format) => scene.save_as(&output, format),
+        None         => scene.save(&output),
+    }.map_err(|err| format!("failed to save '{}': {}", output, err))?;
+    save_layers(scene, frame, options)
+}
+
+fn save_layers(scene: &render::Scene, frame: Option<(usize, usize)>, options: &cli::Options) -> Result<(), String> {
+    if let Some(ref depth) = options.depth {
+        let depth = numbered(depth, frame);
+        scene.save_depth(&depth)
+            .map_err(|err| format!("failed to save depth '{}': {}", depth, err))?;
+    }
+    for &(aov, ref path) in options.aovs.iter() {
+        let path = numbered(path, frame);
+        scene.save_aov(aov, &path)
+            .map_err(|err| format!("failed to save {} '{}': {}", aov, path, err))?;
+    }
+    Ok(())
+}
+
+fn numbered(path: &str, frame: Option<(usize, usize)>) -> String {
+    match frame {
+        Some((i, frames)) => turntable::frame_path(path, i, frames),
+        None              => String::from(path),
+    }
+}
@@ -0,0 +1,320 @@
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+use geo;
+use geo::Vector;
+use obj::Obj;
+use ply;
+use stl;
+
+/// Load a mesh in the format given by the extension of `path`: .stl, .ply, or OBJ otherwise
+pub fn load(path: &Path) -> io::Result<Obj> {
+    let extension = path.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase());
+    match extension.as_ref().map(|e| e.as_str()) {
+        Some("stl") => stl::load(path),
+        Some("ply") => ply::load(path),
+        _           => Obj::from_file(&path.to_string_lossy()),
+    }
+}
+
+/// A unique combination of the attributes found at a face corner
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Vertex {
+    pub position: geo::Vec3f,
+    pub uv: Option<geo::Vec3f>,
+    pub normal: Option<geo::Vec3f>,
+    pub color: Option<geo::Vec3f>,
+}
+
+/// Triangle mesh in which every corner refers to one shared `Vertex`
+#[derive(Debug, Clone)]
+pub struct IndexedMesh {
+    pub vertices: Vec<Vertex>,
+    pub triangles: Vec<[usize; 3]>,
+}
+
+/// Which triangles of an `IndexedMesh` meet at each edge and vertex, as they were when it was built
+#[derive(Debug, Clone)]
+pub struct Adjacency {
+    /// Triangles around each undirected edge, keyed by the smaller vertex index first
+    edge_faces: HashMap<(usize, usize), Vec<usize>>,
+    vertex_faces: Vec<Vec<usize>>,
+}
+
+impl Vertex {
+
+    pub fn new(position: geo::Vec3f) -> Vertex {
+        Vertex{position, uv: None, normal: None, color: None}
+    }
+
+    /// Whether all attributes agree to within `epsilon` in every component
+    fn near(&self, other: &Vertex, epsilon: f64) -> bool {
+        let close = |a: &geo::Vec3f, b: &geo::Vec3f| {
+            (a.x - b.x).abs() <= epsilon && (a.y - b.y).abs() <= epsilon && (a.z - b.z).abs() <= epsilon
+        };
+        let attribute = |a: &Option<geo::Vec3f>, b: &Option<geo::Vec3f>| match (a, b) {
+            (Some(a), Some(b)) => close(a, b),
+            (None, None)       => true,
+            _                  => false,
+        };
+        close(&self.position, &other.position) && attribute(&self.uv, &other.uv)
+            && attribute(&self.normal, &other.normal) && attribute(&self.color, &other.color)
+    }
+
+}
+
+impl IndexedMesh {
+
+    pub fn new(vertices: Vec<Vertex>, triangles: Vec<[usize; 3]>) -> IndexedMesh {
+        IndexedMesh{vertices, triangles}
+    }
+
+    /// Gather the corners of `obj` into unique vertices, welding those whose attributes all lie
+    /// within `epsilon` of each other. Materials are not carried over.
+    #[allow(dead_code)]
+    pub fn from_obj(obj: &Obj, epsilon: f64) -> IndexedMesh {
+        let mut vertices = Vec::with_capacity(3*obj.faces.len());
+        for i in 0..obj.faces.len() {
+            let face = obj.faces[i];
+            let (uvs, normals, colors) = (obj.face_uvs(i), obj.face_vertex_normals(i), obj.face_vertex_colors(i));
+            for (k, &v) in [face.x, face.y, face.z].iter().enumerate() {
+                vertices.push(Vertex{position: obj.vertices[v as usize], uv: uvs.map(|uvs| uvs[k]),
+                                     normal: normals.map(|normals| normals[k]), color: colors.map(|colors| colors[k])});
+            }
+        }
+        let triangles = (0..obj.faces.len()).map(|i| [3*i, 3*i + 1, 3*i + 2]).collect();
+        let mut mesh = IndexedMesh::new(vertices, triangles);
+        mesh.weld(epsilon);
+        mesh.remove_unreferenced();
+        mesh
+    }
+
+    /// The mesh as an `Obj`, with one position, and texture coordinate, normal and color where
+    /// present, per vertex
+    pub fn to_obj(&self) -> Obj {
+        let faces = self.triangles.iter().map(|t| geo::Vec3i::new(t[0] as i32, t[1] as i32, t[2] as i32)).collect();
+        let mut obj = Obj::new(self.vertices.iter().map(|v| v.position).collect(), faces);
+        let attribute = |values: Vec<Option<geo::Vec3f>>, data: &mut Vec<geo::Vec3f>, indices: &mut Vec<geo::Vec3i>| {
+            if values.iter().any(|v| v.is_some()) {
+                *data = values.iter().map(|v| v.unwrap_or(geo::Vec3f::new(0., 0., 0.))).collect();
+                *indices = self.triangles.iter().map(|t| {
+                    let index = |v: usize| if values[v].is_some() { v as i32 } else { -1 };
+                    geo::Vec3i::new(index(t[0]), index(t[1]), index(t[2]))
+                }).collect();
+            }
+        };
+        attribute(self.vertices.iter().map(|v| v.uv).collect(), &mut obj.textures, &mut obj.face_textures);
+        attribute(self.vertices.iter().map(|v| v.normal).collect(), &mut obj.normals, &mut obj.face_normals);
+        if self.vertices.iter().any(|v| v.color.is_some()) {
+            obj.colors = self.vertices.iter().map(|v| v.color.unwrap_or(geo::Vec3f::new(1., 1., 1.))).collect();
+        }
+        obj
+    }
+
+    /// Merge vertices whose attributes lie within `epsilon` of an earlier vertex into it, and
+    /// return how many were merged. Vertices left unused stay in place until `remove_unreferenced`.
+    pub fn weld(&mut self, epsilon: f64) -> usize {
+        // positions are bucketed in cells at least epsilon wide, so matches lie in adjacent cells
+        let cell = if epsilon > 0. { epsilon } else { 1. };
+        let key = |p: &geo::Vec3f| ((p.x/cell).floor() as i64, (p.y/cell).floor() as i64, (p.z/cell).floor() as i64);
+        let mut grid: HashMap<(i64, i64, i64), Vec<usize>> = HashMap::new();
+        let mut remap = Vec::with_capacity(self.vertices.len());
+        let mut merged = 0;
+        for (i, vertex) in self.vertices.iter().enumerate() {
+            let (x, y, z) = key(&vertex.position);
+            let mut found = None;
+            'search: for dx in -1..2 {
+                for dy in -1..2 {
+                    for dz in -1..2 {
+                        let candidates = match grid.get(&(x + dx, y + dy, z + dz)) {
+                            Some(candidates) => candidates,
+                            None             => continue,
+                        };
+                        if let Some(&j) = candidates.iter().find(|&&j| self.vertices[j].near(vertex, epsilon)) {
+                            found = Some(j);
+                            break 'search;
+                        }
+                    }
+                }
+            }
+            match found {
+                Some(j) => {
+                    remap.push(j);
+                    merged += 1;
+                },
+                None    => {
+                    grid.entry((x, y, z)).or_insert_with(Vec::new).push(i);
+                    remap.push(i);
+                },
+            }
+        }
+        for triangle in self.triangles.iter_mut() {
+            for v in triangle.iter_mut() {
+                *v = remap[*v];
+            }
+        }
+        merged
+    }
+
+    /// Drop vertices no triangle refers to, and return how many there were
+    pub fn remove_unreferenced(&mut self) -> usize {
+        let mut used = vec![false; self.vertices.len()];
+        for triangle in self.triangles.iter() {
+            for &v in triangle.iter() {
+                used[v] = true;
+            }
+        }
+        let mut remap = vec![0; self.vertices.len()];
+        let mut kept = 0;
+        for v in 0..self.vertices.len() {
+            if used[v] {
+                self.vertices[kept] = self.vertices[v];
+                remap[v] = kept;
+                kept += 1;
+            }
+        }
+        let removed = self.vertices.len() - kept;
+        self.vertices.truncate(kept);
+        for triangle in self.triangles.iter_mut() {
+            for v in triangle.iter_mut() {
+                *v = remap[*v];
+            }
+        }
+        removed
+    }
+
+    /// Drop triangles that repeat a vertex or have no area, and return how many there were
+    pub fn remove_degenerate(&mut self) -> usize {
+        let count = self.triangles.len();
+        let vertices = &self.vertices;
+        self.triangles.retain(|t| {
+            if t[0] == t[1] || t[1] == t[2] || t[2] == t[0] {
+                return false;
+            }
+            let (a, b, c) = (&vertices[t[0]].position, &vertices[t[1]].position, &vertices[t[2]].position);
+            let (e1, e2) = (b - a, c - a);
+            // compare against the edge lengths so that the test does not depend on the scale
+            e1.cross(&e2).norm() > 1e-12*e1.norm()*e2.norm()
+        });
+        count - self.triangles.len()
+    }
+
+    /// Build the adjacency of the triangles as they are now
+    pub fn adjacency(&self) -> Adjacency {
+        let mut edge_faces = HashMap::new();
+        let mut vertex_faces = vec![Vec::new(); self.vertices.len()];
+        for (i, t) in self.triangles.iter().enumerate() {
+            for k in 0..3 {
+                let (a, b) = (t[k], t[(k + 1) % 3]);
+                let key = if a < b { (a, b) } else { (b, a) };
+                edge_faces.entry(key).or_insert_with(Vec::new).push(i);
+                if !vertex_faces[a].contains(&i) {
+                    vertex_faces[a].push(i);
+                }
+            }
+        }
+        Adjacency{edge_faces, vertex_faces}
+    }
+
+}
+
+impl Adjacency {
+
+    /// Triangles sharing the edge between vertices `a` and `b`, in either direction
+    pub fn edge_faces(&self, a: usize, b: usize) -> &[usize] {
+        let key = if a < b { (a, b) } else { (b, a) };
+        self.edge_faces.get(&key).map_or(&[], |faces| faces.as_slice())
+    }
+
+    /// Triangles using vertex `v`
+    #[allow(dead_code)]
+    pub fn vertex_faces(&self, v: usize) -> &[usize] {
+        self.vertex_faces.get(v).map_or(&[], |faces| faces.as_slice())
+    }
+
+    /// Every undirected edge once, with the smaller vertex index first, in no particular order
+    pub fn edges(&self) -> Vec<(usize, usize)> {
+        self.edge_faces.keys().cloned().collect()
+    }
+
+    /// Edges used by a single triangle, which border holes in the surface
+    #[allow(dead_code)]
+    pub fn boundary_edges(&self) -> Vec<(usize, usize)> {
+        self.edge_faces.iter().filter(|(_, faces)| faces.len() == 1).map(|(&edge, _)| edge).collect()
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Unit cube as 12 separate triangles, with every corner its own vertex
+    fn cube_soup() -> IndexedMesh {
+        let corners = |i: usize| geo::Vec3f::new((i >> 2) as f64, ((i >> 1) & 1) as f64, (i & 1) as f64);
+        let faces = [[1, 3, 2], [1, 2, 0], [4, 6, 7], [4, 7, 5], [4, 5, 1], [4, 1, 0],
+                     [2, 3, 7], [2, 7, 6], [2, 6, 4], [2, 4, 0], [1, 5, 7], [1, 7, 3]];
+        let vertices = faces.iter().flat_map(|f| f.iter().map(|&i| Vertex::new(corners(i))).collect::<Vec<_>>()).collect();
+        IndexedMesh::new(vertices, (0..12).map(|i| [3*i, 3*i + 1, 3*i + 2]).collect())
+    }
+
+    #[test]
+    fn weld_and_adjacency() {
+        let mut mesh = cube_soup();
+        assert_eq!(mesh.adjacency().boundary_edges().len(), 36);
+        assert_eq!(mesh.weld(0.), 28);
+        assert_eq!(mesh.remove_unreferenced(), 28);
+        assert_eq!(mesh.vertices.len(), 8);
+        // a closed surface: every edge joins two faces
+        let adjacency = mesh.adjacency();
+        assert_eq!(adjacency.edges().len(), 18);
+        assert!(adjacency.boundary_edges().is_empty());
+        let (a, b) = (mesh.triangles[0][0], mesh.triangles[0][1]);
+        assert_eq!(adjacency.edge_faces(b, a).len(), 2);
+        assert!(adjacency.edge_faces(b, a).contains(&0));
+        let corner = mesh.triangles[0][0];
+        assert!(adjacency.vertex_faces(corner).iter().all(|&f| mesh.triangles[f].contains(&corner)));
+        assert_eq!(adjacency.vertex_faces.iter().map(|faces| faces.len()).sum::<usize>(), 36);
+    }
+
+    #[test]
+    fn epsilon_and_degenerates() {
+        let vertex = |x: f64, y: f64| Vertex::new(geo::Vec3f::new(x, y, 0.));
+        let vertices = vec![vertex(0., 0.), vertex(1., 0.), vertex(0., 1.), vertex(1e-7, 1e-7), vertex(2., 0.),
+                            vertex(7., 7.)];
+        // the second triangle lies on a line once welded, the third repeats a vertex
+        let mut mesh = IndexedMesh::new(vertices.clone(), vec![[0, 1, 2], [3, 1, 4], [0, 0, 2]]);
+        assert_eq!(mesh.clone().weld(0.), 0);
+        assert_eq!(mesh.weld(1e-6), 1);
+        assert_eq!(mesh.remove_degenerate(), 2);
+        assert_eq!(mesh.triangles, vec![[0, 1, 2]]);
+        assert_eq!(mesh.remove_unreferenced(), 3);
+        assert_eq!(mesh.vertices, vertices[..3].to_vec());
+
+        // differing normals keep vertices apart
+        let mut split = IndexedMesh::new(vec![Vertex{normal: Some(geo::Vec3f::new(0., 0., 1.)), ..vertex(0., 0.)},
+                                              Vertex{normal: Some(geo::Vec3f::new(1., 0., 0.)), ..vertex(0., 0.)},
+                                              Vertex{color: Some(geo::Vec3f::new(1., 0., 0.)), ..vertex(0., 0.)}],
+                                         Vec::new());
+        assert_eq!(split.weld(1e-3), 0);
+    }
+
+    #[test]
+    fn obj_round_trip() {
+        let head = Obj::from_file("obj/african_head.obj").unwrap();
+        let mesh = IndexedMesh::from_obj(&head, 0.);
+        assert_eq!(mesh.triangles.len(), head.faces.len());
+        assert!(mesh.vertices.len() >= head.vertices.len() && mesh.vertices.len() < 3*head.faces.len());
+        let obj = mesh.to_obj();
+        for i in (0..head.faces.len()).step_by(97) {
+            let positions = |obj: &Obj| {
+                let face = obj.faces[i];
+                [obj.vertices[face.x as usize], obj.vertices[face.y as usize], obj.vertices[face.z as usize]]
+            };
+            assert_eq!(positions(&obj), positions(&head));
+            assert_eq!(obj.face_uvs(i), head.face_uvs(i));
+            assert_eq!(obj.face_vertex_normals(i), head.face_vertex_normals(i));
+        }
+    }
+
+}
@@ -5,13 +5,13 @@ use std::mem;
 extern crate num;
 use geo;
 use geo::Vector;
-use image;
+use framebuffer::{Blend, Color, RenderTarget};
 
 pub trait Polygon<T>
 {
-    fn draw(&self, img: &mut image::RgbImage, color: &[u8; 3]);
+    fn draw<R: RenderTarget>(&self, target: &mut R, color: &Color);
 
-    fn draw_filled(&self, img: &mut image::RgbImage, color: &[u8; 3], zbuf: &mut Vec<f64>);
+    fn draw_filled<R: RenderTarget>(&self, target: &mut R, color: &Color);
 
     fn inside(&self, point: &geo::Vec3<T>) -> bool;
 
@@ -51,15 +51,17 @@ impl<T> Polygon<T> for Line<T>
     where T: geo::Number<T>
 {
 
-    fn draw(&self, img: &mut image::RgbImage, color: &[u8; 3]) {
+    fn draw<R: RenderTarget>(&self, target: &mut R, color: &Color) {
         for pixel in self.into_iter() {
             let geo::Vec3i{x, y, z: _} = pixel;
-            img.put_pixel(x as u32, y as u32, image::Rgb::<u8>(*color));
+            if x >= 0 && y >= 0 {
+                target.set_color(x as u32, y as u32, color);
+            }
         }
     }
 
-    fn draw_filled(&self, img: &mut image::RgbImage, color: &[u8; 3], zbuf: &mut Vec<f64>) {
-        self.draw(img, color);
+    fn draw_filled<R: RenderTarget>(&self, target: &mut R, color: &Color) {
+        self.draw(target, color);
     }
 
     fn inside(&self, point: &geo::Vec3<T>) -> bool {
@@ -179,7 +181,7 @@ impl<T> Triangle<T>
         if u.z.abs() < 1. {
             geo::Vec3f::new(-1., 1., 1.)
         } else {
-            geo::Vec3f::new(1.-(u.x+u.y)/u.z, u.y/u.z, u.x/u.z)
+            geo::Vec3f::new(1.-(u.x+u.y)/u.z, u.x/u.z, u.y/u.z)
         }
     }
 
@@ -202,6 +204,94 @@ impl<T> Triangle<T>
        [&self.a, &self.b, &self.c]
     }
 
+    /// Barycentric coordinates of pixel (x, y), for a triangle in normalized device coordinates drawn
+    /// to a `dimx` by `dimy` image, matching the weights used by `draw_shaded`
+    pub fn pixel_barycentric(&self, dimx: u32, dimy: u32, x: u32, y: u32) -> geo::Vec3f {
+        self.rasterize(dimx, dimy).barycentric(&geo::Vec3f::new(x as f64, y as f64, 0.))
+    }
+
+    /// Tie-break for pixels exactly on the boundary: an edge is owned if the triangle lies to its
+    /// right, or above it for horizontal edges, so two faces sharing an edge never both own it
+    fn owns_boundary(&self, barycentric: &geo::Vec3f) -> bool {
+        let vertices = self.vertices();
+        let weights = [barycentric.x, barycentric.y, barycentric.z];
+        (0..3).filter(|&i| weights[i] == 0.).all(|i| {
+            let (p, q, r) = (vertices[(i + 1) % 3].to_f64().unwrap(), vertices[(i + 2) % 3].to_f64().unwrap(),
+                             vertices[i].to_f64().unwrap());
+            let (mut nx, mut ny) = (q.y - p.y, p.x - q.x);
+            if nx*(r.x - p.x) + ny*(r.y - p.y) < 0. {
+                nx = -nx;
+                ny = -ny;
+            }
+            nx > 0. || (nx == 0. && ny > 0.)
+        })
+    }
+
+    /// Rates of change of the barycentric coordinates per pixel step in x and in y, for a
+    /// triangle in normalized device coordinates drawn to a `dimx` by `dimy` image
+    pub fn screen_gradients(&self, dimx: u32, dimy: u32) -> Option<(geo::Vec3f, geo::Vec3f)> {
+        let to_pixels = |v: &geo::Vec3<T>| {
+            let v = v.to_f64().unwrap();
+            geo::Vec2f::new((v.x + 1.)*0.5*dimx as f64, (v.y + 1.)*0.5*dimy as f64)
+        };
+        let (a, b, c) = (to_pixels(&self.a), to_pixels(&self.b), to_pixels(&self.c));
+        let e1 = b - &a;
+        let e2 = c - &a;
+        let det = e1.x*e2.y - e1.y*e2.x;
+        if det.abs() < 1e-12 {
+            return None;
+        }
+        let (db_dx, db_dy) = (e2.y/det, -e2.x/det);
+        let (dc_dx, dc_dy) = (-e1.y/det, e1.x/det);
+        Some((geo::Vec3f::new(-db_dx - dc_dx, db_dx, dc_dx), geo::Vec3f::new(-db_dy - dc_dy, db_dy, dc_dy)))
+    }
+
+    /// Rasterize the triangle, calling `shader` with the barycentric coordinates of every covered
+    /// pixel that passes the depth test. The shader returns the pixel color, or None to discard it.
+    pub fn draw_shaded<R, F>(&self, target: &mut R, shader: F)
+        where R: RenderTarget, F: FnMut(&geo::Vec3f) -> Option<Color>
+    {
+        self.draw_blended(target, Blend::Replace, shader);
+    }
+
+    /// Like `draw_shaded`, combining the shaded pixels with the target according to `blend`
+    pub fn draw_blended<R, F>(&self, target: &mut R, blend: Blend, mut shader: F)
+        where R: RenderTarget, F: FnMut(&geo::Vec3f) -> Option<Color>
+    {
+        let (imgx, imgy) = target.dimensions();
+        let rast = self.rasterize(imgx, imgy);
+        let Line{start: bbox_min, end: bbox_max} = self.bounding_box(imgx, imgy);
+        let depths: Vec<f64> = self.vertices().iter().map(|v| v.z.to_f64().unwrap()).collect();
+        for x in bbox_min.x..bbox_max.x + 1 {
+            for y in bbox_min.y..bbox_max.y + 1 {
+                let point = geo::Vec3f::new(x as f64, y as f64, 0.);
+                let barycentric = rast.barycentric(&point);
+                if barycentric.x < 0. || barycentric.y < 0. || barycentric.z < 0. {
+                    continue;
+                }
+                // pixels on an edge shared with a neighbour must only be blended once
+                if blend != Blend::Replace && !rast.owns_boundary(&barycentric) {
+                    continue;
+                }
+                let z = depths[0]*barycentric.x + depths[1]*barycentric.y + depths[2]*barycentric.z;
+                let (x, y) = (x as u32, y as u32);
+                if target.depth(x, y).map_or(true, |depth| depth >= z) {
+                    continue;
+                }
+                if let Some(color) = shader(&barycentric) {
+                    match blend {
+                        Blend::Replace  => {
+                            target.set_depth(x, y, z);
+                            target.set_color(x, y, &color);
+                        },
+                        Blend::Over     => target.blend_color(x, y, &color),
+                        Blend::Weighted => target.accumulate(x, y, &color, z),
+                    }
+                }
+            }
+        }
+    }
+
 }
 
 
@@ -209,40 +299,20 @@ impl<T> Polygon<T> for Triangle<T>
     where T: geo::Number<T> + num::ToPrimitive
 {
 
-    fn draw(&self, img: &mut image::RgbImage, color: &[u8; 3]) {
-        let (imgx, imgy) = img.dimensions();
+    fn draw<R: RenderTarget>(&self, target: &mut R, color: &Color) {
+        let (imgx, imgy) = target.dimensions();
         let rast = self.rasterize(imgx-1, imgy-1);
         let (a, b, c) = match &rast.edges {
             [a, b, c] => (a, b, c),
             _         => unreachable!()
         };
-        a.draw(img, color);
-        b.draw(img, color);
-        c.draw(img, color);
+        a.draw(target, color);
+        b.draw(target, color);
+        c.draw(target, color);
     }
 
-    fn draw_filled(&self, img: &mut image::RgbImage, color: &[u8; 3], zbuf: &mut Vec<f64>) {
-        let (imgx, imgy) = img.dimensions();
-        let rast = self.rasterize(imgx, imgy);
-        let Line{start: bbox_min, end: bbox_max} = self.bounding_box(imgx, imgy);
-        let mut point = geo::Vec3::<f64>::new(0., 0., 0.);
-        for x in bbox_min.x..bbox_max.x {
-            for y in bbox_min.y..bbox_max.y {
-                point.x = x as f64; point.y = y as f64;
-                if !rast.inside(&point.to_i32().unwrap()) {
-                    continue;
-                }
-                point.z = 0.;
-                let barycentric: [f64; 3] = self.barycentric(&point).into();
-                for (i, vertex) in self.vertices().into_iter().enumerate() {
-                    point.z += vertex.to_f64().unwrap().z*barycentric[i];
-                }
-                if zbuf[(point.x + point.y*(imgx as f64)) as usize] < point.z {
-                    zbuf[(point.x + point.y*(imgx as f64)) as usize] = point.z;
-                    img.put_pixel(point.x as u32, point.y as u32, image::Rgb::<u8>(*color));
-                }
-            }
-        }
+    fn draw_filled<R: RenderTarget>(&self, target: &mut R, color: &Color) {
+        self.draw_shaded(target, |_| Some(*color));
     }
 
     fn inside(&self, point: &geo::Vec3<T>) -> bool {
@@ -270,6 +340,7 @@ impl<T> Polygon<T> for Triangle<T>
 #[cfg(test)]
 mod tests {
     use super::*;
+    use image;
 
     #[test]
     fn triangle_create() {
@@ -333,4 +404,40 @@ mod tests {
         assert!(triangle.inside(&inside));
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn barycentric_weights() {
+        let triangle = Triangle::new(geo::Vec3i::new(0, 0, 0), geo::Vec3i::new(10, 0, 0),
+                                     geo::Vec3i::new(0, 10, 0));
+        assert_eq!(triangle.barycentric(&geo::Vec3f::new(10., 0., 0.)), geo::Vec3f::new(0., 1., 0.));
+        assert_eq!(triangle.barycentric(&geo::Vec3f::new(0., 10., 0.)), geo::Vec3f::new(0., 0., 1.));
+    }
+
+    #[test]
+    fn gradients() {
+        let triangle = Triangle::new(geo::Vec3f::new(-1., -1., 0.), geo::Vec3f::new(1., -1., 0.),
+                                     geo::Vec3f::new(-1., 1., 0.));
+        let (ddx, ddy) = triangle.screen_gradients(10, 20).unwrap();
+        assert_eq!(ddx, geo::Vec3f::new(-0.1, 0.1, 0.));
+        assert_eq!(ddy, geo::Vec3f::new(-0.05, 0., 0.05));
+    }
+
+    #[test]
+    fn blended_edges_drawn_once() {
+        use framebuffer::{Format, Framebuffer};
+        let mut fb = Framebuffer::new(16, 16, Format::Rgb32F);
+        fb.clear(&[0., 0., 0., 1.]);
+        let (a, b) = (geo::Vec3f::new(-0.5, -0.5, 0.), geo::Vec3f::new(0.5, -0.5, 0.));
+        let (c, d) = (geo::Vec3f::new(0.5, 0.5, 0.), geo::Vec3f::new(-0.5, 0.5, 0.));
+        for triangle in [Triangle::new(a, b, c), Triangle::new(a, c, d)].iter() {
+            triangle.draw_blended(&mut fb, Blend::Over, |_| Some([1., 1., 1., 0.5]));
+        }
+        for y in 0..16 {
+            for x in 0..16 {
+                let value = RenderTarget::color(&fb, x, y).unwrap()[0];
+                assert!(value == 0. || value == 0.5, "pixel {},{} blended to {}", x, y, value);
+            }
+        }
+        assert_eq!(RenderTarget::color(&fb, 8, 8).unwrap()[0], 0.5);
+    }
+
+}
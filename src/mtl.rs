@@ -0,0 +1,110 @@
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::Path;
+
+/// A Wavefront material. Only the diffuse color, the opacity and the diffuse map are read.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Material {
+    pub name: String,
+    /// `Kd`, linear RGB in [0, 1]
+    pub diffuse: [f64; 3],
+    /// `d`, or one minus `Tr`; 1 is opaque
+    pub opacity: f64,
+    /// `map_Kd`, as written in the file
+    pub diffuse_map: Option<String>,
+}
+
+impl Material {
+
+    pub fn new(name: &str) -> Material {
+        Material{name: String::from(name), diffuse: [1., 1., 1.], opacity: 1., diffuse_map: None}
+    }
+
+}
+
+pub fn load(path: &Path) -> io::Result<Vec<Material>> {
+    let mut text = String::new();
+    File::open(path)?.read_to_string(&mut text)?;
+    parse(&text).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), err)))
+}
+
+/// Write materials in the MTL format, with diffuse maps as they were read
+pub fn write<W: Write>(materials: &[Material], w: &mut W) -> io::Result<()> {
+    for (i, material) in materials.iter().enumerate() {
+        if i > 0 {
+            writeln!(w)?;
+        }
+        let [r, g, b] = material.diffuse;
+        writeln!(w, "newmtl {}\nKd {} {} {}\nd {}", material.name, r, g, b, material.opacity)?;
+        if let Some(ref map) = material.diffuse_map {
+            writeln!(w, "map_Kd {}", map)?;
+        }
+    }
+    Ok(())
+}
+
+/// Parse the contents of an MTL file; errors name the offending line
+pub fn parse(text: &str) -> Result<Vec<Material>, String> {
+    let mut materials: Vec<Material> = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let mut words = line.split_whitespace();
+        let keyword = match words.next() {
+            Some(keyword) if !keyword.starts_with('#') => keyword,
+            _ => continue,
+        };
+        let args: Vec<&str> = words.collect();
+        let error = |message: &str| format!("line {}: {}", n + 1, message);
+        if keyword == "newmtl" {
+            let name = args.first().ok_or_else(|| error("newmtl without a name"))?;
+            materials.push(Material::new(name));
+            continue;
+        }
+        let material = match materials.last_mut() {
+            Some(material) => material,
+            None           => return Err(error(&format!("'{}' before any newmtl", keyword))),
+        };
+        let numbers = || -> Result<Vec<f64>, String> {
+            args.iter().map(|arg| arg.parse().map_err(|_| error(&format!("invalid number '{}'", arg)))).collect()
+        };
+        match keyword {
+            "Kd" => {
+                let kd = numbers()?;
+                if kd.len() != 3 {
+                    return Err(error("Kd needs three components"));
+                }
+                material.diffuse = [kd[0], kd[1], kd[2]];
+            },
+            "d" | "Tr" => {
+                let value = *numbers()?.first().ok_or_else(|| error(&format!("{} needs a value", keyword)))?;
+                material.opacity = if keyword == "d" { value } else { 1. - value };
+            },
+            "map_Kd" => material.diffuse_map = args.last().map(|path| String::from(*path)),
+            _ => continue,
+        }
+    }
+    Ok(materials)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_materials() {
+        let materials = parse("# glass\nnewmtl glass\nKd 0.2 0.4 1\nd 0.25\nillum 4\n\n\
+                               newmtl leaf\nTr 0.1\nmap_Kd -bm 1 leaf.png\n").unwrap();
+        assert_eq!(materials.len(), 2);
+        assert_eq!(materials[0].diffuse, [0.2, 0.4, 1.]);
+        assert_eq!(materials[0].opacity, 0.25);
+        assert!((materials[1].opacity - 0.9).abs() < 1e-12);
+        assert_eq!(materials[1].diffuse_map, Some(String::from("leaf.png")));
+        assert_eq!(parse("Kd 1 1 1"), Err(String::from("line 1: 'Kd' before any newmtl")));
+        assert_eq!(parse("newmtl a\nKd 1 x 1"), Err(String::from("line 2: invalid number 'x'")));
+
+        let mut text = Vec::new();
+        write(&materials, &mut text).unwrap();
+        assert_eq!(parse(&String::from_utf8(text).unwrap()).unwrap(), materials);
+    }
+
+}
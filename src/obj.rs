@@ -1,15 +1,44 @@
 use std;
 use std::io;
 use std::vec;
-use std::str::FromStr;
-use std::io::BufReader;
 use std::fs::File;
+use std::path::Path;
 use std::io::prelude::*;
+use std::ops::Range;
+use std::thread;
 extern crate rand;
 use model;
 use geo;
 use geo::Vector;
 use image;
+use texture;
+use mtl;
+use objparse;
+use bounds::{Aabb, Sphere};
+
+/// How the normals of the faces around a vertex are combined by `Obj::compute_normals`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NormalWeighting {
+    /// Every face counts the same
+    Flat,
+    /// Faces count in proportion to their area
+    Area,
+    /// Faces count in proportion to their angle at the vertex
+    Angle,
+}
+
+/// Crease angle used for meshes loaded without normals
+pub const DEFAULT_CREASE_ANGLE: f64 = std::f64::consts::PI/3.;
+
+/// Files from this many bytes on are parsed in parallel
+const PARALLEL_SIZE: usize = 1 << 20;
+
+/// A named object (`o`) or group (`g`) of a mesh: a run of consecutive faces
+#[derive(Debug, Clone, PartialEq)]
+pub struct Group {
+    pub name: String,
+    pub faces: Range<usize>,
+}
 
 pub struct Obj {
     pub nvert: usize,
@@ -17,38 +46,186 @@ pub struct Obj {
     pub vertices: vec::Vec<geo::Vec3f>,
     pub faces: vec::Vec<geo::Vec3i>,
     pub textures: vec::Vec<geo::Vec3f>,
-    texture_img: Option<image::DynamicImage>,
+    pub normals: vec::Vec<geo::Vec3f>,
+    /// Texture coordinate indices per face, -1 where the face has none
+    pub face_textures: vec::Vec<geo::Vec3i>,
+    /// Vertex normal indices per face, -1 where the face has none
+    pub face_normals: vec::Vec<geo::Vec3i>,
+    /// Linear RGB in [0, 1] per entry of `vertices`, or empty if the mesh has no vertex colors
+    pub colors: vec::Vec<geo::Vec3f>,
+    /// Materials from the `mtllib` files of the mesh
+    pub materials: vec::Vec<mtl::Material>,
+    /// Index into `materials` per face, None where no known material is in use
+    pub face_materials: vec::Vec<Option<usize>>,
+    /// Objects started by `o` lines, in file order
+    pub objects: vec::Vec<Group>,
+    /// Groups started by `g` lines, in file order; faces may belong to several at once
+    pub groups: vec::Vec<Group>,
+    /// Smoothing group per face, 0 where smoothing is off
+    pub face_smoothing: vec::Vec<u32>,
+    /// Problems that did not stop the mesh from loading, such as a missing material library
+    pub warnings: vec::Vec<String>,
+    /// Per face whether it is hidden, or empty if all faces are shown
+    hidden: vec::Vec<bool>,
+    texture: Option<texture::Texture>,
+    color: [u8; 3],
+    opacity: f64,
+    alpha_cutoff: Option<f64>,
 }
 
 impl Obj {
+    /// Mesh of the given triangles, without texture coordinates, normals or materials
+    pub fn new(vertices: vec::Vec<geo::Vec3f>, faces: vec::Vec<geo::Vec3i>) -> Obj {
+        let missing = vec![geo::Vec3i::new(-1, -1, -1); faces.len()];
+        Obj{nvert: vertices.len(), nfaces: faces.len(), vertices, textures: vec::Vec::new(), normals: vec::Vec::new(),
+            face_textures: missing.clone(), face_normals: missing, colors: vec::Vec::new(), materials: vec::Vec::new(),
+            face_materials: vec![None; faces.len()], objects: vec::Vec::new(), groups: vec::Vec::new(),
+            face_smoothing: vec![0; faces.len()], warnings: vec::Vec::new(), hidden: vec::Vec::new(), faces,
+            texture: None, color: [255, 255, 255], opacity: 1., alpha_cutoff: None}
+    }
+
+    /// Load an OBJ file, parsing large ones on all available threads
     pub fn from_file(fpath: &str) -> io::Result<Obj> {
-        let file = File::open(fpath)?;
-        let buf_reader = BufReader::new(file);
-        let mut vertices = vec::Vec::<geo::Vec3f>::new();
-        let mut faces = vec::Vec::<geo::Vec3i>::new();
-        let mut textures = vec::Vec::<geo::Vec3f>::new();
-        for line in buf_reader.lines() {
-            let line = line.unwrap();
-            if line.len() < 3 {
-                continue;
-            }
-            let prefix = &line[..2];
-            match prefix {
-                "v " => vertices.push(geo::Vec3f::from(&Obj::collect_vec::<f64>(&line))),
-                "f " => faces.push(geo::Vec3i::from(&Obj::collect_face(&line))),
-                "vt" => textures.push(geo::Vec3f::from(&Obj::collect_vec::<f64>(&line))),
-                _    => continue
-            };
+        let mut bytes = vec::Vec::new();
+        File::open(fpath)?.read_to_end(&mut bytes)?;
+        let threads = if bytes.len() < PARALLEL_SIZE { 1 } else { thread::available_parallelism().map_or(1, |n| n.get()) };
+        // material libraries are found relative to the mesh
+        let dir = Path::new(fpath).parent().unwrap_or(Path::new(""));
+        let mut obj = objparse::parse(&bytes, dir, threads)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", fpath, err)))?;
+        // smooth shading needs vertex normals, so make them up where the file has none
+        if obj.normals.is_empty() {
+            obj.compute_normals(NormalWeighting::Angle, DEFAULT_CREASE_ANGLE);
         }
-        Ok(Obj{nvert: vertices.len(), nfaces: faces.len(), 
-            vertices, faces, textures, texture_img: None})
+        Ok(obj)
+    }
+
+    pub fn load_texture(self, path: &str) -> image::ImageResult<Self> {
+        Ok(self.with_texture(texture::Texture::load(Path::new(path))?))
     }
 
-    pub fn load_texture(mut self, path: &str) -> Self {
-        self.texture_img = Some(image::open(path).unwrap());
+    pub fn with_texture(mut self, texture: texture::Texture) -> Self {
+        self.texture = Some(texture);
         self
     }
 
+    pub fn texture_mut(&mut self) -> Option<&mut texture::Texture> {
+        self.texture.as_mut()
+    }
+
+    /// Set the diffuse color, which tints the texture if there is one
+    pub fn with_color(mut self, color: [u8; 3]) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn color(&self) -> [u8; 3] {
+        self.color
+    }
+
+    /// Set the opacity of the whole object, which multiplies that of its materials
+    pub fn with_opacity(mut self, opacity: f64) -> Self {
+        self.opacity = opacity;
+        self
+    }
+
+    pub fn opacity(&self) -> f64 {
+        self.opacity
+    }
+
+    /// Alpha-test the diffuse texture: texels with less alpha than `threshold` are discarded, the
+    /// rest are drawn opaque
+    pub fn with_alpha_cutoff(mut self, threshold: f64) -> Self {
+        self.alpha_cutoff = Some(threshold);
+        self
+    }
+
+    pub fn alpha_cutoff(&self) -> Option<f64> {
+        self.alpha_cutoff
+    }
+
+    /// Whether a pixel with texture alpha `alpha` fails the alpha test
+    pub fn cuts_out(&self, alpha: f64) -> bool {
+        self.alpha_cutoff.map_or(false, |threshold| alpha < threshold)
+    }
+
+    /// Material of face `i`, if it has one
+    pub fn face_material(&self, i: usize) -> Option<&mtl::Material> {
+        self.face_materials[i].map(|m| &self.materials[m])
+    }
+
+    /// Faces of the objects and groups named `name`
+    fn named_faces(&self, name: &str) -> vec::Vec<Range<usize>> {
+        self.objects.iter().chain(self.groups.iter())
+            .filter(|group| group.name == name)
+            .map(|group| group.faces.clone())
+            .collect()
+    }
+
+    /// Show or hide the faces of the objects and groups named `name`; false if there are none
+    pub fn set_group_visible(&mut self, name: &str, visible: bool) -> bool {
+        let ranges = self.named_faces(name);
+        if self.hidden.is_empty() && !ranges.is_empty() {
+            self.hidden = vec![false; self.faces.len()];
+        }
+        for range in ranges.iter() {
+            for i in range.clone() {
+                self.hidden[i] = !visible;
+            }
+        }
+        !ranges.is_empty()
+    }
+
+    pub fn is_face_visible(&self, i: usize) -> bool {
+        !self.hidden.get(i).cloned().unwrap_or(false)
+    }
+
+    /// Give the faces of the objects and groups named `name` their own material, replacing any
+    /// material of the same name; false if there are no such faces
+    pub fn set_group_material(&mut self, name: &str, material: mtl::Material) -> bool {
+        let ranges = self.named_faces(name);
+        if ranges.is_empty() {
+            return false;
+        }
+        let index = match self.materials.iter().position(|m| m.name == material.name) {
+            Some(index) => {
+                self.materials[index] = material;
+                index
+            },
+            None => {
+                self.materials.push(material);
+                self.materials.len() - 1
+            },
+        };
+        for range in ranges {
+            for i in range {
+                self.face_materials[i] = Some(index);
+            }
+        }
+        true
+    }
+
+    /// Opacity of face `i` from the object and its material, before texture alpha
+    pub fn face_opacity(&self, i: usize) -> f64 {
+        self.opacity*self.face_material(i).map_or(1., |m| m.opacity)
+    }
+
+    /// Whether face `i` needs blending: it is not fully opaque, or it may be drawn with a texture
+    /// that has transparent texels and no alpha test
+    pub fn is_transparent(&self, i: usize, textured: bool) -> bool {
+        self.face_opacity(i) < 1.
+            || (textured && self.alpha_cutoff.is_none() && self.texture.as_ref().map_or(false, |t| t.has_alpha()))
+    }
+
+    pub fn has_texture(&self) -> bool {
+        self.texture.is_some()
+    }
+
+    /// Filtered RGBA lookup of the diffuse texture for a pixel footprint spanned by `duv_dx` and `duv_dy`
+    pub fn diffuse(&self, uv: &geo::Vec2f, duv_dx: &geo::Vec2f, duv_dy: &geo::Vec2f) -> Option<[f64; 4]> {
+        Some(self.texture.as_ref()?.sample(uv, duv_dx, duv_dy))
+    }
+
     pub fn face(&self, i: usize) -> geo::Vec3i {
         self.faces[i].clone()
     }
@@ -57,24 +234,191 @@ impl Obj {
         self.vertices[i].clone()
     }
 
-    fn collect_vec<T>(s: &str) -> [T; 3]
-        where T: FromStr + geo::Number<T>,
-              <T as std::str::FromStr>::Err : std::fmt::Debug
-    {
-        let v: Vec<T> = s[2..].split_whitespace().map(|x| x.parse::<T>().unwrap()).collect();
-        [v[0].clone(), v[1].clone(), v[2].clone()]
+    /// Bounding box of the vertices, or None for an empty mesh
+    pub fn bounds(&self) -> Option<Aabb> {
+        Aabb::from_points(self.vertices.iter())
+    }
+
+    pub fn bounding_sphere(&self) -> Option<Sphere> {
+        Sphere::from_points(self.vertices.iter())
     }
 
-    fn collect_face(s: &str) -> [i32; 3] {
-        let terms: Vec<&str> = s[2..].split_whitespace().collect();
-        let mut vec = vec::Vec::<i32>::new();
-        for i in 0..3 {
-            let indices = terms[i].split("/")
-                .map(|x| x.parse::<i32>().unwrap());
-            vec.push(indices.collect::<vec::Vec<i32>>()[0]-1); // indices in wavefront start with 1
+    /// Center the mesh on the origin and scale it uniformly so that it fits into [-1, 1] on every
+    /// axis, touching the bounds along its largest extent
+    pub fn normalize_to_unit_cube(&mut self) {
+        let bounds = match self.bounds() {
+            Some(bounds) => bounds,
+            None         => return,
+        };
+        let (center, size) = (bounds.center(), bounds.size());
+        let largest = size.x.max(size.y).max(size.z);
+        let scale = if largest > 0. { 2./largest } else { 1. };
+        for v in self.vertices.iter_mut() {
+            *v = &(&*v - &center)*scale;
         }
-        let array: [i32; 3] = [vec[0], vec[1], vec[2]];
-        array
+    }
+
+    /// Replace the vertex normals with ones averaged from the faces around each vertex. Faces
+    /// whose normals differ by more than `crease_angle` radians from that of a corner's own face
+    /// are left out, which splits the vertex along hard edges; a crease angle of zero gives
+    /// faceted normals. If the mesh has smoothing groups they decide instead: only faces of the
+    /// same group are smoothed together, and faces with smoothing off stay flat.
+    pub fn compute_normals(&mut self, weighting: NormalWeighting, crease_angle: f64) {
+        let corners = |face: &geo::Vec3i| [face.x as usize, face.y as usize, face.z as usize];
+        // unnormalized outward normals, twice the area of the face long
+        let areas: Vec<geo::Vec3f> = self.faces.iter().map(|face| {
+            let [a, b, c] = corners(face);
+            let (a, b, c) = (&self.vertices[a], &self.vertices[b], &self.vertices[c]);
+            (b - a).cross(&(c - a))
+        }).collect();
+        let units: Vec<Option<geo::Vec3f>> = areas.iter()
+            .map(|n| if n.norm() > 0. { Some(n.normalize()) } else { None })
+            .collect();
+        let mut incident = vec![Vec::new(); self.vertices.len()];
+        for (i, face) in self.faces.iter().enumerate() {
+            for (k, &v) in corners(face).iter().enumerate() {
+                incident[v].push((i, k));
+            }
+        }
+        let weight = |i: usize, k: usize| -> f64 {
+            match weighting {
+                NormalWeighting::Flat  => 1.,
+                NormalWeighting::Area  => areas[i].norm(),
+                NormalWeighting::Angle => {
+                    let vertices = corners(&self.faces[i]);
+                    let p = &self.vertices[vertices[k]];
+                    let e1 = (&self.vertices[vertices[(k + 1) % 3]] - p).normalize();
+                    let e2 = (&self.vertices[vertices[(k + 2) % 3]] - p).normalize();
+                    e1.dot(&e2).max(-1.).min(1.).acos()
+                },
+            }
+        };
+        let cos_crease = crease_angle.cos();
+        let smoothing = &self.face_smoothing;
+        let by_group = smoothing.iter().any(|&group| group != 0);
+        let joins = |i: usize, j: usize, unit: &geo::Vec3f, own: &geo::Vec3f| {
+            if by_group {
+                i == j || (smoothing[i] != 0 && smoothing[i] == smoothing[j])
+            } else {
+                unit.dot(own) >= cos_crease
+            }
+        };
+        let mut normals = Vec::new();
+        // normals already made for each vertex, shared by corners that end up with the same one
+        let mut made: Vec<Vec<usize>> = vec![Vec::new(); self.vertices.len()];
+        let mut face_normals = vec![geo::Vec3i::new(-1, -1, -1); self.faces.len()];
+        for (i, face) in self.faces.iter().enumerate() {
+            let own = match units[i] {
+                Some(own) => own,
+                None      => continue,
+            };
+            let mut indices = [-1; 3];
+            for (k, &v) in corners(face).iter().enumerate() {
+                let mut sum = geo::Vec3f::new(0., 0., 0.);
+                for &(j, l) in incident[v].iter() {
+                    if let Some(unit) = units[j] {
+                        if joins(i, j, &unit, &own) {
+                            sum = &sum + &(&unit*weight(j, l));
+                        }
+                    }
+                }
+                let normal = if sum.norm() > 0. { sum.normalize() } else { own };
+                let index = match made[v].iter().find(|&&n| normals[n] == normal) {
+                    Some(&index) => index,
+                    None         => {
+                        normals.push(normal);
+                        made[v].push(normals.len() - 1);
+                        normals.len() - 1
+                    },
+                };
+                indices[k] = index as i32;
+            }
+            face_normals[i] = geo::Vec3i::from(&indices);
+        }
+        self.normals = normals;
+        self.face_normals = face_normals;
+    }
+
+    /// Save the mesh to `path`, and its materials, if it has any, to an MTL file of the same name
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut mtllib = None;
+        if !self.materials.is_empty() {
+            let mtl_path = path.with_extension("mtl");
+            let mut file = io::BufWriter::new(File::create(&mtl_path)?);
+            mtl::write(&self.materials, &mut file)?;
+            file.flush()?;
+            mtllib = mtl_path.file_name().map(|name| name.to_string_lossy().into_owned());
+        }
+        let mut file = io::BufWriter::new(File::create(path)?);
+        self.write_to(&mut file, mtllib.as_ref().map(|name| name.as_str()))?;
+        file.flush()
+    }
+
+    /// Write the mesh in the Wavefront OBJ format, referring to the material library `mtllib`.
+    /// Faces give every index they have as `v/vt/vn` and are preceded by the object, group,
+    /// smoothing group and material lines that apply from them on.
+    pub fn write_to<W: Write>(&self, w: &mut W, mtllib: Option<&str>) -> io::Result<()> {
+        if let Some(mtllib) = mtllib {
+            writeln!(w, "mtllib {}", mtllib)?;
+        }
+        for v in self.vertices.iter() {
+            writeln!(w, "v {} {} {}", v.x, v.y, v.z)?;
+        }
+        for vt in self.textures.iter() {
+            writeln!(w, "vt {} {} {}", vt.x, vt.y, vt.z)?;
+        }
+        for vn in self.normals.iter() {
+            writeln!(w, "vn {} {} {}", vn.x, vn.y, vn.z)?;
+        }
+        let mut material = None;
+        let mut groups = vec::Vec::new();
+        let mut smoothing = 0;
+        for (i, face) in self.faces.iter().enumerate() {
+            for object in self.objects.iter().filter(|object| object.faces.start == i) {
+                writeln!(w, "o {}", object.name)?;
+            }
+            let face_groups: vec::Vec<&str> = self.groups.iter()
+                .filter(|group| group.faces.contains(&i))
+                .map(|group| group.name.as_str())
+                .collect();
+            if face_groups != groups {
+                groups = face_groups;
+                // a bare `g` ends the groups
+                if groups.is_empty() {
+                    writeln!(w, "g")?;
+                } else {
+                    writeln!(w, "g {}", groups.join(" "))?;
+                }
+            }
+            if self.face_smoothing[i] != smoothing {
+                smoothing = self.face_smoothing[i];
+                if smoothing == 0 {
+                    writeln!(w, "s off")?;
+                } else {
+                    writeln!(w, "s {}", smoothing)?;
+                }
+            }
+            if self.face_materials[i] != material {
+                material = self.face_materials[i];
+                // a bare `usemtl` goes back to the default material
+                match material {
+                    Some(m) => writeln!(w, "usemtl {}", self.materials[m].name)?,
+                    None    => writeln!(w, "usemtl")?,
+                }
+            }
+            let (t, n) = (&self.face_textures[i], &self.face_normals[i]);
+            write!(w, "f")?;
+            for &(v, t, n) in [(face.x, t.x, n.x), (face.y, t.y, n.y), (face.z, t.z, n.z)].iter() {
+                match (t >= 0, n >= 0) {
+                    (true, true)   => write!(w, " {}/{}/{}", v + 1, t + 1, n + 1)?,
+                    (true, false)  => write!(w, " {}/{}", v + 1, t + 1)?,
+                    (false, true)  => write!(w, " {}//{}", v + 1, n + 1)?,
+                    (false, false) => write!(w, " {}", v + 1)?,
+                }
+            }
+            writeln!(w)?;
+        }
+        Ok(())
     }
 
     pub fn get_triangle(&self, face: &geo::Vec3<i32>) -> model::Triangle<f64> {
@@ -84,6 +428,29 @@ impl Obj {
         model::Triangle::new(*af, *bf, *cf)
     }
 
+    /// Texture coordinates of the corners of face `i`, if the face has them
+    pub fn face_uvs(&self, i: usize) -> Option<[geo::Vec3f; 3]> {
+        Obj::lookup(&self.textures, &self.face_textures[i])
+    }
+
+    /// Vertex normals of the corners of face `i`, if the face has them
+    pub fn face_vertex_normals(&self, i: usize) -> Option<[geo::Vec3f; 3]> {
+        Obj::lookup(&self.normals, &self.face_normals[i])
+    }
+
+    /// Colors of the corners of face `i`, if the mesh has vertex colors
+    pub fn face_vertex_colors(&self, i: usize) -> Option<[geo::Vec3f; 3]> {
+        if self.colors.is_empty() {
+            return None;
+        }
+        Obj::lookup(&self.colors, &self.faces[i])
+    }
+
+    fn lookup(data: &[geo::Vec3f], idx: &geo::Vec3i) -> Option<[geo::Vec3f; 3]> {
+        let get = |i: i32| if i < 0 { None } else { data.get(i as usize).cloned() };
+        Some([get(idx.x)?, get(idx.y)?, get(idx.z)?])
+    }
+
 }
 
 #[cfg(test)]
@@ -97,6 +464,139 @@ mod tests {
         assert_eq!(obj.nfaces, 2492);
         assert_eq!(obj.vert(0), geo::Vec3f::new(-0.000581696, -0.734665, -0.623267));
         assert_eq!(obj.face(0), geo::Vec3i::new(23, 24, 25));
+        assert_eq!(obj.face_textures[0], geo::Vec3i::new(0, 1, 2));
+        assert_eq!(obj.face_normals[0], geo::Vec3i::new(23, 24, 25));
+        assert!(obj.face_uvs(0).is_some());
+    }
+
+    /// Load an OBJ file written from `text`
+    fn from_text(name: &str, text: &str) -> Obj {
+        let path = std::env::temp_dir().join(format!("rustyrenderer_{}.obj", name));
+        std::fs::write(&path, text).unwrap();
+        let obj = Obj::from_file(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        obj.unwrap()
+    }
+
+    const CUBE: &str = "v 0 0 0\nv 0 0 1\nv 0 1 0\nv 0 1 1\nv 1 0 0\nv 1 0 1\nv 1 1 0\nv 1 1 1\n\
+                        f 2 4 3\nf 2 3 1\nf 5 7 8\nf 5 8 6\nf 5 6 2\nf 5 2 1\n\
+                        f 3 4 8\nf 3 8 7\nf 3 7 5\nf 3 5 1\nf 2 6 8\nf 2 8 4\n";
+
+    #[test]
+    fn computed_normals() {
+        // loading splits every corner of the cube along its 90 degree edges
+        let mut cube = from_text("cube", CUBE);
+        assert_eq!(cube.normals.len(), 24);
+        for i in 0..cube.faces.len() {
+            let normals = cube.face_vertex_normals(i).unwrap();
+            let outward = &cube.get_triangle(&cube.faces[i]).normal()*-1.;
+            assert!(normals.iter().all(|n| (n.dot(&outward) - 1.).abs() < 1e-9));
+        }
+        // without creases, angle weighting points every corner along the diagonal
+        cube.compute_normals(NormalWeighting::Angle, std::f64::consts::PI);
+        assert_eq!(cube.normals.len(), 8);
+        let diagonal = geo::Vec3f::new(1., 1., 1.).normalize();
+        let corner = cube.normals[cube.face_normals[2].z as usize];
+        assert!((corner.dot(&diagonal) - 1.).abs() < 1e-9);
+        // a zero crease angle keeps the faces flat
+        cube.compute_normals(NormalWeighting::Area, 0.);
+        assert_eq!(cube.normals.len(), 24);
+
+        // recomputed normals of the head agree with the ones in the file
+        let mut head = Obj::from_file("obj/african_head.obj").unwrap();
+        let original: Vec<[geo::Vec3f; 3]> = (0..head.faces.len()).filter_map(|i| head.face_vertex_normals(i)).collect();
+        head.compute_normals(NormalWeighting::Angle, std::f64::consts::PI);
+        let mut agreement = 0.;
+        for (i, normals) in original.iter().enumerate() {
+            let computed = head.face_vertex_normals(i).unwrap();
+            for k in 0..3 {
+                agreement += normals[k].normalize().dot(&computed[k]);
+            }
+        }
+        assert!(agreement/(3*original.len()) as f64 > 0.9);
+    }
+
+    #[test]
+    fn write_round_trip() {
+        let mut head = Obj::from_file("obj/african_head.obj").unwrap();
+        let mut skin = mtl::Material::new("skin");
+        skin.diffuse = [0.8, 0.6, 0.5];
+        head.materials = vec![skin];
+        head.face_materials = (0..head.nfaces).map(|i| if i % 3 == 0 { Some(0) } else { None }).collect();
+        let path = std::env::temp_dir().join("rustyrenderer_head_copy.obj");
+        head.save(&path).unwrap();
+        let copy = Obj::from_file(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(path.with_extension("mtl")).unwrap();
+        let copy = copy.unwrap();
+        assert_eq!((copy.nvert, copy.nfaces), (head.nvert, head.nfaces));
+        assert_eq!((&copy.vertices, &copy.textures, &copy.normals), (&head.vertices, &head.textures, &head.normals));
+        assert_eq!((&copy.faces, &copy.face_textures, &copy.face_normals),
+                   (&head.faces, &head.face_textures, &head.face_normals));
+        assert_eq!((&copy.materials, &copy.face_materials), (&head.materials, &head.face_materials));
+
+        // faces without texture coordinates leave out that index
+        let mut text = Vec::new();
+        from_text("cube_copy", CUBE).write_to(&mut text, None).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        assert!(text.starts_with("v 0 0 0\n"));
+        assert!(text.contains("\nf 2//1 4//2 3//3\n"));
+    }
+
+    #[test]
+    fn groups_and_smoothing() {
+        let vertices: Vec<&str> = CUBE.lines().filter(|line| line.starts_with("v ")).collect();
+        let faces: Vec<&str> = CUBE.lines().filter(|line| line.starts_with("f ")).collect();
+        let text = format!("{}\no box\ng sides\ns 1\n{}\ng top bottom\ns off\n{}\n", vertices.join("\n"),
+                           faces[..8].join("\n"), faces[8..].join("\n"));
+        let mut cube = from_text("grouped_cube", &text);
+        assert_eq!(cube.objects, vec![Group{name: String::from("box"), faces: 0..12}]);
+        assert_eq!(cube.groups.iter().map(|g| (g.name.as_str(), g.faces.clone())).collect::<Vec<_>>(),
+                   vec![("sides", 0..8), ("top", 8..12), ("bottom", 8..12)]);
+        assert_eq!((cube.face_smoothing[7], cube.face_smoothing[8]), (1, 0));
+        // the four sides are smoothed into each other across their edges, the ends stay flat
+        let side = cube.face_vertex_normals(0).unwrap()[1];
+        assert!((&side - &geo::Vec3f::new(-1., 1., 0.).normalize()).norm() < 1e-9);
+        assert!(cube.face_vertex_normals(8).unwrap().iter().all(|n| *n == geo::Vec3f::new(0., 0., -1.)));
+
+        assert!(cube.set_group_visible("top", false));
+        assert!(!cube.set_group_visible("lid", false));
+        assert!(cube.is_face_visible(7) && !cube.is_face_visible(8));
+        assert!(cube.set_group_material("sides", mtl::Material::new("red")));
+        assert_eq!((cube.face_materials[0], cube.face_materials[8]), (Some(0), None));
+
+        let mut text = Vec::new();
+        cube.write_to(&mut text, None).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        assert!(text.contains("\no box\ng sides\ns 1\nusemtl red\nf "));
+        let copy = from_text("grouped_cube_copy", &text);
+        assert_eq!((&copy.objects, &copy.groups, &copy.face_smoothing), (&cube.objects, &cube.groups, &cube.face_smoothing));
+
+        // faces without a material switch back to the default one, which survives a reload
+        assert!(text.contains("\ng top bottom\ns off\nusemtl\nf "));
+        let path = std::env::temp_dir().join("rustyrenderer_grouped_cube_materials.obj");
+        cube.save(&path).unwrap();
+        let copy = Obj::from_file(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(path.with_extension("mtl")).unwrap();
+        assert_eq!(copy.face_materials, cube.face_materials);
+    }
+
+    #[test]
+    fn normalize_bounds() {
+        let scaled: Vec<String> = CUBE.lines().map(|line| {
+            if !line.starts_with("v ") {
+                return String::from(line);
+            }
+            let v: Vec<f64> = line[2..].split_whitespace().map(|x| x.parse().unwrap()).collect();
+            format!("v {} {} {}", v[0]*20., v[1]*10., v[2]*10.)
+        }).collect();
+        let mut cube = from_text("big_cube", &scaled.join("\n"));
+        assert_eq!(cube.bounds().unwrap().size(), geo::Vec3f::new(20., 10., 10.));
+        cube.normalize_to_unit_cube();
+        let bounds = cube.bounds().unwrap();
+        assert_eq!((bounds.min, bounds.max), (geo::Vec3f::new(-1., -0.5, -0.5), geo::Vec3f::new(1., 0.5, 0.5)));
+        assert_eq!(cube.bounding_sphere().unwrap().radius, 1.5f64.sqrt());
     }
 
 }
@@ -0,0 +1,594 @@
+//! Fast Wavefront OBJ parsing from the bytes of a whole file.
+//!
+//! A first pass counts the vertices, texture coordinates and normals of each chunk of the file,
+//! so that the chunks can then be parsed independently, on separate threads if asked to, with
+//! relative indices resolved and all indices checked. Materials, objects, groups and smoothing
+//! groups are recorded as events and applied in file order when the chunks are joined.
+use std::io;
+use std::path::Path;
+use std::str;
+use std::thread;
+use geo;
+use mtl;
+use obj::{Group, Obj};
+
+/// Parse the contents of an OBJ file with up to `threads` threads, loading material libraries
+/// from `dir`. Polygons are split into triangle fans.
+pub fn parse(bytes: &[u8], dir: &Path, threads: usize) -> Result<Obj, String> {
+    let pieces = split(bytes, threads.max(1));
+    let counts = map(&pieces, |piece| Ok(count(piece)))?;
+    let mut total = Counts::default();
+    let mut starts = Vec::with_capacity(pieces.len());
+    for counts in counts.iter() {
+        starts.push(total);
+        total = total.add(counts);
+    }
+    let work: Vec<(&[u8], Counts, Counts)> = (0..pieces.len()).map(|k| (pieces[k], starts[k], counts[k])).collect();
+    let chunks = map(&work, |&(piece, ref start, ref own)| parse_chunk(piece, start, own, &total))?;
+    join(chunks, dir)
+}
+
+/// Numbers of lines and of the vertex data of each kind
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Counts {
+    lines: usize,
+    vertices: usize,
+    textures: usize,
+    normals: usize,
+}
+
+impl Counts {
+    fn add(&self, other: &Counts) -> Counts {
+        Counts{lines: self.lines + other.lines, vertices: self.vertices + other.vertices,
+               textures: self.textures + other.textures, normals: self.normals + other.normals}
+    }
+}
+
+/// A line that changes the state faces are read with, and the number of faces of its chunk before it
+enum Event<'a> {
+    MtlLib(Vec<&'a str>),
+    UseMtl(Option<&'a str>),
+    Object(&'a str),
+    Group(Vec<&'a str>),
+    Smoothing(u32),
+}
+
+#[derive(Default)]
+struct Chunk<'a> {
+    vertices: Vec<geo::Vec3f>,
+    textures: Vec<geo::Vec3f>,
+    normals: Vec<geo::Vec3f>,
+    faces: Vec<geo::Vec3i>,
+    face_textures: Vec<geo::Vec3i>,
+    face_normals: Vec<geo::Vec3i>,
+    events: Vec<(usize, Event<'a>)>,
+}
+
+/// Split `bytes` into at most `n` pieces of about equal size that end after a newline
+fn split(bytes: &[u8], n: usize) -> Vec<&[u8]> {
+    let mut pieces = Vec::with_capacity(n);
+    let mut start = 0;
+    for k in 1..n {
+        // a piece is never empty, which also keeps `end - 1` in range
+        let mut end = (bytes.len()*k/n).max(start + 1);
+        while end < bytes.len() && bytes[end - 1] != b'\n' {
+            end += 1;
+        }
+        if end > start && end < bytes.len() {
+            pieces.push(&bytes[start..end]);
+            start = end;
+        }
+    }
+    pieces.push(&bytes[start..]);
+    pieces
+}
+
+/// Apply `f` to all items, each on a thread of its own if there are several
+fn map<'a, T, R, F>(items: &'a [T], f: F) -> Result<Vec<R>, String>
+    where T: Sync, R: Send, F: Fn(&'a T) -> Result<R, String> + Sync
+{
+    if items.len() == 1 {
+        return Ok(vec![f(&items[0])?]);
+    }
+    let f = &f;
+    thread::scope(|scope| {
+        let handles: Vec<_> = items.iter().map(|item| scope.spawn(move || f(item))).collect();
+        handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+    })
+}
+
+/// Reads a piece of the file word by word and line by line
+struct Cursor<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Cursor<'a> {
+
+    fn is_space(b: u8) -> bool {
+        b == b' ' || b == b'\t' || b == b'\r'
+    }
+
+    /// The next word of the current line, empty at its end
+    fn word(&mut self) -> &'a [u8] {
+        let bytes = self.bytes;
+        while self.at < bytes.len() && Cursor::is_space(bytes[self.at]) {
+            self.at += 1;
+        }
+        let start = self.at;
+        while self.at < bytes.len() && !Cursor::is_space(bytes[self.at]) && bytes[self.at] != b'\n' {
+            self.at += 1;
+        }
+        &bytes[start..self.at]
+    }
+
+    /// The rest of the current line without surrounding whitespace
+    fn rest(&mut self) -> &'a [u8] {
+        let start = self.at;
+        while self.at < self.bytes.len() && self.bytes[self.at] != b'\n' {
+            self.at += 1;
+        }
+        trim(&self.bytes[start..self.at])
+    }
+
+    /// Move to the start of the next line; false if there is none
+    fn next_line(&mut self) -> bool {
+        self.rest();
+        self.at += 1;
+        self.at < self.bytes.len()
+    }
+
+}
+
+fn count(bytes: &[u8]) -> Counts {
+    let mut counts = Counts::default();
+    let mut cursor = Cursor{bytes, at: 0};
+    if bytes.is_empty() {
+        return counts;
+    }
+    loop {
+        counts.lines += 1;
+        match cursor.word() {
+            b"v"  => counts.vertices += 1,
+            b"vt" => counts.textures += 1,
+            b"vn" => counts.normals += 1,
+            _     => (),
+        }
+        if !cursor.next_line() {
+            return counts;
+        }
+    }
+}
+
+/// Parse a piece of the file with the counts `own`, after the ones in `start`, of `total` in all
+fn parse_chunk<'a>(bytes: &'a [u8], start: &Counts, own: &Counts, total: &Counts) -> Result<Chunk<'a>, String> {
+    let mut chunk = Chunk::default();
+    chunk.vertices.reserve(own.vertices);
+    chunk.textures.reserve(own.textures);
+    chunk.normals.reserve(own.normals);
+    if bytes.is_empty() {
+        return Ok(chunk);
+    }
+    let mut cursor = Cursor{bytes, at: 0};
+    let mut n = 0;
+    loop {
+        let line = start.lines + n + 1;
+        let error = |message: &str| format!("line {}: {}", line, message);
+        let keyword = cursor.word();
+        let text = |word: &'a [u8]| str::from_utf8(word).map_err(|_| error("name is not UTF-8"));
+        match keyword {
+            b"v" | b"vt" | b"vn" => {
+                let mut values = [0.; 3];
+                let mut len = 0;
+                while len < 3 {
+                    let word = cursor.word();
+                    if word.is_empty() {
+                        break;
+                    }
+                    values[len] = parse_float(word).ok_or_else(|| error(&format!("invalid number '{}'",
+                                                                                 String::from_utf8_lossy(word))))?;
+                    len += 1;
+                }
+                let (needed, list) = match keyword {
+                    b"v"  => (3, &mut chunk.vertices),
+                    b"vt" => (1, &mut chunk.textures),
+                    _     => (3, &mut chunk.normals),
+                };
+                if len < needed {
+                    return Err(error(&format!("{} needs {} coordinates", String::from_utf8_lossy(keyword), needed)));
+                }
+                list.push(geo::Vec3f::from(&values));
+            },
+            b"f" => {
+                // the first and the latest corner, which make a triangle with each new one
+                let (mut first, mut last) = ([-1; 3], [-1; 3]);
+                let mut corners = 0;
+                loop {
+                    let word = cursor.word();
+                    if word.is_empty() {
+                        break;
+                    }
+                    let mut corner = [-1; 3];
+                    let mut end = 0;
+                    for k in 0..3 {
+                        if end > word.len() {
+                            break;
+                        }
+                        let begin = end;
+                        while end < word.len() && word[end] != b'/' {
+                            end += 1;
+                        }
+                        let index = &word[begin..end];
+                        end += 1;
+                        if index.is_empty() {
+                            continue;
+                        }
+                        let (before, count) = match k {
+                            0 => (start.vertices + chunk.vertices.len(), total.vertices),
+                            1 => (start.textures + chunk.textures.len(), total.textures),
+                            _ => (start.normals + chunk.normals.len(), total.normals),
+                        };
+                        let value = parse_int(index).ok_or_else(|| error(&format!("invalid index '{}'",
+                                                                                  String::from_utf8_lossy(index))))?;
+                        // indices start at 1, negative ones count back from the latest element
+                        let resolved = if value < 0 { before as i64 + value } else { value - 1 };
+                        if resolved < 0 || resolved >= count as i64 {
+                            return Err(error(&format!("index {} out of range", value)));
+                        }
+                        corner[k] = resolved as i32;
+                    }
+                    if corners >= 2 {
+                        chunk.faces.push(geo::Vec3i::new(first[0], last[0], corner[0]));
+                        chunk.face_textures.push(geo::Vec3i::new(first[1], last[1], corner[1]));
+                        chunk.face_normals.push(geo::Vec3i::new(first[2], last[2], corner[2]));
+                    } else if corners == 0 {
+                        first = corner;
+                    }
+                    last = corner;
+                    corners += 1;
+                }
+                if corners < 3 {
+                    return Err(error("face with fewer than 3 vertices"));
+                }
+            },
+            b"o" => {
+                // object names may contain spaces
+                let name = cursor.rest();
+                if name.is_empty() {
+                    return Err(error("o without a name"));
+                }
+                chunk.events.push((chunk.faces.len(), Event::Object(text(name)?)));
+            },
+            b"mtllib" | b"usemtl" | b"g" => {
+                let mut names = Vec::new();
+                loop {
+                    let word = cursor.word();
+                    if word.is_empty() {
+                        break;
+                    }
+                    names.push(text(word)?);
+                }
+                let event = match keyword {
+                    b"mtllib" => Event::MtlLib(names),
+                    b"g"      => Event::Group(names),
+                    // a bare `usemtl` goes back to the default material
+                    _         => Event::UseMtl(names.first().cloned()),
+                };
+                chunk.events.push((chunk.faces.len(), event));
+            },
+            b"s" => {
+                let group = match cursor.word() {
+                    b"off" | b"" => 0,
+                    word         => str::from_utf8(word).ok().and_then(|word| word.parse::<u32>().ok())
+                        .ok_or_else(|| error(&format!("invalid smoothing group '{}'", String::from_utf8_lossy(word))))?,
+                };
+                chunk.events.push((chunk.faces.len(), Event::Smoothing(group)));
+            },
+            // comments, blank lines and everything else
+            _ => (),
+        }
+        n += 1;
+        if !cursor.next_line() {
+            return Ok(chunk);
+        }
+    }
+}
+
+fn join(chunks: Vec<Chunk>, dir: &Path) -> Result<Obj, String> {
+    let mut parts = Chunk::default();
+    let mut materials = Vec::new();
+    let mut warnings = Vec::new();
+    let mut face_materials = Vec::new();
+    let mut face_smoothing = Vec::new();
+    let mut object_starts = Vec::new();
+    let mut group_starts = Vec::new();
+    let (mut material, mut smoothing) = (None, 0);
+    for chunk in chunks {
+        let base = parts.faces.len();
+        let mut filled = 0;
+        for (at, event) in chunk.events {
+            face_materials.extend((filled..at).map(|_| material));
+            face_smoothing.extend((filled..at).map(|_| smoothing));
+            filled = at;
+            match event {
+                // meshes often come without their material library, which leaves the faces with
+                // the default material; a library that is there must be valid
+                Event::MtlLib(names) => for name in names {
+                    match mtl::load(&dir.join(name)) {
+                        Ok(library) => materials.extend(library),
+                        Err(ref err) if err.kind() == io::ErrorKind::NotFound => {
+                            warnings.push(format!("material library '{}' not found", name));
+                        },
+                        Err(err)    => return Err(format!("mtllib {}: {}", name, err)),
+                    }
+                },
+                Event::UseMtl(name)     => {
+                    material = name.and_then(|name| materials.iter().position(|m: &mtl::Material| m.name == name));
+                },
+                Event::Object(name)     => object_starts.push((name, base + at)),
+                Event::Group(names)     => group_starts.push((names, base + at)),
+                Event::Smoothing(group) => smoothing = group,
+            }
+        }
+        face_materials.extend((filled..chunk.faces.len()).map(|_| material));
+        face_smoothing.extend((filled..chunk.faces.len()).map(|_| smoothing));
+        append(&mut parts.vertices, chunk.vertices);
+        append(&mut parts.textures, chunk.textures);
+        append(&mut parts.normals, chunk.normals);
+        append(&mut parts.faces, chunk.faces);
+        append(&mut parts.face_textures, chunk.face_textures);
+        append(&mut parts.face_normals, chunk.face_normals);
+    }
+    let nfaces = parts.faces.len();
+    // every object and group runs until the next line of its kind
+    let mut objects = Vec::new();
+    for (k, &(name, start)) in object_starts.iter().enumerate() {
+        let end = object_starts.get(k + 1).map_or(nfaces, |next| next.1);
+        objects.push(Group{name: String::from(name), faces: start..end});
+    }
+    let mut groups = Vec::new();
+    for (k, &(ref names, start)) in group_starts.iter().enumerate() {
+        let end = group_starts.get(k + 1).map_or(nfaces, |next| next.1);
+        groups.extend(names.iter().map(|name| Group{name: String::from(*name), faces: start..end}));
+    }
+    objects.retain(|object| !object.faces.is_empty());
+    groups.retain(|group| !group.faces.is_empty());
+
+    let mut obj = Obj::new(parts.vertices, parts.faces);
+    obj.textures = parts.textures;
+    obj.normals = parts.normals;
+    obj.face_textures = parts.face_textures;
+    obj.face_normals = parts.face_normals;
+    obj.materials = materials;
+    obj.face_materials = face_materials;
+    obj.face_smoothing = face_smoothing;
+    obj.objects = objects;
+    obj.groups = groups;
+    obj.warnings = warnings;
+    Ok(obj)
+}
+
+/// Append `part` to `all`, without copying if `all` is empty
+fn append<T>(all: &mut Vec<T>, mut part: Vec<T>) {
+    if all.is_empty() {
+        *all = part;
+    } else {
+        all.append(&mut part);
+    }
+}
+
+fn trim(bytes: &[u8]) -> &[u8] {
+    let is_space = |b: &u8| *b == b' ' || *b == b'\t' || *b == b'\r';
+    let start = bytes.iter().position(|b| !is_space(b)).unwrap_or(bytes.len());
+    let end = bytes.iter().rposition(|b| !is_space(b)).map_or(start, |end| end + 1);
+    &bytes[start..end]
+}
+
+fn parse_int(word: &[u8]) -> Option<i64> {
+    let (negative, digits) = match word.first() {
+        Some(b'-') => (true, &word[1..]),
+        Some(b'+') => (false, &word[1..]),
+        _          => (false, word),
+    };
+    if digits.is_empty() || digits.len() > 18 {
+        return None;
+    }
+    let mut value = 0i64;
+    for &d in digits {
+        if !d.is_ascii_digit() {
+            return None;
+        }
+        value = value*10 + (d - b'0') as i64;
+    }
+    Some(if negative { -value } else { value })
+}
+
+/// Parse a number like `str::parse::<f64>` does, but only finite ones
+fn parse_float(word: &[u8]) -> Option<f64> {
+    fast_float(word).or_else(|| str::from_utf8(word).ok()?.parse().ok()).filter(|value| value.is_finite())
+}
+
+/// Powers of ten that are exact doubles
+const POWERS_OF_TEN: [f64; 23] = [1e0, 1e1, 1e2, 1e3, 1e4, 1e5, 1e6, 1e7, 1e8, 1e9, 1e10, 1e11, 1e12, 1e13,
+                                  1e14, 1e15, 1e16, 1e17, 1e18, 1e19, 1e20, 1e21, 1e22];
+
+/// Decimal numbers with at most 15 digits and a small exponent, None for all others. The digits
+/// and the power of ten are then exact doubles, so a single, correctly rounded multiplication or
+/// division gives the same result as the standard library.
+fn fast_float(word: &[u8]) -> Option<f64> {
+    let negative = word.first() == Some(&b'-');
+    let mut i = if negative || word.first() == Some(&b'+') { 1 } else { 0 };
+    let (mut mantissa, mut digits, mut exponent) = (0u64, 0, 0i32);
+    let mut seen_point = false;
+    while i < word.len() {
+        match word[i] {
+            d @ b'0'..=b'9' => {
+                if digits == 15 {
+                    return None;
+                }
+                mantissa = mantissa*10 + (d - b'0') as u64;
+                digits += 1;
+                if seen_point {
+                    exponent -= 1;
+                }
+            },
+            b'.' if !seen_point => seen_point = true,
+            _ => break,
+        }
+        i += 1;
+    }
+    if digits == 0 {
+        return None;
+    }
+    if i < word.len() {
+        if word[i] != b'e' && word[i] != b'E' {
+            return None;
+        }
+        exponent += parse_int(&word[i + 1..]).filter(|e| e.abs() <= 100)? as i32;
+    }
+    let value = match exponent {
+        0..=22   => mantissa as f64*POWERS_OF_TEN[exponent as usize],
+        -22..=-1 => mantissa as f64/POWERS_OF_TEN[-exponent as usize],
+        _        => return None,
+    };
+    Some(if negative { -value } else { value })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::time::Instant;
+
+    /// The vertex and face data of an OBJ read the way `Obj::from_file` used to, line by line into
+    /// owned strings
+    fn by_lines(text: &str) -> (Vec<geo::Vec3f>, Vec<geo::Vec3f>, Vec<geo::Vec3f>, Vec<[[i32; 3]; 3]>) {
+        let (mut vertices, mut textures, mut normals, mut faces) = (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+        for line in text.lines().map(String::from) {
+            let vector = || {
+                let v: Vec<f64> = line[2..].split_whitespace().map(|x| x.parse().unwrap()).collect();
+                geo::Vec3f::new(v[0], v[1], v[2])
+            };
+            match line.get(..2) {
+                Some("v ") => vertices.push(vector()),
+                Some("vt") => textures.push(vector()),
+                Some("vn") => normals.push(vector()),
+                Some("f ") => {
+                    let terms: Vec<&str> = line[2..].split_whitespace().collect();
+                    let mut face = [[-1; 3]; 3];
+                    for i in 0..3 {
+                        for (j, index) in terms[i].split("/").enumerate() {
+                            face[j][i] = index.parse::<i32>().unwrap() - 1;
+                        }
+                    }
+                    faces.push(face);
+                },
+                _ => continue,
+            }
+        }
+        (vertices, textures, normals, faces)
+    }
+
+    #[test]
+    fn floats() {
+        for word in ["0", "-0", "1.5", "-0.000581696", "0.923", "3.", ".25", "1e3", "-2.5E-3", "12345678901234567",
+                     "0.1234567890123456789", "1e300", "4.9e-324", "+7", "1e-22", "123456789012345e22"].iter() {
+            let expected: f64 = word.parse().unwrap();
+            assert_eq!(parse_float(word.as_bytes()).map(f64::to_bits), Some(expected.to_bits()), "{}", word);
+        }
+        for word in ["", "-", ".", "1.2.3", "1e", "x", "1f"].iter() {
+            assert_eq!(parse_float(word.as_bytes()), None, "{}", word);
+        }
+    }
+
+    #[test]
+    fn same_as_line_reader() {
+        let text = fs::read_to_string("obj/diablo3_pose.obj").unwrap();
+        let (vertices, textures, normals, faces) = by_lines(&text);
+        for &threads in [1, 3, 8].iter() {
+            let obj = parse(text.as_bytes(), Path::new("obj"), threads).unwrap();
+            assert_eq!((&obj.vertices, &obj.textures, &obj.normals), (&vertices, &textures, &normals));
+            for (i, face) in faces.iter().enumerate() {
+                assert_eq!([obj.faces[i], obj.face_textures[i], obj.face_normals[i]],
+                           [geo::Vec3i::from(&face[0]), geo::Vec3i::from(&face[1]), geo::Vec3i::from(&face[2])]);
+            }
+            assert_eq!(obj.groups[0].name, "objDiablo3");
+            assert_eq!(obj.face_smoothing[0], 1);
+        }
+    }
+
+    #[test]
+    fn polygons_and_relative_indices() {
+        let text = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvt 1 1\n\
+                    o quad\nf -4/1 -3/2 -2/3 -1/3\nv 2 0 0\ns 2\nf 2 5 3\n";
+        for &threads in [1, 2, 5, 200].iter() {
+            let obj = parse(text.as_bytes(), Path::new(""), threads).unwrap();
+            assert_eq!(obj.faces, vec![geo::Vec3i::new(0, 1, 2), geo::Vec3i::new(0, 2, 3), geo::Vec3i::new(1, 4, 2)]);
+            assert_eq!(obj.face_textures[1], geo::Vec3i::new(0, 2, 2));
+            assert_eq!(obj.face_textures[2], geo::Vec3i::new(-1, -1, -1));
+            assert_eq!(obj.textures[1], geo::Vec3f::new(1., 0., 0.));
+            assert_eq!(obj.objects[0].faces, 0..3);
+            assert_eq!(obj.face_smoothing, vec![0, 0, 2]);
+        }
+        // more threads than bytes
+        assert_eq!(parse(b"v 0 0 0\n", Path::new(""), 16).unwrap().vertices.len(), 1);
+        let obj = parse(b"mtllib missing.mtl\nusemtl red\nv 0 0 0\nf 1 1 1\n", Path::new(""), 1).unwrap();
+        assert_eq!((obj.materials.len(), obj.face_materials.clone()), (0, vec![None]));
+        assert_eq!(obj.warnings, vec!["material library 'missing.mtl' not found"]);
+        let dir = std::env::temp_dir();
+        std::fs::write(dir.join("rustyrenderer_broken.mtl"), "newmtl red\nKd 1 x 0\n").unwrap();
+        let err = parse(b"mtllib rustyrenderer_broken.mtl\n", &dir, 1).err().unwrap();
+        assert!(err.starts_with("mtllib rustyrenderer_broken.mtl: "), "{}", err);
+        assert!(err.ends_with("line 2: invalid number 'x'"), "{}", err);
+        let error = |text: &str| parse(text.as_bytes(), Path::new(""), 1).err().unwrap();
+        assert_eq!(error("v 0 0 0\nv 1 0 0\nf 1 2\n"), "line 3: face with fewer than 3 vertices");
+        assert_eq!(error("v 0 0 0\nf 1 1 -2\n"), "line 2: index -2 out of range");
+        assert_eq!(error("v 0 0 0\nf 1 1 4\n"), "line 2: index 4 out of range");
+        assert_eq!(error("v 0 0 0\nvn 0 x 1\n"), "line 2: invalid number 'x'");
+        assert_eq!(error("v nan inf 0\n"), "line 1: invalid number 'nan'");
+        assert_eq!(error("v 0 1e400 0\n"), "line 1: invalid number '1e400'");
+        assert_eq!(error("s 4294967296\n"), "line 1: invalid smoothing group '4294967296'");
+        let obj = parse(b"s 4294967295\nv 0 0 0\nf 1 1 1\n", Path::new(""), 1).unwrap();
+        assert_eq!(obj.face_smoothing, vec![u32::max_value()]);
+        assert_eq!(error("# two coordinates\nv 0 0\n"), "line 2: v needs 3 coordinates");
+    }
+
+    /// A grid of `n` by `n` quads with texture coordinates and normals, all with three components
+    /// as the line reader needs them
+    fn grid(n: usize) -> String {
+        let mut text = String::new();
+        for y in 0..n + 1 {
+            for x in 0..n + 1 {
+                let (u, v) = (x as f64/n as f64, y as f64/n as f64);
+                text += &format!("v {:.6} {:.6} {:.6}\nvt {:.6} {:.6} 0\nvn 0 0 1\n", u*2. - 1., v*2. - 1., (u*v).sin()*0.1, u, v);
+            }
+        }
+        for y in 0..n {
+            for x in 0..n {
+                let corner = |dx: usize, dy: usize| (y + dy)*(n + 1) + x + dx + 1;
+                let (a, b, c, d) = (corner(0, 0), corner(1, 0), corner(1, 1), corner(0, 1));
+                text += &format!("f {0}/{0}/{0} {1}/{1}/{1} {2}/{2}/{2}\nf {0}/{0}/{0} {2}/{2}/{2} {3}/{3}/{3}\n",
+                                 a, b, c, d);
+            }
+        }
+        text
+    }
+
+    /// Compare with the line reader on two million triangles: cargo test --release -- --ignored
+    #[test]
+    #[ignore]
+    fn benchmark() {
+        let text = grid(1000);
+        let start = Instant::now();
+        let expected = by_lines(&text);
+        println!("line reader: {:?}", start.elapsed());
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        for &threads in [1, threads].iter() {
+            let start = Instant::now();
+            let obj = parse(text.as_bytes(), Path::new(""), threads).unwrap();
+            println!("{} thread(s): {:?}", threads, start.elapsed());
+            assert_eq!((obj.vertices.len(), obj.faces.len()), (expected.0.len(), expected.3.len()));
+        }
+    }
+
+}
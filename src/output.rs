@@ -0,0 +1,509 @@
+use std::f64::INFINITY;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+use color_quant::NeuQuant;
+use gif;
+use gif::SetParameter;
+use image;
+use aov::Aov;
+use framebuffer::{to_u8, Attachment, Buffer};
+
+/// File formats for the color image that are written without the image crate. They are chosen
+/// explicitly, whatever the extension of the output path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    /// Plain-text portable pixmap (P3), 8 bits per channel
+    PpmAscii,
+    /// Binary portable pixmap (P6), 8 bits per channel
+    PpmBinary,
+    /// Portable float map with RGB floats, little-endian and bottom row first
+    Pfm,
+    /// Radiance RGBE with run-length encoded scanlines, top row first
+    Hdr,
+}
+
+impl ImageFormat {
+
+    /// Whether the format keeps values outside [0, 1], which needs a float framebuffer
+    pub fn is_float(&self) -> bool {
+        match self {
+            ImageFormat::Pfm | ImageFormat::Hdr => true,
+            _                                   => false,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::PpmAscii | ImageFormat::PpmBinary => "ppm",
+            ImageFormat::Pfm                               => "pfm",
+            ImageFormat::Hdr                               => "hdr",
+        }
+    }
+
+}
+
+impl FromStr for ImageFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ImageFormat, String> {
+        match s {
+            "ppm"       => Ok(ImageFormat::PpmBinary),
+            "ppm-ascii" => Ok(ImageFormat::PpmAscii),
+            "pfm"       => Ok(ImageFormat::Pfm),
+            "hdr"       => Ok(ImageFormat::Hdr),
+            _           => Err(format!("unknown image format '{}'", s)),
+        }
+    }
+}
+
+/// File formats for the depth buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DepthFormat {
+    /// 16-bit grayscale PNG, nearest drawn depth white and farthest black; empty pixels are black
+    Png16,
+    /// Little-endian f32 values, top row first, without a header
+    RawF32,
+    /// Portable float map, little-endian and bottom row first as the format prescribes
+    Pfm,
+}
+
+impl DepthFormat {
+
+    /// Format matching the extension of `path`: png, raw, f32 or pfm
+    pub fn from_path(path: &Path) -> Option<DepthFormat> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "png"         => Some(DepthFormat::Png16),
+            "raw" | "f32" => Some(DepthFormat::RawF32),
+            "pfm"         => Some(DepthFormat::Pfm),
+            _             => None,
+        }
+    }
+
+}
+
+/// File formats for output variables other than depth
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AovFormat {
+    /// 8-bit RGB PNG with values encoded by `Aov::encode`
+    Png,
+    /// Portable float map with the raw values
+    Pfm,
+}
+
+impl AovFormat {
+
+    pub fn from_path(path: &Path) -> Option<AovFormat> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "png" => Some(AovFormat::Png),
+            "pfm" => Some(AovFormat::Pfm),
+            _     => None,
+        }
+    }
+
+}
+
+/// Save a depth buffer, where larger values are closer and -inf marks empty pixels. The float
+/// formats keep the values as they are.
+pub fn save_depth(depth: &Buffer<f64>, path: &Path, format: DepthFormat) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    match format {
+        DepthFormat::Png16  => write_depth_png16(depth, &mut file),
+        DepthFormat::RawF32 => write_raw_f32(depth, &mut file),
+        DepthFormat::Pfm    => write_pfm(depth, &mut file),
+    }?;
+    file.flush()
+}
+
+/// Save the values of an output variable in the format given by the extension of `path`
+pub fn save_aov(aov: Aov, values: &Attachment, path: &Path) -> io::Result<()> {
+    let format = AovFormat::from_path(path).ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, "output variables must be saved as .png or .pfm")
+    })?;
+    let (width, height) = values.dimensions();
+    match format {
+        AovFormat::Png => {
+            let img = image::RgbImage::from_fn(width, height, |x, y| {
+                image::Rgb(aov.encode(&values.get(x, height - 1 - y).unwrap()))
+            });
+            img.save(path)
+        },
+        AovFormat::Pfm => {
+            let mut file = BufWriter::new(File::create(path)?);
+            write_pfm_color(values, &mut file)?;
+            file.flush()
+        },
+    }
+}
+
+/// Save a color buffer in `format`. The 8-bit formats clamp the colors, the float ones keep them.
+pub fn save_image(color: &Attachment, path: &Path, format: ImageFormat) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    write_image(color, &mut file, format)?;
+    file.flush()
+}
+
+pub fn write_image<W: Write>(color: &Attachment, w: &mut W, format: ImageFormat) -> io::Result<()> {
+    match format {
+        ImageFormat::PpmAscii  => write_ppm_ascii(color, w),
+        ImageFormat::PpmBinary => write_ppm_binary(color, w),
+        ImageFormat::Pfm       => write_pfm_color(color, w),
+        ImageFormat::Hdr       => write_hdr(color, w),
+    }
+}
+
+/// Animated GIF that loops forever, written frame by frame. Each frame is quantized to its own
+/// palette of 256 colors.
+pub struct GifEncoder<W: Write> {
+    encoder: gif::Encoder<W>,
+    /// Time each frame is shown, in hundredths of a second
+    delay: u16,
+}
+
+impl<W: Write> GifEncoder<W> {
+
+    /// Start an animation of `width` by `height` frames, shown `fps` frames per second. The file is
+    /// complete once the encoder is dropped.
+    pub fn new(w: W, width: u32, height: u32, fps: f64) -> io::Result<GifEncoder<W>> {
+        if width > 0xffff || height > 0xffff {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "GIF images are at most 65535 pixels wide and high"));
+        }
+        let mut encoder = gif::Encoder::new(w, width as u16, height as u16, &[])?;
+        encoder.set(gif::Repeat::Infinite)?;
+        let delay = (100./fps).round().max(1.).min(65535.) as u16;
+        Ok(GifEncoder{encoder, delay})
+    }
+
+    /// Add a frame. The palette is learned from every tenth pixel; an 800 by 800 frame takes about
+    /// a tenth of a second in a release build.
+    pub fn add_frame(&mut self, color: &Attachment) -> io::Result<()> {
+        let (width, height) = color.dimensions();
+        let mut pixels = Vec::with_capacity((4*width*height) as usize);
+        for y in (0..height).rev() {
+            for x in 0..width {
+                let c = color.get(x, y).unwrap();
+                pixels.extend(&[to_u8(c[0]), to_u8(c[1]), to_u8(c[2]), 255]);
+            }
+        }
+        // the encoder's own quantizer samples every pixel, which is several times slower
+        let quantizer = NeuQuant::new(10, 256, &pixels);
+        let indices: Vec<u8> = pixels.chunks(4).map(|p| quantizer.index_of(p) as u8).collect();
+        let mut frame = gif::Frame::from_palette_pixels(width as u16, height as u16, &indices,
+                                                        &quantizer.color_map_rgb(), None);
+        frame.delay = self.delay;
+        self.encoder.write_frame(&frame)
+    }
+
+}
+
+/// Depth values scaled to the full 16-bit range, top row first
+fn normalize_depth(depth: &Buffer<f64>) -> Vec<u16> {
+    let (width, height) = depth.dimensions();
+    let finite = depth.values().iter().cloned().filter(|z| z.is_finite());
+    let (near, far) = finite.fold((-INFINITY, INFINITY), |(near, far), z| (near.max(z), far.min(z)));
+    let range = if near > far { near - far } else { 1. };
+    let mut values = Vec::with_capacity((width*height) as usize);
+    for y in (0..height).rev() {
+        for x in 0..width {
+            let z = depth.get(x, y).unwrap();
+            values.push(if z.is_finite() { ((z - far)/range*65535.).round() as u16 } else { 0 });
+        }
+    }
+    values
+}
+
+fn write_depth_png16<W: Write>(depth: &Buffer<f64>, w: &mut W) -> io::Result<()> {
+    let (width, height) = depth.dimensions();
+    // PNG stores 16-bit samples big-endian
+    let mut bytes = Vec::with_capacity((2*width*height) as usize);
+    for value in normalize_depth(depth) {
+        bytes.push((value >> 8) as u8);
+        bytes.push(value as u8);
+    }
+    image::png::PNGEncoder::new(w).encode(&bytes, width, height, image::ColorType::Gray(16))
+}
+
+/// Write `value` as a little-endian f32
+pub fn write_f32<W: Write>(value: f64, w: &mut W) -> io::Result<()> {
+    let bits = (value as f32).to_bits();
+    w.write_all(&[bits as u8, (bits >> 8) as u8, (bits >> 16) as u8, (bits >> 24) as u8])
+}
+
+fn write_raw_f32<W: Write>(depth: &Buffer<f64>, w: &mut W) -> io::Result<()> {
+    let (width, height) = depth.dimensions();
+    for y in (0..height).rev() {
+        for x in 0..width {
+            write_f32(depth.get(x, y).unwrap(), w)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_pfm<W: Write>(depth: &Buffer<f64>, w: &mut W) -> io::Result<()> {
+    let (width, height) = depth.dimensions();
+    // a negative scale marks little-endian data
+    write!(w, "Pf\n{} {}\n-1.0\n", width, height)?;
+    for &z in depth.values() {
+        write_f32(z, w)?;
+    }
+    Ok(())
+}
+
+fn write_pfm_color<W: Write>(values: &Attachment, w: &mut W) -> io::Result<()> {
+    let (width, height) = values.dimensions();
+    write!(w, "PF\n{} {}\n-1.0\n", width, height)?;
+    for y in 0..height {
+        for x in 0..width {
+            let color = values.get(x, y).unwrap();
+            for c in 0..3 {
+                write_f32(color[c], w)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_ppm_ascii<W: Write>(color: &Attachment, w: &mut W) -> io::Result<()> {
+    let (width, height) = color.dimensions();
+    write!(w, "P3\n{} {}\n255\n", width, height)?;
+    for y in (0..height).rev() {
+        // one row per line keeps lines short enough for most readers
+        let row: Vec<String> = (0..width).map(|x| {
+            let c = color.get(x, y).unwrap();
+            format!("{} {} {}", to_u8(c[0]), to_u8(c[1]), to_u8(c[2]))
+        }).collect();
+        writeln!(w, "{}", row.join("  "))?;
+    }
+    Ok(())
+}
+
+fn write_ppm_binary<W: Write>(color: &Attachment, w: &mut W) -> io::Result<()> {
+    let (width, height) = color.dimensions();
+    write!(w, "P6\n{} {}\n255\n", width, height)?;
+    let mut bytes = Vec::with_capacity((3*width*height) as usize);
+    for y in (0..height).rev() {
+        for x in 0..width {
+            let c = color.get(x, y).unwrap();
+            bytes.extend(&[to_u8(c[0]), to_u8(c[1]), to_u8(c[2])]);
+        }
+    }
+    w.write_all(&bytes)
+}
+
+/// Shared-exponent encoding of a color, with negative components clamped to zero
+fn rgbe(color: &[f64]) -> [u8; 4] {
+    let (r, g, b) = (color[0].max(0.), color[1].max(0.), color[2].max(0.));
+    let max = r.max(g).max(b);
+    if !(max >= 1e-32) {
+        return [0; 4];
+    }
+    // max = mantissa*2^exponent with the mantissa in [0.5, 1)
+    let mut exponent = max.log2().floor() as i32 + 1;
+    if max/2f64.powi(exponent) >= 1. {
+        exponent += 1;
+    }
+    let scale = 256./2f64.powi(exponent);
+    [(r*scale) as u8, (g*scale) as u8, (b*scale) as u8, (exponent + 128).max(0).min(255) as u8]
+}
+
+fn write_hdr<W: Write>(color: &Attachment, w: &mut W) -> io::Result<()> {
+    let (width, height) = color.dimensions();
+    write!(w, "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n", height, width)?;
+    for y in (0..height).rev() {
+        let row: Vec<[u8; 4]> = (0..width).map(|x| rgbe(&color.get(x, y).unwrap())).collect();
+        // run-length encoding is only defined for these widths
+        if width < 8 || width > 0x7fff {
+            for pixel in row.iter() {
+                w.write_all(pixel)?;
+            }
+            continue;
+        }
+        w.write_all(&[2, 2, (width >> 8) as u8, width as u8])?;
+        for c in 0..4 {
+            let values: Vec<u8> = row.iter().map(|pixel| pixel[c]).collect();
+            write_hdr_rle(&values, w)?;
+        }
+    }
+    Ok(())
+}
+
+/// One component of a scanline as runs of up to 127 equal values and literals of up to 128
+fn write_hdr_rle<W: Write>(values: &[u8], w: &mut W) -> io::Result<()> {
+    let run_at = |i: usize| values[i..].iter().take(127).take_while(|&&v| v == values[i]).count();
+    let mut i = 0;
+    while i < values.len() {
+        let run = run_at(i);
+        // shorter runs are cheaper as part of a literal
+        if run >= 3 {
+            w.write_all(&[128 + run as u8, values[i]])?;
+            i += run;
+            continue;
+        }
+        let mut count = run;
+        while i + count < values.len() && count < 128 && run_at(i + count) < 3 {
+            count += 1;
+        }
+        w.write_all(&[count as u8])?;
+        w.write_all(&values[i..i + count])?;
+        i += count;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use framebuffer::Format;
+
+    fn depth() -> Buffer<f64> {
+        let mut depth = Buffer::new(2, 2, -INFINITY);
+        depth.set(0, 0, 0.5);
+        depth.set(1, 0, -0.5);
+        depth.set(0, 1, 0.);
+        depth
+    }
+
+    #[test]
+    fn depth_formats() {
+        assert_eq!(normalize_depth(&depth()), vec![32768, 0, 65535, 0]);
+
+        let mut raw = Vec::new();
+        write_raw_f32(&depth(), &mut raw).unwrap();
+        assert_eq!(raw.len(), 16);
+        assert_eq!(&raw[8..12], &[0, 0, 0, 0x3f]);
+        assert_eq!(&raw[4..8], &[0, 0, 0x80, 0xff]);
+
+        let mut pfm = Vec::new();
+        write_pfm(&depth(), &mut pfm).unwrap();
+        assert!(pfm.starts_with(b"Pf\n2 2\n-1.0\n"));
+        assert_eq!(&pfm[12..16], &[0, 0, 0, 0x3f]);
+
+        assert_eq!(DepthFormat::from_path(Path::new("out/depth.PFM")), Some(DepthFormat::Pfm));
+        assert_eq!(DepthFormat::from_path(Path::new("depth.exr")), None);
+    }
+
+    /// Decode the scanlines of a run-length encoded Radiance file, top row first
+    fn read_hdr(bytes: &[u8], width: usize, height: usize) -> Vec<[u8; 4]> {
+        let mut pixels = vec![[0; 4]; width*height];
+        let mut i = 0;
+        for y in 0..height {
+            assert_eq!(&bytes[i..i + 4], &[2, 2, (width >> 8) as u8, width as u8]);
+            i += 4;
+            for c in 0..4 {
+                let mut x = 0;
+                while x < width {
+                    let count = bytes[i] as usize;
+                    if count > 128 {
+                        for _ in 0..count - 128 {
+                            pixels[y*width + x][c] = bytes[i + 1];
+                            x += 1;
+                        }
+                        i += 2;
+                    } else {
+                        for k in 0..count {
+                            pixels[y*width + x][c] = bytes[i + 1 + k];
+                            x += 1;
+                        }
+                        i += 1 + count;
+                    }
+                }
+            }
+        }
+        assert_eq!(i, bytes.len());
+        pixels
+    }
+
+    #[test]
+    fn image_formats() {
+        let mut color = Attachment::new(Format::Rgb8, 2, 2);
+        color.set(0, 0, &[1., 0., 0., 1.]);
+        color.set(1, 1, &[0., 0.5, 2., 1.]);
+        let mut ppm = Vec::new();
+        write_image(&color, &mut ppm, ImageFormat::PpmAscii).unwrap();
+        assert_eq!(String::from_utf8(ppm).unwrap(), "P3\n2 2\n255\n0 0 0  0 128 255\n255 0 0  0 0 0\n");
+        let mut ppm = Vec::new();
+        write_image(&color, &mut ppm, ImageFormat::PpmBinary).unwrap();
+        assert_eq!(&ppm[..], &b"P6\n2 2\n255\n\0\0\0\0\x80\xff\xff\0\0\0\0\0"[..]);
+
+        // values above one survive in a float buffer
+        let mut color = Attachment::new(Format::Rgb32F, 10, 2);
+        for x in 0..10 {
+            color.set(x, 0, &[4., 2., 1., 1.]);
+            color.set(x, 1, &[x as f64/10., 0., 1., 1.]);
+        }
+        let mut pfm = Vec::new();
+        write_image(&color, &mut pfm, ImageFormat::Pfm).unwrap();
+        assert!(pfm.starts_with(b"PF\n10 2\n-1.0\n"));
+        assert_eq!(&pfm[13..17], &[0, 0, 0x80, 0x40]);
+
+        assert_eq!(rgbe(&[1., 0.5, 0.25]), [128, 64, 32, 129]);
+        assert_eq!(rgbe(&[4., 2., 1.]), [128, 64, 32, 131]);
+        assert_eq!(rgbe(&[0., -1., 0.]), [0; 4]);
+        let mut hdr = Vec::new();
+        write_image(&color, &mut hdr, ImageFormat::Hdr).unwrap();
+        let header = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 2 +X 10\n";
+        assert!(hdr.starts_with(header));
+        let pixels = read_hdr(&hdr[header.len()..], 10, 2);
+        assert_eq!(pixels[3], rgbe(&[0.3, 0., 1.]));
+        assert_eq!(pixels[19], [128, 64, 32, 131]);
+
+        assert_eq!("ppm-ascii".parse(), Ok(ImageFormat::PpmAscii));
+        assert!("exr".parse::<ImageFormat>().is_err());
+    }
+
+    #[test]
+    fn animated_gif() {
+        let mut color = Attachment::new(Format::Rgb8, 3, 2);
+        let mut bytes = Vec::new();
+        {
+            let mut encoder = GifEncoder::new(&mut bytes, 3, 2, 25.).unwrap();
+            for i in 0..3 {
+                color.set(i, 0, &[1., 0., 0., 1.]);
+                encoder.add_frame(&color).unwrap();
+            }
+        }
+        assert!(bytes.starts_with(b"GIF89a\x03\x00\x02\x00"));
+        assert_eq!(bytes.last(), Some(&0x3b));
+        let mut decoder = gif::Decoder::new(&bytes[..]).read_info().unwrap();
+        let mut frames = 0;
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            assert_eq!((frame.width, frame.height, frame.delay), (3, 2, 4));
+            frames += 1;
+        }
+        assert_eq!(frames, 3);
+        // the application extension that makes the animation loop
+        assert!(bytes.windows(11).any(|w| w == b"NETSCAPE2.0"));
+    }
+
+    #[test]
+    fn gif_frame_colors() {
+        // a full-size frame of smooth gradients, which the palette has to approximate
+        let (width, height) = (800, 800);
+        let mut color = Attachment::new(Format::Rgb8, width, height);
+        for y in 0..height {
+            for x in 0..width {
+                color.set(x, y, &[x as f64/width as f64, y as f64/height as f64, 0.5, 1.]);
+            }
+        }
+        let mut bytes = Vec::new();
+        GifEncoder::new(&mut bytes, width, height, 25.).unwrap().add_frame(&color).unwrap();
+        let mut decoder = gif::Decoder::new(&bytes[..]);
+        decoder.set(gif::ColorOutput::RGBA);
+        let mut decoder = decoder.read_info().unwrap();
+        let frame = decoder.read_next_frame().unwrap().unwrap();
+        for &(x, y) in [(0, 0), (400, 100), (799, 799), (100, 650)].iter() {
+            let expected = color.get(x, y).unwrap();
+            // rows are stored top first
+            let at = 4*((height - 1 - y)*width + x) as usize;
+            for c in 0..3 {
+                let error = (frame.buffer[at + c] as f64 - to_u8(expected[c]) as f64).abs();
+                assert!(error <= 24., "pixel {},{} channel {}: {}", x, y, c, error);
+            }
+        }
+    }
+
+}
@@ -0,0 +1,367 @@
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::Path;
+use std::str;
+use geo;
+use obj;
+use obj::Obj;
+
+/// Scalar types of PLY properties
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Property {
+    Scalar(String, Scalar),
+    /// A list with the given types of the length and of the items
+    List(String, Scalar, Scalar),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+/// The data after the header, read value by value
+enum Body<'a> {
+    Ascii(str::SplitWhitespace<'a>),
+    Binary(&'a [u8], bool),
+}
+
+impl Scalar {
+
+    fn parse(name: &str) -> Option<Scalar> {
+        match name {
+            "char" | "int8"      => Some(Scalar::I8),
+            "uchar" | "uint8"    => Some(Scalar::U8),
+            "short" | "int16"    => Some(Scalar::I16),
+            "ushort" | "uint16"  => Some(Scalar::U16),
+            "int" | "int32"      => Some(Scalar::I32),
+            "uint" | "uint32"    => Some(Scalar::U32),
+            "float" | "float32"  => Some(Scalar::F32),
+            "double" | "float64" => Some(Scalar::F64),
+            _                    => None,
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8   => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+
+    /// Value of `bytes`, which hold exactly one scalar of this type
+    fn decode(self, bytes: &[u8], big_endian: bool) -> f64 {
+        let mut bits = 0u64;
+        for i in 0..bytes.len() {
+            let byte = if big_endian { bytes[i] } else { bytes[bytes.len() - 1 - i] };
+            bits = bits << 8 | byte as u64;
+        }
+        match self {
+            Scalar::I8  => bits as u8 as i8 as f64,
+            Scalar::U8  => bits as u8 as f64,
+            Scalar::I16 => bits as u16 as i16 as f64,
+            Scalar::U16 => bits as u16 as f64,
+            Scalar::I32 => bits as u32 as i32 as f64,
+            Scalar::U32 => bits as u32 as f64,
+            Scalar::F32 => f32::from_bits(bits as u32) as f64,
+            Scalar::F64 => f64::from_bits(bits),
+        }
+    }
+
+    /// Factor that maps color channels of this type to [0, 1]
+    fn color_scale(self) -> f64 {
+        match self {
+            Scalar::F32 | Scalar::F64 => 1.,
+            Scalar::U16 | Scalar::I16 => 1./65535.,
+            _                         => 1./255.,
+        }
+    }
+
+}
+
+impl<'a> Body<'a> {
+
+    fn read(&mut self, scalar: Scalar) -> Result<f64, String> {
+        match self {
+            Body::Ascii(words) => {
+                let word = words.next().ok_or_else(|| String::from("unexpected end of data"))?;
+                word.parse().map_err(|_| format!("invalid number '{}'", word))
+            },
+            Body::Binary(bytes, big_endian) => {
+                let size = scalar.size();
+                if bytes.len() < size {
+                    return Err(String::from("unexpected end of data"));
+                }
+                let data: &'a [u8] = *bytes;
+                *bytes = &data[size..];
+                Ok(scalar.decode(&data[..size], *big_endian))
+            },
+        }
+    }
+
+    /// Values of every property of one element, with lists flattened after their length
+    fn read_element(&mut self, element: &Element) -> Result<Vec<Vec<f64>>, String> {
+        let mut values = Vec::with_capacity(element.properties.len());
+        for property in element.properties.iter() {
+            match property {
+                Property::Scalar(_, scalar) => values.push(vec![self.read(*scalar)?]),
+                Property::List(_, count, item) => {
+                    let count = self.read(*count)?;
+                    if count < 0. || count.fract() != 0. {
+                        return Err(format!("invalid list length {}", count));
+                    }
+                    // the length comes from the file, so it does not size the list up front
+                    let mut list = Vec::new();
+                    for _ in 0..count as usize {
+                        list.push(self.read(*item)?);
+                    }
+                    values.push(list);
+                },
+            }
+        }
+        Ok(values)
+    }
+
+}
+
+impl Property {
+
+    fn name(&self) -> &str {
+        match self {
+            Property::Scalar(name, _) | Property::List(name, _, _) => name,
+        }
+    }
+
+}
+
+impl Element {
+
+    /// Position of the first scalar property with one of `names`, and its type
+    fn find(&self, names: &[&str]) -> Option<(usize, Scalar)> {
+        self.properties.iter().enumerate().filter_map(|(i, property)| match property {
+            Property::Scalar(name, scalar) if names.contains(&name.as_str()) => Some((i, *scalar)),
+            _ => None,
+        }).next()
+    }
+
+}
+
+pub fn load(path: &Path) -> io::Result<Obj> {
+    let mut bytes = Vec::new();
+    File::open(path)?.read_to_end(&mut bytes)?;
+    parse(&bytes).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), err)))
+}
+
+/// Parse a PLY file in any of its encodings. Positions, normals, texture coordinates and colors of
+/// the `vertex` element and the `vertex_indices` of the `face` element are used, polygons are
+/// split into triangle fans and all other data is skipped.
+pub fn parse(bytes: &[u8]) -> Result<Obj, String> {
+    let (elements, mut body) = parse_header(bytes)?;
+    let mut vertices = Vec::new();
+    let mut normals = Vec::new();
+    let mut uvs = Vec::new();
+    let mut colors = Vec::new();
+    let mut faces = Vec::new();
+    for element in elements.iter() {
+        let position = [element.find(&["x"]), element.find(&["y"]), element.find(&["z"])];
+        let normal = [element.find(&["nx"]), element.find(&["ny"]), element.find(&["nz"])];
+        let uv = [element.find(&["u", "s", "texture_u", "texture_s"]), element.find(&["v", "t", "texture_v", "texture_t"])];
+        let color = [element.find(&["red", "r", "diffuse_red"]), element.find(&["green", "g", "diffuse_green"]),
+                     element.find(&["blue", "b", "diffuse_blue"])];
+        let indices = element.properties.iter()
+            .position(|p| p.name() == "vertex_indices" || p.name() == "vertex_index");
+        for _ in 0..element.count {
+            let values = body.read_element(element)?;
+            let get = |property: Option<(usize, Scalar)>| property.map(|(i, _)| values[i][0]);
+            match element.name.as_str() {
+                "vertex" => {
+                    let vector = |p: [Option<(usize, Scalar)>; 3]| {
+                        Some(geo::Vec3f::new(get(p[0])?, get(p[1])?, get(p[2])?))
+                    };
+                    vertices.push(vector(position).ok_or_else(|| String::from("vertex without x, y and z"))?);
+                    normals.extend(vector(normal));
+                    if let (Some(u), Some(v)) = (get(uv[0]), get(uv[1])) {
+                        uvs.push(geo::Vec3f::new(u, v, 0.));
+                    }
+                    if let Some(c) = vector(color) {
+                        let scale = color[0].unwrap().1.color_scale();
+                        colors.push(&c*scale);
+                    }
+                },
+                "face" => {
+                    let list = &values[indices.ok_or_else(|| String::from("face without vertex_indices"))?];
+                    if list.len() < 3 {
+                        return Err(format!("face with {} vertices", list.len()));
+                    }
+                    for k in 1..list.len() - 1 {
+                        faces.push(geo::Vec3i::new(list[0] as i32, list[k] as i32, list[k + 1] as i32));
+                    }
+                },
+                _ => continue,
+            }
+        }
+    }
+    if faces.iter().any(|f| [f.x, f.y, f.z].iter().any(|&v| v < 0 || v as usize >= vertices.len())) {
+        return Err(String::from("face refers to a missing vertex"));
+    }
+    let count = vertices.len();
+    let mut obj = Obj::new(vertices, faces.clone());
+    if uvs.len() == count {
+        obj.textures = uvs;
+        obj.face_textures = faces.clone();
+    }
+    if colors.len() == count {
+        obj.colors = colors;
+    }
+    if normals.len() == count {
+        obj.normals = normals;
+        obj.face_normals = faces;
+    } else {
+        obj.compute_normals(obj::NormalWeighting::Angle, obj::DEFAULT_CREASE_ANGLE);
+    }
+    Ok(obj)
+}
+
+fn parse_header<'a>(bytes: &'a [u8]) -> Result<(Vec<Element>, Body<'a>), String> {
+    const END: &[u8] = b"end_header";
+    let end = bytes.windows(END.len()).position(|w| w == END).ok_or_else(|| String::from("missing end_header"))?;
+    let data = bytes[end..].iter().position(|&b| b == b'\n').map_or(bytes.len(), |n| end + n + 1);
+    let header = str::from_utf8(&bytes[..end]).map_err(|_| String::from("header is not text"))?;
+    let mut lines = header.lines().enumerate();
+    if lines.next().map(|(_, line)| line.trim()) != Some("ply") {
+        return Err(String::from("not a PLY file"));
+    }
+    let mut elements: Vec<Element> = Vec::new();
+    let mut body = None;
+    for (n, line) in lines {
+        let error = |message: &str| format!("line {}: {}", n + 1, message);
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.first() {
+            Some(&"format") => {
+                body = match words.get(1) {
+                    Some(&"ascii") => {
+                        let text = str::from_utf8(&bytes[data..]).map_err(|_| error("ASCII data is not text"))?;
+                        Some(Body::Ascii(text.split_whitespace()))
+                    },
+                    Some(&"binary_little_endian") => Some(Body::Binary(&bytes[data..], false)),
+                    Some(&"binary_big_endian")    => Some(Body::Binary(&bytes[data..], true)),
+                    _ => return Err(error("unknown format")),
+                };
+            },
+            Some(&"element") => {
+                let count = words.get(2).and_then(|c| c.parse().ok()).ok_or_else(|| error("invalid element count"))?;
+                let name = String::from(*words.get(1).ok_or_else(|| error("element without a name"))?);
+                elements.push(Element{name, count, properties: Vec::new()});
+            },
+            Some(&"property") => {
+                let element = elements.last_mut().ok_or_else(|| error("property before any element"))?;
+                let scalar = |i: usize| {
+                    words.get(i).and_then(|w| Scalar::parse(w)).ok_or_else(|| error("unknown property type"))
+                };
+                let property = if words.get(1) == Some(&"list") {
+                    Property::List(String::from(*words.get(4).ok_or_else(|| error("list without a name"))?),
+                                   scalar(2)?, scalar(3)?)
+                } else {
+                    Property::Scalar(String::from(*words.get(2).ok_or_else(|| error("property without a name"))?),
+                                     scalar(1)?)
+                };
+                element.properties.push(property);
+            },
+            _ => continue,
+        }
+    }
+    Ok((elements, body.ok_or_else(|| String::from("missing format"))?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A colored square split into two triangles, with an extra list on its face and an element
+    /// the loader does not know
+    const HEADER: &str = "ply\nformat {}\ncomment made by hand\nelement vertex 4\nproperty float x\nproperty float y\n\
+                          property float z\nproperty uchar red\nproperty uchar green\nproperty uchar blue\n\
+                          element face 1\nproperty list uchar int vertex_indices\nproperty list uchar float texcoord\n\
+                          element edge 1\nproperty int vertex1\nproperty int vertex2\nend_header\n";
+
+    fn assert_square(obj: &Obj) {
+        assert_eq!(obj.vertices.len(), 4);
+        assert_eq!(obj.faces, vec![geo::Vec3i::new(0, 1, 2), geo::Vec3i::new(0, 2, 3)]);
+        assert_eq!(obj.vertices[2], geo::Vec3f::new(1., 1., -0.5));
+        let colors = obj.face_vertex_colors(1).unwrap();
+        assert_eq!(colors[0], geo::Vec3f::new(1., 0., 0.));
+        assert_eq!(colors[1], geo::Vec3f::new(0., 0., 1.));
+        assert!(obj.face_vertex_normals(0).is_some());
+    }
+
+    /// The square in binary, with scalars in the given byte order
+    fn binary(big_endian: bool) -> Vec<u8> {
+        let mut bytes = Vec::from(HEADER.replace("{}", if big_endian { "binary_big_endian 1.0" }
+                                                     else { "binary_little_endian 1.0" }).as_bytes());
+        let mut push = |value: &[u8]| {
+            let mut value = value.to_vec();
+            if !big_endian {
+                value.reverse();
+            }
+            bytes.extend(value);
+        };
+        let vertices = [(0., 0., [255, 0, 0]), (1., 0., [0, 255, 0]), (1., 1., [0, 0, 255]), (0., 1., [255, 255, 255])];
+        for &(x, y, color) in vertices.iter() {
+            for &c in [x, y, if x*y > 0. { -0.5 } else { 0. }].iter() {
+                let bits = (c as f32).to_bits();
+                push(&[(bits >> 24) as u8, (bits >> 16) as u8, (bits >> 8) as u8, bits as u8]);
+            }
+            for &c in color.iter() {
+                push(&[c]);
+            }
+        }
+        push(&[4]);
+        for &i in [0u32, 1, 2, 3].iter() {
+            push(&[(i >> 24) as u8, (i >> 16) as u8, (i >> 8) as u8, i as u8]);
+        }
+        push(&[0]);
+        push(&[0, 0, 0, 1]);
+        push(&[0, 0, 0, 2]);
+        bytes
+    }
+
+    #[test]
+    fn ascii() {
+        let text = HEADER.replace("{}", "ascii 1.0") + "0 0 0 255 0 0\n1 0 0 0 255 0\n1 1 -0.5 0 0 255\n0 1 0 255 255 255\n\
+                                                       4 0 1 2 3 2 0.5 0.5\n0 1\n";
+        assert_square(&parse(text.as_bytes()).unwrap());
+        assert!(parse(text.replace("4 0 1 2 3", "4 0 1 2 9").as_bytes()).is_err());
+        assert!(parse(text.replace("uchar red", "byte red").as_bytes()).is_err());
+        assert!(parse(&text.as_bytes()[..text.len() - 4]).is_err());
+        let huge = text.replace("list uchar int", "list uint int").replace("4 0 1 2 3", "4000000000 0 1 2 3");
+        assert_eq!(parse(huge.as_bytes()).err(), Some(String::from("unexpected end of data")));
+    }
+
+    #[test]
+    fn binary_byte_orders() {
+        let little = binary(false);
+        let big = binary(true);
+        assert_ne!(little[little.len() - 1], big[big.len() - 1]);
+        assert_square(&parse(&little).unwrap());
+        assert_square(&parse(&big).unwrap());
+        assert_eq!(Scalar::I16.decode(&[0xff, 0xfe], true), -2.);
+        assert_eq!(Scalar::U16.decode(&[0xff, 0xfe], false), 65279.);
+    }
+
+}
@@ -1,70 +1,808 @@
 use std;
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
 use std::vec::{Vec};
-use image;
+use std::path::Path;
+use std::rc::Rc;
+use std::f64::INFINITY;
+use aov::Aov;
+use bounds::{Aabb, Sphere};
+use framebuffer::{Attachment, Blend, Buffer, Format, Framebuffer, RenderTarget};
 use obj;
+use output;
+use output::{DepthFormat, ImageFormat};
+use tga;
 use geo;
 use geo::Vector;
+use mesh::{IndexedMesh, Vertex};
 use model;
 use model::Polygon;
-use std::f64::INFINITY;
+use camera::Camera;
+use transform::Transform;
+use graph::Node;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shading {
+    Wireframe,
+    Flat,
+    Gouraud,
+    Textured,
+    /// Smooth lighting quantized into the given number of bands
+    Toon(u32),
+}
+
+impl FromStr for Shading {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Shading, String> {
+        match s {
+            "wireframe" => Ok(Shading::Wireframe),
+            "flat"      => Ok(Shading::Flat),
+            "gouraud"   => Ok(Shading::Gouraud),
+            "textured"  => Ok(Shading::Textured),
+            "toon"      => Ok(Shading::Toon(4)),
+            _ if s.starts_with("toon:") => {
+                match s["toon:".len()..].parse() {
+                    Ok(bands) if bands >= 2 && bands <= 16 => Ok(Shading::Toon(bands)),
+                    _ => Err(format!("number of toon bands in '{}' must be between 2 and 16", s)),
+                }
+            },
+            _           => Err(format!("unknown shading mode '{}'", s)),
+        }
+    }
+}
+
+impl fmt::Display for Shading {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Shading::Wireframe => "wireframe",
+            Shading::Flat      => "flat",
+            Shading::Gouraud   => "gouraud",
+            Shading::Textured  => "textured",
+            Shading::Toon(bands) => return write!(f, "toon:{}", bands),
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// What lies under a pixel of the rendered image
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pick {
+    /// Index of the mesh in the depth-first order of the scene graph
+    pub object: usize,
+    /// Index of the face within the mesh
+    pub face: usize,
+    /// Weights of the face corners at the pixel
+    pub barycentric: geo::Vec3f,
+    /// Point on the face in world space
+    pub position: geo::Vec3f,
+}
+
+/// How faces that are not opaque are combined
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transparency {
+    /// Blend faces back to front, sorted by the depth of their centers
+    Sorted,
+    /// Weighted-blended order-independent transparency, needing no sort
+    Weighted,
+}
+
+impl FromStr for Transparency {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Transparency, String> {
+        match s {
+            "sorted"   => Ok(Transparency::Sorted),
+            "weighted" => Ok(Transparency::Weighted),
+            _          => Err(format!("unknown transparency mode '{}'", s)),
+        }
+    }
+}
+
+/// Ink lines drawn over the image where depth or surface orientation change abruptly
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Outlines {
+    pub color: [u8; 3],
+    /// Depth step between neighbouring pixels, as a fraction of the depth range of the image,
+    /// above which the nearer pixel lies on a silhouette
+    pub depth_threshold: f64,
+    /// Angle in radians between neighbouring normals above which the pixels form a crease
+    pub crease_angle: f64,
+    /// Follow the edges of the meshes instead of steps in the depth and normal buffers: edges
+    /// between a front and a back face, edges of open surfaces and creases are inked
+    pub mesh_edges: bool,
+}
+
+impl Outlines {
+
+    pub fn new(color: [u8; 3]) -> Outlines {
+        Outlines{color, depth_threshold: 0.05, crease_angle: 60f64.to_radians(), mesh_edges: false}
+    }
 
+    pub fn with_depth_threshold(mut self, threshold: f64) -> Self {
+        self.depth_threshold = threshold;
+        self
+    }
+
+    pub fn with_crease_angle(mut self, radians: f64) -> Self {
+        self.crease_angle = radians;
+        self
+    }
+
+    pub fn with_mesh_edges(mut self, mesh_edges: bool) -> Self {
+        self.mesh_edges = mesh_edges;
+        self
+    }
+
+    /// Depth difference that counts as a step in `depth`, from the depth range of the drawn pixels
+    fn depth_step(&self, depth: &Buffer<f64>) -> f64 {
+        let finite = depth.values().iter().cloned().filter(|z| z.is_finite());
+        let (near, far) = finite.fold((-INFINITY, INFINITY), |(near, far), z| (near.max(z), far.min(z)));
+        self.depth_threshold*(near - far).max(0.)
+    }
+
+    /// Pixels on an outline, from a depth buffer where -inf marks empty pixels and the matching
+    /// unit normals. Silhouettes are drawn on the nearer side of a depth step or an object border.
+    pub fn detect(&self, depth: &Buffer<f64>, normals: &Attachment) -> Buffer<bool> {
+        let (width, height) = depth.dimensions();
+        let step = self.depth_step(depth);
+        let cos_crease = self.crease_angle.cos();
+        let mut edges = Buffer::new(width, height, false);
+        for y in 0..height {
+            for x in 0..width {
+                let z = depth.get(x, y).unwrap();
+                if !z.is_finite() {
+                    continue;
+                }
+                let n = normals.get(x, y).unwrap();
+                let neighbours = [(x.wrapping_sub(1), y), (x + 1, y), (x, y.wrapping_sub(1)), (x, y + 1)];
+                let edge = neighbours.iter().enumerate().any(|(k, &(nx, ny))| {
+                    let other = match depth.get(nx, ny) {
+                        Some(other) => other,
+                        None        => return false,
+                    };
+                    if !other.is_finite() || z - other > step {
+                        return true;
+                    }
+                    // creases are marked on one side only, to keep them a pixel wide
+                    let m = normals.get(nx, ny).unwrap();
+                    k % 2 == 1 && n[0]*m[0] + n[1]*m[1] + n[2]*m[2] < cos_crease
+                });
+                edges.set(x, y, edge);
+            }
+        }
+        edges
+    }
+
+}
 
-pub struct Scene<'a> {
-    objects: Vec<obj::Obj>,
-    light_dir: geo::Vec3f,
-    img: &'a mut image::RgbImage,
-    zbuffer: Vec<f64>,
+pub struct Scene {
+    root: Node,
+    lights: Vec<geo::Vec3f>,
+    camera: Camera,
+    camera_node: Option<String>,
+    shading: Shading,
+    transparency: Transparency,
+    outlines: Option<Outlines>,
+    background: [u8; 3],
+    framebuffer: Framebuffer,
+    aovs: Vec<(Aov, Framebuffer)>,
+    /// World-to-screen transform of the last `draw`, which `pick` maps pixels back through
+    drawn: Option<geo::Mat4>,
 }
 
-impl<'a> Scene<'a> {
+impl Scene {
 
-    pub fn new(objects: Vec<obj::Obj>, img: &'a mut image::RgbImage) -> Scene {
-        let (imgx, imgy) = img.dimensions();
-        Scene{objects, light_dir: geo::Vec3f::new(0., 0., -1.), img,
-           zbuffer: vec![-INFINITY; (imgx*imgy) as usize]}
+    pub fn new(objects: Vec<obj::Obj>, framebuffer: Framebuffer) -> Scene {
+        let mut root = Node::new("root");
+        for obj in objects.into_iter() {
+            root.add_child(Node::new("").with_mesh(Rc::new(obj)));
+        }
+        Scene{root, lights: vec![geo::Vec3f::new(0., 0., -1.)], camera: Camera::default(), camera_node: None,
+           shading: Shading::Flat, transparency: Transparency::Sorted, outlines: None,
+           background: [0, 0, 0],
+           framebuffer: framebuffer.with_ids(),
+           aovs: Vec::new(), drawn: None}
+    }
+
+    /// Add a mesh in its own coordinates; the returned handle can be used to add more instances
+    pub fn add_object(&mut self, obj: obj::Obj) -> Rc<obj::Obj> {
+        let mesh = Rc::new(obj);
+        self.add_instance(Rc::clone(&mesh), Transform::identity());
+        mesh
+    }
+
+    pub fn add_instance(&mut self, mesh: Rc<obj::Obj>, transform: Transform) {
+        self.root.add_child(Node::new("").with_transform(transform).with_mesh(mesh));
+    }
+
+    /// Attach a node, with its whole subtree, to the root of the scene graph
+    pub fn add_node(&mut self, node: Node) -> &mut Node {
+        self.root.add_child(node)
     }
 
-    pub fn add_object(&mut self, obj: obj::Obj) {
-        self.objects.push(obj);
+    pub fn root(&self) -> &Node {
+        &self.root
     }
 
+    pub fn root_mut(&mut self) -> &mut Node {
+        &mut self.root
+    }
+
+    /// Replace all lights by a single directional light shining along (x, y, z)
     #[allow(dead_code)]
     pub fn light_direction(&mut self, x: f64, y: f64, z: f64) {
-        self.light_dir = geo::Vec3f::new(x, y, z).normalize();
+        self.lights = vec![geo::Vec3f::new(x, y, z).normalize()];
+    }
+
+    pub fn add_light(&mut self, x: f64, y: f64, z: f64) {
+        self.lights.push(geo::Vec3f::new(x, y, z).normalize());
+    }
+
+    pub fn clear_lights(&mut self) {
+        self.lights.clear();
+    }
+
+    pub fn set_camera(&mut self, camera: Camera) {
+        self.camera = camera;
+        self.camera_node = None;
+    }
+
+    /// Bounding box of all meshes as placed in the world, or None if there are none
+    pub fn bounds(&self) -> Option<Aabb> {
+        self.root.meshes().iter()
+            .filter_map(|(mesh, world)| mesh.bounds().map(|bounds| bounds.transform(world)))
+            .fold(None, |all: Option<Aabb>, bounds| Some(all.map_or(bounds, |all| all.union(&bounds))))
+    }
+
+    /// Bounding sphere of all meshes as placed in the world, or None if there are none
+    pub fn bounding_sphere(&self) -> Option<Sphere> {
+        let mut points = Vec::new();
+        for (mesh, world) in self.root.meshes() {
+            points.extend(mesh.vertices.iter().map(|v| world.transform_point(v)));
+        }
+        Sphere::from_points(points.iter())
+    }
+
+    /// Aim the active camera at the whole scene from the direction it looks in, and zoom it so
+    /// that everything is in view with `margin`, a fraction of the view, left free around it
+    pub fn frame_all(&mut self, margin: f64) {
+        if let Some(sphere) = self.bounding_sphere() {
+            let camera = self.active_camera().framing(&sphere, margin);
+            self.set_camera(camera);
+        }
+    }
+
+    /// View the scene through the camera attached to the graph node named `name`
+    pub fn use_camera_node(&mut self, name: &str) -> Result<(), String> {
+        if self.root.camera(name).is_none() {
+            return Err(format!("no camera attached to a node named '{}'", name));
+        }
+        self.camera_node = Some(String::from(name));
+        Ok(())
+    }
+
+    /// Name of the graph node the scene is viewed through, if not a camera set with `set_camera`
+    pub fn camera_node(&self) -> Option<&str> {
+        self.camera_node.as_ref().map(|name| name.as_str())
+    }
+
+    /// The camera the scene is viewed through
+    pub fn active_camera(&self) -> Camera {
+        self.camera_node.as_ref()
+            .and_then(|name| self.root.camera(name))
+            .unwrap_or(self.camera)
+    }
+
+    pub fn set_shading(&mut self, shading: Shading) {
+        self.shading = shading;
+    }
+
+    pub fn set_transparency(&mut self, transparency: Transparency) {
+        if transparency == Transparency::Weighted {
+            let framebuffer = std::mem::replace(&mut self.framebuffer, Framebuffer::new(0, 0, Format::Rgb8));
+            self.framebuffer = framebuffer.with_oit();
+        }
+        self.transparency = transparency;
+    }
+
+    /// Store the image in `format`; Rgb32F keeps values above one for the float output formats
+    pub fn set_color_format(&mut self, format: Format) {
+        let framebuffer = std::mem::replace(&mut self.framebuffer, Framebuffer::new(0, 0, Format::Rgb8));
+        self.framebuffer = framebuffer.with_color_format(format);
+    }
+
+    /// Draw silhouette and crease lines over the shaded image, or no lines with None
+    pub fn set_outlines(&mut self, outlines: Option<Outlines>) {
+        self.outlines = outlines;
+    }
+
+    pub fn set_background(&mut self, color: [u8; 3]) {
+        self.background = color;
+    }
+
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.framebuffer
+    }
+
+    /// Render `aov` in an extra pass on every `draw`
+    pub fn enable_aov(&mut self, aov: Aov) {
+        if self.aov(aov).is_none() {
+            let (width, height) = self.framebuffer.dimensions();
+            self.aovs.push((aov, Framebuffer::new(width, height, Format::Rgb32F)));
+        }
+    }
+
+    /// Raw values of an enabled output variable from the last `draw`
+    pub fn aov(&self, aov: Aov) -> Option<&Attachment> {
+        self.aovs.iter().find(|(a, _)| *a == aov).and_then(|(_, buffer)| buffer.color(0))
     }
 
     pub fn draw(&mut self) {
-        for obj in (&self.objects).into_iter() {
-            ObjRenderer::new(obj).draw_lit(self.img, self.light_dir, &mut self.zbuffer);
+        let background = [self.background[0] as f64/255., self.background[1] as f64/255.,
+                          self.background[2] as f64/255., 1.];
+        self.framebuffer.clear(&background);
+        let transform = self.active_camera().transform();
+        self.drawn = Some(transform);
+        let mut lights = self.lights.clone();
+        lights.extend(self.root.lights().iter().map(|light| light.normalize()));
+        let meshes = self.root.meshes();
+        // transparent faces of all objects are blended after everything opaque
+        let mut transparent = Vec::new();
+        for (id, &(ref mesh, world)) in meshes.iter().enumerate() {
+            let renderer = ObjRenderer::new(mesh).with_transform(world).with_id(id);
+            for i in renderer.draw_opaque(&mut self.framebuffer, &transform, &lights, self.shading) {
+                if let Some(depth) = renderer.face_depth(i, &transform) {
+                    transparent.push((depth, id, i));
+                }
+            }
+        }
+        let blend = match self.transparency {
+            Transparency::Sorted   => {
+                transparent.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+                Blend::Over
+            },
+            Transparency::Weighted => Blend::Weighted,
+        };
+        for &(_, id, i) in transparent.iter() {
+            let (ref mesh, world) = meshes[id];
+            ObjRenderer::new(mesh).with_transform(world).with_id(id)
+                .draw_face(&mut self.framebuffer, i, &transform, &lights, self.shading, blend);
+        }
+        self.framebuffer.resolve_oit();
+        let camera = self.active_camera();
+        if let Some(outlines) = self.outlines {
+            self.draw_outlines(&outlines, &meshes, &camera);
+        }
+        for &mut (aov, ref mut buffer) in self.aovs.iter_mut() {
+            let background = if aov == Aov::Depth { [-INFINITY; 4] } else { aov.background() };
+            buffer.clear(&background);
+            for (id, &(ref mesh, world)) in meshes.iter().enumerate() {
+                ObjRenderer::new(mesh).with_transform(world).draw_aov(buffer, &camera, aov, id);
+            }
+        }
+    }
+
+    /// Detect outlines from the depth buffer and a view-space normal pass, and ink them in
+    fn draw_outlines(&mut self, outlines: &Outlines, meshes: &[(Rc<obj::Obj>, geo::Mat4)], camera: &Camera) {
+        let (width, height) = self.framebuffer.dimensions();
+        let edges = if outlines.mesh_edges {
+            let depth = self.framebuffer.depth_buffer();
+            let mut edges = Buffer::new(width, height, false);
+            for &(ref mesh, world) in meshes.iter() {
+                ObjRenderer::new(mesh).with_transform(world).mark_edges(&mut edges, depth, &camera.transform(), outlines);
+            }
+            edges
+        } else {
+            let mut normals = Framebuffer::new(width, height, Format::Rgb32F);
+            normals.clear(&Aov::ViewNormal.background());
+            for (id, &(ref mesh, world)) in meshes.iter().enumerate() {
+                ObjRenderer::new(mesh).with_transform(world).draw_aov(&mut normals, camera, Aov::ViewNormal, id);
+            }
+            outlines.detect(self.framebuffer.depth_buffer(), normals.color(0).unwrap())
+        };
+        let color = [outlines.color[0] as f64/255., outlines.color[1] as f64/255., outlines.color[2] as f64/255., 1.];
+        let target = self.framebuffer.color_mut(0).unwrap();
+        for y in 0..height {
+            for x in 0..width {
+                if edges.get(x, y).unwrap() {
+                    target.set(x, y, &color);
+                }
+            }
+        }
+    }
+
+    /// The face drawn at pixel (x, y) of the saved image, which has its origin at the top left,
+    /// by the last `draw`, as seen through the camera of that draw. The scene graph must not have
+    /// changed since.
+    pub fn pick(&self, x: u32, y: u32) -> Option<Pick> {
+        let (width, height) = self.framebuffer.dimensions();
+        if y >= height {
+            return None;
+        }
+        // the framebuffer has its origin at the bottom left
+        let y = height - 1 - y;
+        let (object, face) = self.framebuffer.ids()?.get(x, y)??;
+        let (mesh, world) = self.root.meshes().into_iter().nth(object)?;
+        let renderer = ObjRenderer::new(&mesh).with_transform(world);
+        let vertices = renderer.world_vertices(&mesh.faces[face]);
+        let transform = self.drawn?;
+        let screen = ObjRenderer::project(&vertices, &transform)?;
+        let screen_weights = model::Triangle::new(screen[0], screen[1], screen[2])
+            .pixel_barycentric(width, height, x, y);
+        // undo the perspective divide so the weights are those of the point on the face itself
+        let w: Vec<f64> = vertices.iter()
+            .map(|v| transform.m[3][0]*v.x + transform.m[3][1]*v.y + transform.m[3][2]*v.z + transform.m[3][3])
+            .collect();
+        let weights = geo::Vec3f::new(screen_weights.x/w[0], screen_weights.y/w[1], screen_weights.z/w[2]);
+        let barycentric = &weights*(1./(weights.x + weights.y + weights.z));
+        let position = ObjRenderer::interpolate(&vertices, &barycentric);
+        Some(Pick{object, face, barycentric, position})
+    }
 
+    /// Save the image in the format given by the extension of `path`; TGA files are run-length encoded
+    pub fn save(&self, path: &str) -> Result<(), std::io::Error>  {
+        if tga::is_tga(Path::new(path)) {
+            return tga::save(&self.framebuffer.to_tga(), Path::new(path), true);
         }
+        self.framebuffer.to_image().save(path)
     }
 
-    pub fn save(self, path: &str) -> Result<(), std::io::Error>  {
-        image::imageops::flip_vertical(self.img).save(path)
+    /// Save the image in an explicitly chosen format, whatever the extension of `path`
+    pub fn save_as(&self, path: &str, format: ImageFormat) -> Result<(), std::io::Error> {
+        output::save_image(self.framebuffer.color(0).unwrap(), Path::new(path), format)
     }
 
+    /// Save the depth buffer of the last `draw`, in the format given by the extension of `path`
+    pub fn save_depth(&self, path: &str) -> Result<(), std::io::Error> {
+        let path = Path::new(path);
+        let format = DepthFormat::from_path(path).ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::InvalidInput, "depth output must be .png, .pfm, .raw or .f32")
+        })?;
+        output::save_depth(self.framebuffer.depth_buffer(), path, format)
+    }
+
+    /// Save an enabled output variable: .png holds 8-bit encoded values, .pfm the raw floats. Depth
+    /// is saved like `save_depth`.
+    pub fn save_aov(&self, aov: Aov, path: &str) -> Result<(), std::io::Error> {
+        let buffer = self.aov(aov).ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("output variable '{}' is not enabled", aov))
+        })?;
+        if aov == Aov::Depth {
+            return self.save_depth(path);
+        }
+        output::save_aov(aov, buffer, Path::new(path))
+    }
+
+}
+
+/// A face that survived projection and culling
+struct ScreenFace {
+    world: [geo::Vec3f; 3],
+    screen: model::Triangle<f64>,
+    screen_z: [f64; 3],
+    /// Barycentric gradients per pixel in x and y
+    gradients: Option<(geo::Vec3f, geo::Vec3f)>,
 }
 
 pub struct ObjRenderer<'a> {
     obj: &'a obj::Obj,
+    model: geo::Mat4,
+    normal_model: geo::Mat4,
+    id: usize,
 }
 
 impl<'a> ObjRenderer<'a> {
 
     pub fn new(obj: &'a obj::Obj) -> ObjRenderer {
-        ObjRenderer{obj}
+        ObjRenderer{obj, model: geo::Mat4::identity(), normal_model: geo::Mat4::identity(), id: 0}
+    }
+
+    /// Place the object in the world with the model matrix `model`
+    pub fn with_transform(mut self, model: geo::Mat4) -> Self {
+        self.normal_model = model.inverse().unwrap_or(geo::Mat4::identity()).transpose();
+        self.model = model;
+        self
+    }
+
+    /// Object index reported to the render target along with the face index
+    pub fn with_id(mut self, id: usize) -> Self {
+        self.id = id;
+        self
+    }
+
+    /// Draw the opaque faces as seen through `transform`, which maps world space to screen space, and
+    /// return the indices of the faces that need blending
+    pub fn draw_opaque<R: RenderTarget>(&self, target: &mut R, transform: &geo::Mat4, lights: &[geo::Vec3f],
+                                        shading: Shading) -> Vec<usize> {
+        if shading == Shading::Wireframe {
+            self.draw_wireframe(target, transform);
+            return Vec::new();
+        }
+        let textured = shading == Shading::Textured && self.obj.has_texture();
+        let mut transparent = Vec::new();
+        for i in 0..self.obj.faces.len() {
+            if self.obj.is_transparent(i, textured) {
+                transparent.push(i);
+            } else {
+                self.draw_face(target, i, transform, lights, shading, Blend::Replace);
+            }
+        }
+        transparent
+    }
+
+    /// Screen depth of the center of face `i`, larger is closer, or None if it is behind the camera
+    pub fn face_depth(&self, i: usize, transform: &geo::Mat4) -> Option<f64> {
+        let screen = ObjRenderer::project(&self.world_vertices(&self.obj.faces[i]), transform)?;
+        let depth = (screen[0].z + screen[1].z + screen[2].z)/3.;
+        // faces with coordinates that are not numbers have no place in the order
+        if depth.is_finite() { Some(depth) } else { None }
+    }
+
+    pub fn draw_face<R: RenderTarget>(&self, target: &mut R, i: usize, transform: &geo::Mat4,
+                                      lights: &[geo::Vec3f], shading: Shading, blend: Blend) {
+        let face = match self.visible_face(i, transform, target.dimensions()) {
+            Some(face) => face,
+            None       => return,
+        };
+        target.set_primitive(self.id, i);
+        let triangle = model::Triangle::new(face.world[0], face.world[1], face.world[2]);
+        // several lights only add up past one where the target can hold it
+        let limit = if target.high_dynamic_range() { INFINITY } else { 1. };
+        let face_intensity = lights.iter()
+            .map(|light| ObjRenderer::light_intensity(&triangle, *light).max(0.))
+            .sum::<f64>().min(limit);
+        let vertex_intensity = match shading {
+            Shading::Gouraud | Shading::Textured | Shading::Toon(_) => self.vertex_normals(i).map(|normals| {
+                [ObjRenderer::vertex_intensity(&normals[0], lights).min(limit),
+                 ObjRenderer::vertex_intensity(&normals[1], lights).min(limit),
+                 ObjRenderer::vertex_intensity(&normals[2], lights).min(limit)]
+            }),
+            _ => None,
+        };
+        // the alpha test applies in every shading mode, the texture colors only when textured
+        let uvs = if self.obj.has_texture() { self.obj.face_uvs(i) } else { None };
+        let shaded_uvs = if shading == Shading::Textured { uvs } else { None };
+        let uv_gradients = ObjRenderer::uv_gradients(&uvs, &face.gradients);
+        face.screen.draw_blended(target, blend, |bc| {
+            let intensity = match vertex_intensity {
+                Some(ref intensity) => intensity[0]*bc.x + intensity[1]*bc.y + intensity[2]*bc.z,
+                None                => face_intensity,
+            };
+            let intensity = match shading {
+                Shading::Toon(bands) => (intensity*bands as f64).ceil()/bands as f64,
+                _                    => intensity,
+            };
+            if self.cuts_out(&uvs, &uv_gradients, bc) {
+                return None;
+            }
+            let albedo = self.albedo(i, &shaded_uvs, &uv_gradients, bc);
+            Some([albedo[0]*intensity, albedo[1]*intensity, albedo[2]*intensity,
+                  albedo[3]*self.obj.face_opacity(i)])
+        });
+    }
+
+    /// Draw the value of an output variable instead of a shaded color, with `object_id` as the
+    /// value of `Aov::ObjectId`
+    pub fn draw_aov<R: RenderTarget>(&self, target: &mut R, camera: &Camera, aov: Aov, object_id: usize) {
+        let transform = camera.transform();
+        let view = camera.view();
+        let dimensions = target.dimensions();
+        for i in 0..self.obj.faces.len() {
+            let face = match self.visible_face(i, &transform, dimensions) {
+                Some(face) => face,
+                None       => continue,
+            };
+            let normals = self.vertex_normals(i).unwrap_or_else(|| {
+                let normal = &model::Triangle::new(face.world[0], face.world[1], face.world[2]).normal()*-1.;
+                [normal, normal, normal]
+            });
+            let uvs = self.obj.face_uvs(i);
+            let uv_gradients = ObjRenderer::uv_gradients(&uvs, &face.gradients);
+            let depths = [geo::Vec3f::new(face.screen_z[0], 0., 0.), geo::Vec3f::new(face.screen_z[1], 0., 0.),
+                          geo::Vec3f::new(face.screen_z[2], 0., 0.)];
+            face.screen.draw_shaded(target, |bc| {
+                if self.cuts_out(&uvs, &uv_gradients, bc) {
+                    return None;
+                }
+                let value = match aov {
+                    Aov::Normal     => ObjRenderer::interpolate(&normals, bc).normalize(),
+                    Aov::ViewNormal => view.transform_vector(&ObjRenderer::interpolate(&normals, bc)).normalize(),
+                    Aov::Uv         => uvs.map_or(geo::Vec3f::new(0., 0., 0.), |uvs| ObjRenderer::interpolate(&uvs, bc)),
+                    Aov::ObjectId   => geo::Vec3f::new(object_id as f64, object_id as f64, object_id as f64),
+                    Aov::FaceId     => geo::Vec3f::new(i as f64, i as f64, i as f64),
+                    Aov::Albedo     => {
+                        let albedo = self.albedo(i, &uvs, &uv_gradients, bc);
+                        geo::Vec3f::new(albedo[0], albedo[1], albedo[2])
+                    },
+                    Aov::Depth      => {
+                        let z = ObjRenderer::interpolate(&depths, bc).x;
+                        geo::Vec3f::new(z, z, z)
+                    },
+                };
+                Some([value.x, value.y, value.z, 1.])
+            });
+        }
+    }
+
+    fn draw_wireframe<R: RenderTarget>(&self, target: &mut R, transform: &geo::Mat4) {
+        for (i, face) in self.obj.faces.iter().enumerate() {
+            if !self.obj.is_face_visible(i) {
+                continue;
+            }
+            let screen = match ObjRenderer::project(&self.world_vertices(face), transform) {
+                Some(screen) => screen,
+                None         => continue,
+            };
+            if ObjRenderer::on_screen(&screen) {
+                target.set_primitive(self.id, i);
+                model::Triangle::new(screen[0], screen[1], screen[2]).draw(target, &[1., 1., 1., 1.]);
+            }
+        }
+    }
+
+    /// Mark the outline edges of the mesh in `edges` where no surface in `depth` lies clearly in
+    /// front of them. Corners are shared by position, so texture seams do not count as edges.
+    fn mark_edges(&self, edges: &mut Buffer<bool>, depth: &Buffer<f64>, transform: &geo::Mat4, outlines: &Outlines) {
+        let triangles = (0..self.obj.faces.len()).filter(|&i| self.obj.is_face_visible(i))
+            .map(|i| [self.obj.faces[i].x as usize, self.obj.faces[i].y as usize, self.obj.faces[i].z as usize])
+            .collect();
+        let vertices = self.obj.vertices.iter().map(|v| Vertex::new(self.model.transform_point(v))).collect();
+        let mesh = IndexedMesh::new(vertices, triangles);
+        let screen: Vec<Option<geo::Vec3f>> = mesh.vertices.iter().map(|v| transform.project(&v.position)).collect();
+        // whether each triangle faces the camera, as back-face culling decides it
+        let front: Vec<Option<bool>> = mesh.triangles.iter().map(|t| {
+            let (a, b, c) = (screen[t[0]]?, screen[t[1]]?, screen[t[2]]?);
+            Some((&b - &a).cross(&(&c - &a)).z > 0.)
+        }).collect();
+        let normal = |t: &[usize; 3]| {
+            let (a, b, c) = (&mesh.vertices[t[0]].position, &mesh.vertices[t[1]].position, &mesh.vertices[t[2]].position);
+            (b - a).cross(&(c - a)).normalize()
+        };
+        let cos_crease = outlines.crease_angle.cos();
+        let step = outlines.depth_step(depth);
+        let adjacency = mesh.adjacency();
+        for (a, b) in adjacency.edges() {
+            let (start, end) = match (screen[a], screen[b]) {
+                (Some(start), Some(end)) => (start, end),
+                _                        => continue,
+            };
+            let faces = adjacency.edge_faces(a, b);
+            let facing: Vec<bool> = faces.iter().filter_map(|&f| front[f]).collect();
+            let outline = match facing.len() {
+                0 => false,
+                // the edge of an open surface
+                1 => facing[0],
+                2 => facing[0] != facing[1]
+                    || (facing[0] && normal(&mesh.triangles[faces[0]]).dot(&normal(&mesh.triangles[faces[1]])) < cos_crease),
+                _ => facing.iter().any(|&f| f),
+            };
+            if outline {
+                ObjRenderer::mark_line(edges, depth, &start, &end, step);
+            }
+        }
     }
 
-    pub fn draw_lit(&self, img: &mut image::RgbImage, light_dir: geo::Vec3f, zbuf: &mut Vec<f64>) {
-        for face in self.obj.faces.iter() {
-            let triangle = self.obj.get_triangle(face);
-            let intensity = ObjRenderer::light_intensity(&triangle, light_dir);
-            if intensity > 0. {
-                let color = [(255. * intensity) as u8, (255. * intensity) as u8, (255. * intensity) as u8];
-                triangle.draw_filled(img, &color, zbuf);
+    /// Mark the pixels along the screen-space segment from `start` to `end` that are not more than
+    /// `step` behind the depth buffer
+    fn mark_line(edges: &mut Buffer<bool>, depth: &Buffer<f64>, start: &geo::Vec3f, end: &geo::Vec3f, step: f64) {
+        let (width, height) = depth.dimensions();
+        let (x0, y0) = ((start.x + 1.)*0.5*width as f64, (start.y + 1.)*0.5*height as f64);
+        let (x1, y1) = ((end.x + 1.)*0.5*width as f64, (end.y + 1.)*0.5*height as f64);
+        let n = (x1 - x0).abs().max((y1 - y0).abs()).ceil().max(1.).min(1e6) as usize;
+        for k in 0..n + 1 {
+            let t = k as f64/n as f64;
+            let (x, y) = (x0 + (x1 - x0)*t, y0 + (y1 - y0)*t);
+            if x < 0. || y < 0. {
+                continue;
             }
+            let (x, y, z) = (x as u32, y as u32, start.z + (end.z - start.z)*t);
+            if depth.get(x, y).map_or(false, |d| z >= d - step) {
+                edges.set(x, y, true);
+            }
+        }
+    }
+
+    /// Face `i` projected to the screen, or None if it is hidden, behind the camera or facing away
+    fn visible_face(&self, i: usize, transform: &geo::Mat4, dimensions: (u32, u32)) -> Option<ScreenFace> {
+        if !self.obj.is_face_visible(i) {
+            return None;
+        }
+        let world = self.world_vertices(&self.obj.faces[i]);
+        let screen = ObjRenderer::project(&world, transform)?;
+        // back-face culling: front faces are wound counter-clockwise on screen
+        if (&screen[1] - &screen[0]).cross(&(&screen[2] - &screen[0])).z <= 0. {
+            return None;
+        }
+        let screen_z = [screen[0].z, screen[1].z, screen[2].z];
+        let screen = model::Triangle::new(screen[0], screen[1], screen[2]);
+        let gradients = screen.screen_gradients(dimensions.0, dimensions.1);
+        Some(ScreenFace{world, screen, screen_z, gradients})
+    }
+
+    /// World-space vertex normals of face `i`, if the mesh has them
+    fn vertex_normals(&self, i: usize) -> Option<[geo::Vec3f; 3]> {
+        self.obj.face_vertex_normals(i).map(|normals| {
+            [self.normal_model.transform_vector(&normals[0]), self.normal_model.transform_vector(&normals[1]),
+             self.normal_model.transform_vector(&normals[2])]
+        })
+    }
+
+    /// Texture coordinate footprint of a pixel, from the barycentric gradients of its face
+    fn uv_gradients(uvs: &Option<[geo::Vec3f; 3]>, gradients: &Option<(geo::Vec3f, geo::Vec3f)>)
+        -> (geo::Vec2f, geo::Vec2f)
+    {
+        match (uvs, gradients) {
+            (Some(uvs), Some((dbc_dx, dbc_dy))) => {
+                let (du_dx, du_dy) = (ObjRenderer::interpolate(uvs, dbc_dx), ObjRenderer::interpolate(uvs, dbc_dy));
+                (geo::Vec2f::new(du_dx.x, du_dx.y), geo::Vec2f::new(du_dy.x, du_dy.y))
+            },
+            _ => (geo::Vec2f::new(0., 0.), geo::Vec2f::new(0., 0.)),
+        }
+    }
+
+    /// Whether the alpha test discards the point at `bc`, decided by the texture alone so that
+    /// every pass and shading mode cuts out the same pixels
+    fn cuts_out(&self, uvs: &Option<[geo::Vec3f; 3]>, uv_gradients: &(geo::Vec2f, geo::Vec2f),
+                bc: &geo::Vec3f) -> bool {
+        if self.obj.alpha_cutoff().is_none() {
+            return false;
         }
+        let alpha = match uvs {
+            Some(ref uvs) => {
+                let uv = ObjRenderer::interpolate(uvs, bc);
+                self.obj.diffuse(&geo::Vec2f::new(uv.x, uv.y), &uv_gradients.0, &uv_gradients.1).map_or(1., |t| t[3])
+            },
+            None => 1.,
+        };
+        self.obj.cuts_out(alpha)
+    }
+
+    /// Unlit color of face `i`: the texture, if `uvs` are given, times the object color, the
+    /// material color and the vertex colors. The alpha is that of the texture alone.
+    fn albedo(&self, i: usize, uvs: &Option<[geo::Vec3f; 3]>, uv_gradients: &(geo::Vec2f, geo::Vec2f),
+              bc: &geo::Vec3f) -> [f64; 4]
+    {
+        let texel = match uvs {
+            Some(ref uvs) => {
+                let uv = ObjRenderer::interpolate(uvs, bc);
+                self.obj.diffuse(&geo::Vec2f::new(uv.x, uv.y), &uv_gradients.0, &uv_gradients.1)
+                    .unwrap_or([1., 1., 1., 1.])
+            },
+            None => [1., 1., 1., 1.],
+        };
+        let tint = self.obj.color();
+        let mut diffuse = self.obj.face_material(i).map_or([1.; 3], |m| m.diffuse);
+        if let Some(colors) = self.obj.face_vertex_colors(i) {
+            let color = ObjRenderer::interpolate(&colors, bc);
+            diffuse = [diffuse[0]*color.x, diffuse[1]*color.y, diffuse[2]*color.z];
+        }
+        [texel[0]*tint[0] as f64/255.*diffuse[0], texel[1]*tint[1] as f64/255.*diffuse[1],
+         texel[2]*tint[2] as f64/255.*diffuse[2], texel[3]]
+    }
+
+    /// Combine per-vertex values with barycentric weights
+    fn interpolate(values: &[geo::Vec3f; 3], bc: &geo::Vec3f) -> geo::Vec3f {
+        &(&(&values[0]*bc.x) + &(&values[1]*bc.y)) + &(&values[2]*bc.z)
+    }
+
+    fn world_vertices(&self, face: &geo::Vec3i) -> [geo::Vec3f; 3] {
+        [self.model.transform_point(&self.obj.vertices[face.x as usize]),
+         self.model.transform_point(&self.obj.vertices[face.y as usize]),
+         self.model.transform_point(&self.obj.vertices[face.z as usize])]
+    }
+
+    /// Screen-space corners of a face, or None if any corner lies behind the camera
+    fn project(world: &[geo::Vec3f; 3], transform: &geo::Mat4) -> Option<[geo::Vec3f; 3]> {
+        let a = transform.project(&world[0])?;
+        let b = transform.project(&world[1])?;
+        let c = transform.project(&world[2])?;
+        Some([a, b, c])
+    }
+
+    fn on_screen(screen: &[geo::Vec3f; 3]) -> bool {
+        screen.iter().all(|v| v.x >= -1. && v.x <= 1. && v.y >= -1. && v.y <= 1.)
     }
 
     pub fn light_intensity(triangle: &model::Triangle<f64>, direction: geo::Vec3f) -> f64 {
@@ -72,4 +810,288 @@ impl<'a> ObjRenderer<'a> {
         normal.dot(&direction.normalize())
     }
 
+    /// Summed diffuse intensity of all lights on a surface with outward `normal`, which may exceed one
+    pub fn vertex_intensity(normal: &geo::Vec3f, lights: &[geo::Vec3f]) -> f64 {
+        let normal = normal.normalize();
+        lights.iter()
+            .map(|light| (-normal.dot(light)).max(0.))
+            .sum::<f64>()
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image;
+    use image::GenericImage;
+    use texture;
+
+    #[test]
+    fn shading_names() {
+        for shading in [Shading::Wireframe, Shading::Flat, Shading::Gouraud, Shading::Textured, Shading::Toon(3)].iter() {
+            assert_eq!(shading.to_string().parse(), Ok(*shading));
+        }
+        assert_eq!("toon".parse(), Ok(Shading::Toon(4)));
+        assert!("toon:17".parse::<Shading>().is_err());
+    }
+
+    #[test]
+    fn outline_detection() {
+        let outlines = Outlines::new([0, 0, 0]);
+        let mut depth = Buffer::new(8, 8, 0.);
+        let mut normals = Attachment::new(Format::Rgb32F, 8, 8);
+        normals.fill(&[0., 0., 1., 1.]);
+        for y in 2..6 {
+            for x in 2..6 {
+                depth.set(x, y, 1.);
+            }
+        }
+        // a raised square is outlined along its own border
+        let edges = outlines.detect(&depth, &normals);
+        for y in 0..8 {
+            for x in 0..8 {
+                let border = (x == 2 || x == 5) && y >= 2 && y < 6 || (y == 2 || y == 5) && x >= 2 && x < 6;
+                assert_eq!(edges.get(x, y), Some(border), "pixel {},{}", x, y);
+            }
+        }
+        // a fold between two flat halves is a crease, one pixel wide
+        depth.fill(0.);
+        for y in 0..8 {
+            for x in 4..8 {
+                normals.set(x, y, &[1., 0., 0., 1.]);
+            }
+        }
+        let edges = outlines.detect(&depth, &normals);
+        assert!(edges.values().iter().enumerate().all(|(i, &edge)| edge == (i % 8 == 3)));
+        assert!(!outlines.with_crease_angle(100f64.to_radians()).detect(&depth, &normals).values().contains(&true));
+    }
+
+    #[test]
+    fn instances_share_mesh() {
+        let mut scene = Scene::new(Vec::new(), Framebuffer::new(16, 16, Format::Rgb8));
+        let mesh = scene.add_object(obj::Obj::from_file("obj/african_head.obj").unwrap());
+        for i in 0..3 {
+            let offset = geo::Vec3f::new(i as f64, 0., 0.);
+            scene.add_instance(Rc::clone(&mesh), Transform::new(offset, geo::Vec3f::new(0., 0., 0.),
+                                                                geo::Vec3f::new(1., 1., 1.)));
+        }
+        assert_eq!(scene.root().meshes().len(), 4);
+        assert_eq!(Rc::strong_count(&mesh), 5);
+    }
+
+    #[test]
+    fn aov_passes() {
+        let mut scene = Scene::new(Vec::new(), Framebuffer::new(32, 32, Format::Rgb8));
+        let mesh = scene.add_object(obj::Obj::from_file("obj/african_head.obj").unwrap());
+        scene.add_instance(mesh, Transform::new(geo::Vec3f::new(0.5, 0., -1.), geo::Vec3f::new(0., 0., 0.),
+                                                geo::Vec3f::new(1., 1., 1.)));
+        scene.enable_aov(Aov::ObjectId);
+        scene.enable_aov(Aov::Normal);
+        assert!(scene.aov(Aov::Uv).is_none());
+        scene.draw();
+        let ids = scene.aov(Aov::ObjectId).unwrap();
+        let normals = scene.aov(Aov::Normal).unwrap();
+        let mut seen = [false; 2];
+        for y in 0..32 {
+            for x in 0..32 {
+                let id = ids.get(x, y).unwrap()[0];
+                let covered = scene.framebuffer().depth_buffer().get(x, y).unwrap() > -INFINITY;
+                assert_eq!(id >= 0., covered);
+                if covered {
+                    seen[id as usize] = true;
+                    let n = normals.get(x, y).unwrap();
+                    assert!(((n[0]*n[0] + n[1]*n[1] + n[2]*n[2]).sqrt() - 1.).abs() < 1e-6);
+                }
+            }
+        }
+        assert_eq!(seen, [true, true]);
+    }
+
+    #[test]
+    fn pick_face() {
+        let (width, height) = (48, 32);
+        let mut scene = Scene::new(Vec::new(), Framebuffer::new(width, height, Format::Rgb8));
+        let mesh = scene.add_object(obj::Obj::from_file("obj/african_head.obj").unwrap());
+        scene.add_instance(mesh, Transform::new(geo::Vec3f::new(0.6, 0.6, 1.), geo::Vec3f::new(0., 0., 0.),
+                                                geo::Vec3f::new(0.3, 0.3, 0.3)));
+        scene.set_camera(Camera::new(geo::Vec3f::new(0.5, 0.5, 3.), geo::Vec3f::new(0., 0., 0.),
+                                     geo::Vec3f::new(0., 1., 0.)));
+        scene.draw();
+        assert!(scene.pick(0, height - 1).is_none());
+        assert!(scene.pick(width, 0).is_none());
+        let transform = scene.active_camera().transform();
+        let image = scene.framebuffer().to_image();
+        let mut seen = [false; 2];
+        for y in 0..height {
+            for x in 0..width {
+                let pick = match scene.pick(x, y) {
+                    Some(pick) => pick,
+                    None       => {
+                        assert_eq!(image.get_pixel(x, y).data, [0, 0, 0, 255]);
+                        continue;
+                    },
+                };
+                seen[pick.object] = true;
+                let bc = pick.barycentric;
+                assert!(bc.x > -0.2 && bc.y > -0.2 && bc.z > -0.2 && (bc.x + bc.y + bc.z - 1.).abs() < 1e-9);
+                // the hit projects back onto the picked pixel, counted from the top of the image
+                let screen = transform.project(&pick.position).unwrap();
+                let px = (screen.x + 1.)*0.5*width as f64;
+                let py = height as f64 - 1. - (screen.y + 1.)*0.5*height as f64;
+                assert!((px - x as f64).abs() < 1.5 && (py - y as f64).abs() < 1.5, "{} {} -> {} {}", x, y, px, py);
+            }
+        }
+        assert_eq!(seen, [true, true]);
+        // the small head is in front of the large one, near the top right of the image
+        assert_eq!(scene.pick(width*4/5, height/5).map(|pick| pick.object), Some(1));
+        // moving the camera after drawing does not change what was drawn where
+        let pick = scene.pick(width/2, height/2);
+        scene.set_camera(Camera::default());
+        assert_eq!(scene.pick(width/2, height/2), pick);
+    }
+
+    #[test]
+    fn frame_all() {
+        let mut scene = Scene::new(Vec::new(), Framebuffer::new(32, 32, Format::Rgb8));
+        let mesh = Rc::new(obj::Obj::from_file("obj/african_head.obj").unwrap());
+        scene.add_instance(Rc::clone(&mesh), Transform::new(geo::Vec3f::new(250., -40., 70.), geo::Vec3f::new(0., 0., 0.),
+                                                            geo::Vec3f::new(100., 100., 100.)));
+        scene.set_camera(Camera::new(geo::Vec3f::new(1., 1., 1.), geo::Vec3f::new(0., 0., 0.), geo::Vec3f::new(0., 1., 0.)));
+        scene.frame_all(0.2);
+        let sphere = scene.bounding_sphere().unwrap();
+        assert!((&sphere.center - &geo::Vec3f::new(250., -40., 70.)).norm() < 100.);
+        let camera = scene.active_camera();
+        assert_eq!(camera.center, sphere.center);
+        // the view direction is kept and everything lands inside the margin
+        let direction = (&camera.eye - &camera.center).normalize();
+        assert!((direction.dot(&geo::Vec3f::new(1., 1., 1.).normalize()) - 1.).abs() < 1e-9);
+        let transform = camera.transform();
+        let extent = mesh.vertices.iter()
+            .map(|v| transform.project(&geo::Vec3f::new(v.x*100. + 250., v.y*100. - 40., v.z*100. + 70.)).unwrap())
+            .fold(0., |extent: f64, p| extent.max(p.x.abs()).max(p.y.abs()));
+        assert!(extent > 0.5 && extent < 0.8, "{}", extent);
+    }
+
+    #[test]
+    fn alpha_cutout() {
+        let path = std::env::temp_dir().join("rustyrenderer_cutout.obj");
+        std::fs::write(&path, "v -0.5 -0.5 0\nv 0.5 -0.5 0\nv 0.5 0.5 0\nv -0.5 0.5 0\n\
+                               vt 0 0 0\nvt 1 0 0\nvt 1 1 0\nvt 0 1 0\n\
+                               f 1/1 2/2 3/3\nf 1/1 3/3 4/4\n").unwrap();
+        // the left half of the texture is fully transparent
+        let img = image::ImageRgba8(image::RgbaImage::from_fn(2, 1, |x, _| image::Rgba([255, 255, 255, 255*x as u8])));
+        let texture = texture::Texture::new(&img).with_filter(texture::Filter::Nearest);
+        let mesh = obj::Obj::from_file(path.to_str().unwrap()).unwrap().with_texture(texture).with_alpha_cutoff(0.5);
+        std::fs::remove_file(&path).unwrap();
+        let mut scene = Scene::new(vec![mesh], Framebuffer::new(32, 32, Format::Rgb8));
+        scene.enable_aov(Aov::FaceId);
+        // shading modes that ignore the texture colors still cut out the same pixels
+        for &shading in [Shading::Textured, Shading::Gouraud, Shading::Flat].iter() {
+            scene.set_shading(shading);
+            scene.draw();
+            for &(x, covered) in [(10, false), (21, true)].iter() {
+                assert_eq!(scene.pick(x, 16).is_some(), covered);
+                assert_eq!(scene.aov(Aov::FaceId).unwrap().get(x, 16).unwrap()[0] >= 0., covered);
+                let color = scene.framebuffer().to_image().get_pixel(x, 16).data;
+                assert_eq!(color, if covered { [255, 255, 255, 255] } else { [0, 0, 0, 255] }, "{:?}", shading);
+            }
+        }
+    }
+
+    /// A floor seen at a grazing angle, textured with columns four texels wide. Its corners land on
+    /// whole pixels at every size that is a multiple of 32, so that renders of different sizes agree.
+    fn floor_scene(size: u32, filter: texture::Filter) -> Scene {
+        let vertices = vec![geo::Vec3f::new(-0.875, -0.75, 0.), geo::Vec3f::new(0.875, -0.75, 0.),
+                            geo::Vec3f::new(0.875, -0.75, -3.), geo::Vec3f::new(-0.875, -0.75, -3.)];
+        let mut mesh = obj::Obj::new(vertices, vec![geo::Vec3i::new(0, 1, 2), geo::Vec3i::new(0, 2, 3)]);
+        mesh.textures = vec![geo::Vec3f::new(0., 0., 0.), geo::Vec3f::new(1., 0., 0.),
+                             geo::Vec3f::new(1., 4., 0.), geo::Vec3f::new(0., 4., 0.)];
+        mesh.face_textures = mesh.faces.clone();
+        let img = image::RgbaImage::from_fn(64, 64, |x, _| {
+            let v = if (x/4) % 2 == 0 { 255 } else { 0 };
+            image::Rgba([v, v, v, 255])
+        });
+        let mesh = mesh.with_texture(texture::Texture::new(&image::ImageRgba8(img)).with_filter(filter));
+        let mut scene = Scene::new(vec![mesh], Framebuffer::new(size, size, Format::Rgb32F));
+        scene.set_shading(Shading::Textured);
+        scene.clear_lights();
+        scene.add_light(0., -1., 0.);
+        scene.draw();
+        scene
+    }
+
+    #[test]
+    fn anisotropic_floor_matches_reference() {
+        // the reference averages 9x9 bilinear samples centered on each pixel of the covered floor
+        let (size, n) = (64, 9);
+        let reference = floor_scene(size*n, texture::Filter::Bilinear);
+        let fine = reference.framebuffer();
+        let mut pixels = Vec::new();
+        for y in 1..size {
+            for x in 1..size {
+                let samples: Vec<(u32, u32)> = (0..n*n).map(|i| (x*n + i % n - n/2, y*n + i/n - n/2)).collect();
+                if samples.iter().all(|&(sx, sy)| fine.depth_buffer().get(sx, sy).unwrap() > -INFINITY) {
+                    let sum = samples.iter().map(|&(sx, sy)| fine.color(0).unwrap().get(sx, sy).unwrap()[0]).sum::<f64>();
+                    pixels.push((x, y, sum/(n*n) as f64));
+                }
+            }
+        }
+        assert!(pixels.len() > 300, "{}", pixels.len());
+        let error = |filter: texture::Filter| {
+            let scene = floor_scene(size, filter);
+            pixels.iter()
+                .map(|&(x, y, expected)| (scene.framebuffer().color(0).unwrap().get(x, y).unwrap()[0] - expected).abs())
+                .sum::<f64>()/pixels.len() as f64
+        };
+        let (trilinear, anisotropic) = (error(texture::Filter::Trilinear), error(texture::Filter::Anisotropic(16)));
+        assert!(trilinear > 0.2 && anisotropic < 0.05, "trilinear {} anisotropic {}", trilinear, anisotropic);
+    }
+
+    #[test]
+    fn mesh_edge_outlines() {
+        // a square in front of a smaller one that it hides completely
+        let square = |size: f64, z: f64| {
+            let vertices = vec![geo::Vec3f::new(-size, -size, z), geo::Vec3f::new(size, -size, z),
+                                geo::Vec3f::new(size, size, z), geo::Vec3f::new(-size, size, z)];
+            obj::Obj::new(vertices, vec![geo::Vec3i::new(0, 1, 2), geo::Vec3i::new(0, 2, 3)])
+        };
+        let mut scene = Scene::new(vec![square(0.5, 0.), square(0.25, -1.)], Framebuffer::new(32, 32, Format::Rgb8));
+        scene.set_outlines(Some(Outlines::new([255, 0, 0]).with_mesh_edges(true)));
+        scene.draw();
+        let image = scene.framebuffer().to_image();
+        let red = |x: u32, y: u32| image.get_pixel(x, y).data == [255, 0, 0, 255];
+        // the border of the front square is inked, its diagonal and the hidden square are not
+        assert!(red(16, 7) && red(16, 23) && red(8, 16) && red(24, 16));
+        assert!(!red(16, 16) && !red(12, 12) && !red(20, 20));
+        assert!(!red(16, 13) && !red(13, 16) && !red(19, 16));
+    }
+
+    #[test]
+    fn translucent_face_without_depth() {
+        let vertices = vec![geo::Vec3f::new(std::f64::NAN, 0., 0.), geo::Vec3f::new(1., 0., 0.),
+                            geo::Vec3f::new(0., 1., 0.), geo::Vec3f::new(-1., 0., 0.)];
+        let faces = vec![geo::Vec3i::new(0, 1, 2), geo::Vec3i::new(3, 1, 2)];
+        let mesh = obj::Obj::new(vertices, faces).with_opacity(0.5);
+        let mut scene = Scene::new(vec![mesh], Framebuffer::new(16, 16, Format::Rgb8));
+        scene.draw();
+        assert!(scene.framebuffer().to_image().raw_pixels().iter().any(|&c| c > 0));
+    }
+
+    #[test]
+    fn lights_add_up_in_float_colors() {
+        let vertices = vec![geo::Vec3f::new(-1., 0., 0.), geo::Vec3f::new(1., 0., 0.), geo::Vec3f::new(0., 1., 0.)];
+        let mesh = obj::Obj::new(vertices, vec![geo::Vec3i::new(0, 1, 2)]);
+        let mut scene = Scene::new(vec![mesh], Framebuffer::new(32, 32, Format::Rgb8));
+        scene.add_light(0., 0., -1.);
+        for &shading in [Shading::Flat, Shading::Gouraud].iter() {
+            scene.set_shading(shading);
+            for &(format, red) in [(Format::Rgb8, 1.), (Format::Rgb32F, 2.)].iter() {
+                scene.set_color_format(format);
+                scene.draw();
+                let color = scene.framebuffer().color(0).unwrap().get(16, 20).unwrap();
+                assert!((color[0] - red).abs() < 1e-9, "{:?} {:?}: {:?}", shading, format, color);
+            }
+        }
+    }
 }
@@ -0,0 +1,499 @@
+//! JSON scene descriptions.
+//!
+//! ```json
+//! {
+//!   "render": {"width": 800, "height": 800, "output": "out.png", "shading": "gouraud",
+//!              "background": [0, 0, 0], "camera": "front", "frame": 0.1, "transparency": "sorted",
+//!              "outline": {"color": [0, 0, 0], "crease_angle": 60, "depth_threshold": 0.05,
+//!                          "mesh_edges": false}},
+//!   "cameras": [{"name": "front", "eye": [0, 0, 3], "look_at": [0, 0, 0], "up": [0, 1, 0]}],
+//!   "lights": [{"direction": [0, 0, -1]}],
+//!   "objects": [{"name": "head", "mesh": "head.obj",
+//!                "material": {"texture": "head_diffuse.tga", "color": [255, 255, 255],
+//!                             "filter": "trilinear", "wrap": "repeat", "opacity": 1,
+//!                             "alpha_cutoff": 0.5},
+//!                "transform": {"translate": [0, 0, 0], "rotate": [0, 30, 0], "scale": 1},
+//!                "children": [{"mesh": "eye.obj", "transform": {"translate": [0.3, 0.2, 0.4]}}]}]
+//! }
+//! ```
+//!
+//! Relative paths are resolved against the directory of the scene file. Rotations are in degrees
+//! and applied about x, then y, then z. Children are placed relative to their parent, and an
+//! object without a mesh only groups its children. Objects sharing the same mesh and material are
+//! instanced from a single loaded copy. A glTF mesh (`.gltf` or `.glb`) is added with its own node
+//! hierarchy and materials, and the object's material is ignored.
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use geo;
+use gltf;
+use json;
+use json::Value;
+use mesh;
+use obj;
+use render;
+use render::{Outlines, Shading, Transparency};
+use camera::Camera;
+use transform::Transform;
+use graph::Node;
+use texture;
+use texture::{Filter, Wrap};
+use framebuffer::{Format, Framebuffer};
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(PathBuf, io::Error),
+    Parse(json::ParseError),
+    /// The document is valid JSON but `key` does not match the schema
+    Schema{key: String, message: String},
+    Load(String),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneError::Io(path, err)         => write!(f, "{}: {}", path.display(), err),
+            SceneError::Parse(err)            => write!(f, "invalid JSON at {}", err),
+            SceneError::Schema{key, message}  => write!(f, "{}: {}", key, message),
+            SceneError::Load(message)         => write!(f, "{}", message),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectDesc {
+    pub name: Option<String>,
+    pub mesh: Option<PathBuf>,
+    pub texture: Option<PathBuf>,
+    pub filter: Filter,
+    pub wrap: Wrap,
+    pub color: [u8; 3],
+    pub opacity: f64,
+    pub alpha_cutoff: Option<f64>,
+    pub transform: Transform,
+    pub children: Vec<ObjectDesc>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SceneFile {
+    pub width: u32,
+    pub height: u32,
+    pub output: Option<PathBuf>,
+    pub shading: Shading,
+    pub transparency: Transparency,
+    pub outlines: Option<Outlines>,
+    pub background: [u8; 3],
+    pub camera: Camera,
+    /// Margin to fit the whole scene into the view with, if it should be
+    pub frame: Option<f64>,
+    pub lights: Vec<geo::Vec3f>,
+    pub objects: Vec<ObjectDesc>,
+}
+
+pub fn load(path: &Path) -> Result<SceneFile, SceneError> {
+    let mut text = String::new();
+    File::open(path).and_then(|mut f| f.read_to_string(&mut text))
+        .map_err(|err| SceneError::Io(path.to_path_buf(), err))?;
+    parse(&text, path.parent().unwrap_or(Path::new("")))
+}
+
+/// Parse a scene description, resolving relative paths against `base`
+pub fn parse(text: &str, base: &Path) -> Result<SceneFile, SceneError> {
+    let doc = json::parse(text).map_err(SceneError::Parse)?;
+    members(&doc, "", &["render", "cameras", "lights", "objects"])?;
+
+    let mut cameras = Vec::<(Option<String>, Camera)>::new();
+    if let Some(list) = doc.get("cameras") {
+        for (i, value) in array(list, "cameras")?.iter().enumerate() {
+            let path = format!("cameras[{}]", i);
+            members(value, &path, &["name", "eye", "look_at", "up"])?;
+            let mut camera = Camera::default();
+            if let Some(eye) = value.get("eye") {
+                camera.eye = vec3(eye, &join(&path, "eye"))?;
+            }
+            if let Some(center) = value.get("look_at") {
+                camera.center = vec3(center, &join(&path, "look_at"))?;
+            }
+            if let Some(up) = value.get("up") {
+                camera.up = vec3(up, &join(&path, "up"))?;
+            }
+            if camera.eye == camera.center {
+                return Err(schema(&path, "eye and look_at coincide"));
+            }
+            let name = match value.get("name") {
+                Some(name) => Some(String::from(string(name, &join(&path, "name"))?)),
+                None       => None,
+            };
+            cameras.push((name, camera));
+        }
+    }
+
+    let mut scene = SceneFile{width: 800, height: 800, output: None, shading: Shading::Flat,
+                              transparency: Transparency::Sorted, outlines: None, background: [0, 0, 0],
+                              camera: cameras.first().map(|c| c.1).unwrap_or_default(), frame: None,
+                              lights: vec![geo::Vec3f::new(0., 0., -1.)], objects: Vec::new()};
+
+    if let Some(render) = doc.get("render") {
+        members(render, "render", &["width", "height", "output", "shading", "background", "camera",
+                                   "frame", "transparency", "outline"])?;
+        if let Some(width) = render.get("width") {
+            scene.width = dimension(width, "render.width")?;
+        }
+        if let Some(height) = render.get("height") {
+            scene.height = dimension(height, "render.height")?;
+        }
+        if let Some(output) = render.get("output") {
+            scene.output = Some(base.join(string(output, "render.output")?));
+        }
+        if let Some(shading) = render.get("shading") {
+            scene.shading = string(shading, "render.shading")?.parse()
+                .map_err(|err: String| schema("render.shading", &err))?;
+        }
+        if let Some(margin) = render.get("frame") {
+            let margin = number(margin, "render.frame")?;
+            if margin < 0. || margin >= 1. {
+                return Err(schema("render.frame", "must be at least 0 and less than 1"));
+            }
+            scene.frame = Some(margin);
+        }
+        if let Some(transparency) = render.get("transparency") {
+            scene.transparency = string(transparency, "render.transparency")?.parse()
+                .map_err(|err: String| schema("render.transparency", &err))?;
+        }
+        if let Some(outline) = render.get("outline") {
+            members(outline, "render.outline", &["color", "crease_angle", "depth_threshold", "mesh_edges"])?;
+            let mut outlines = Outlines::new([0, 0, 0]);
+            if let Some(c) = outline.get("color") {
+                outlines.color = color(c, "render.outline.color")?;
+            }
+            if let Some(angle) = outline.get("crease_angle") {
+                outlines = outlines.with_crease_angle(number(angle, "render.outline.crease_angle")?.to_radians());
+            }
+            if let Some(threshold) = outline.get("depth_threshold") {
+                outlines = outlines.with_depth_threshold(number(threshold, "render.outline.depth_threshold")?);
+            }
+            if let Some(mesh_edges) = outline.get("mesh_edges") {
+                outlines = outlines.with_mesh_edges(boolean(mesh_edges, "render.outline.mesh_edges")?);
+            }
+            scene.outlines = Some(outlines);
+        }
+        if let Some(background) = render.get("background") {
+            scene.background = color(background, "render.background")?;
+        }
+        if let Some(name) = render.get("camera") {
+            let name = string(name, "render.camera")?;
+            scene.camera = cameras.iter().find(|c| c.0.as_ref().map(|n| n.as_str()) == Some(name))
+                .map(|c| c.1)
+                .ok_or_else(|| schema("render.camera", &format!("no camera named '{}'", name)))?;
+        }
+    }
+
+    if let Some(list) = doc.get("lights") {
+        scene.lights.clear();
+        for (i, value) in array(list, "lights")?.iter().enumerate() {
+            let path = format!("lights[{}]", i);
+            members(value, &path, &["direction"])?;
+            let direction = value.get("direction").ok_or_else(|| schema(&join(&path, "direction"), "missing"))?;
+            let direction = vec3(direction, &join(&path, "direction"))?;
+            if direction == geo::Vec3f::new(0., 0., 0.) {
+                return Err(schema(&join(&path, "direction"), "must not be zero"));
+            }
+            scene.lights.push(direction);
+        }
+    }
+
+    if let Some(list) = doc.get("objects") {
+        for (i, value) in array(list, "objects")?.iter().enumerate() {
+            let path = format!("objects[{}]", i);
+            scene.objects.push(object(value, &path, base)?);
+        }
+    }
+    Ok(scene)
+}
+
+impl ObjectDesc {
+    fn same_material(&self, other: &ObjectDesc) -> bool {
+        self.texture == other.texture && self.filter == other.filter && self.wrap == other.wrap
+            && self.color == other.color && self.opacity == other.opacity
+            && self.alpha_cutoff == other.alpha_cutoff
+    }
+}
+
+impl SceneFile {
+
+    /// Load all meshes and textures and set up a scene with a framebuffer of the requested size
+    pub fn build(&self) -> Result<render::Scene, SceneError> {
+        let framebuffer = Framebuffer::new(self.width, self.height, Format::Rgb8);
+        let mut scene = render::Scene::new(Vec::new(), framebuffer);
+        let mut loaded = Vec::new();
+        for desc in self.objects.iter() {
+            scene.add_node(SceneFile::build_node(desc, &mut loaded)?);
+        }
+        scene.clear_lights();
+        for light in self.lights.iter() {
+            scene.add_light(light.x, light.y, light.z);
+        }
+        scene.set_camera(self.camera);
+        if let Some(margin) = self.frame {
+            scene.frame_all(margin);
+        }
+        scene.set_shading(self.shading);
+        scene.set_transparency(self.transparency);
+        scene.set_outlines(self.outlines);
+        scene.set_background(self.background);
+        Ok(scene)
+    }
+
+    fn build_node<'d>(desc: &'d ObjectDesc, loaded: &mut Vec<(&'d ObjectDesc, Rc<obj::Obj>)>)
+        -> Result<Node, SceneError>
+    {
+        let mut node = Node::new(desc.name.as_ref().map(|n| n.as_str()).unwrap_or(""))
+            .with_transform(desc.transform);
+        if let Some(path) = desc.mesh.as_ref().filter(|path| gltf::is_gltf(path)) {
+            // the materials of a glTF file replace the object's
+            let scene = gltf::load(path)
+                .map_err(|err| SceneError::Load(format!("failed to load scene '{}': {}", path.display(), err)))?;
+            node = node.with_child(scene);
+        } else if desc.mesh.is_some() {
+            let shared = loaded.iter()
+                .find(|(other, _)| other.mesh == desc.mesh && other.same_material(desc))
+                .map(|(_, mesh)| Rc::clone(mesh));
+            let mesh = match shared {
+                Some(mesh) => mesh,
+                None       => {
+                    let mesh = Rc::new(SceneFile::load_mesh(desc)?);
+                    loaded.push((desc, Rc::clone(&mesh)));
+                    mesh
+                },
+            };
+            node = node.with_mesh(mesh);
+        }
+        for child in desc.children.iter() {
+            node = node.with_child(SceneFile::build_node(child, loaded)?);
+        }
+        Ok(node)
+    }
+
+    fn load_mesh(desc: &ObjectDesc) -> Result<obj::Obj, SceneError> {
+        let mesh = desc.mesh.as_ref().map(|m| m.to_string_lossy()).unwrap_or_default();
+        let mut obj = mesh::load(Path::new(&*mesh))
+            .map_err(|err| SceneError::Load(format!("failed to load mesh '{}': {}", mesh, err)))?;
+        if let Some(ref texture) = desc.texture {
+            let path = texture.to_string_lossy();
+            let texture = texture::Texture::load(texture)
+                .map_err(|err| SceneError::Load(format!("failed to load texture '{}': {}", path, err)))?;
+            obj = obj.with_texture(texture.with_filter(desc.filter).with_wrap(desc.wrap));
+        }
+        if let Some(threshold) = desc.alpha_cutoff {
+            obj = obj.with_alpha_cutoff(threshold);
+        }
+        Ok(obj.with_color(desc.color).with_opacity(desc.opacity))
+    }
+
+}
+
+fn object(value: &Value, path: &str, base: &Path) -> Result<ObjectDesc, SceneError> {
+    members(value, path, &["name", "mesh", "material", "transform", "children"])?;
+    let mut desc = ObjectDesc{name: None, mesh: None, texture: None, filter: Filter::Trilinear,
+                              wrap: Wrap::Repeat, color: [255, 255, 255], opacity: 1.,
+                              alpha_cutoff: None,
+                              transform: Transform::identity(), children: Vec::new()};
+    if let Some(name) = value.get("name") {
+        desc.name = Some(String::from(string(name, &join(path, "name"))?));
+    }
+    if let Some(mesh) = value.get("mesh") {
+        desc.mesh = Some(base.join(string(mesh, &join(path, "mesh"))?));
+    }
+    if let Some(material) = value.get("material") {
+        let path = join(path, "material");
+        members(material, &path, &["texture", "color", "filter", "wrap", "opacity", "alpha_cutoff"])?;
+        if let Some(texture) = material.get("texture") {
+            desc.texture = Some(base.join(string(texture, &join(&path, "texture"))?));
+        }
+        if let Some(filter) = material.get("filter") {
+            let key = join(&path, "filter");
+            desc.filter = string(filter, &key)?.parse().map_err(|err: String| schema(&key, &err))?;
+        }
+        if let Some(wrap) = material.get("wrap") {
+            let key = join(&path, "wrap");
+            desc.wrap = string(wrap, &key)?.parse().map_err(|err: String| schema(&key, &err))?;
+        }
+        if let Some(c) = material.get("color") {
+            desc.color = color(c, &join(&path, "color"))?;
+        }
+        if let Some(opacity) = material.get("opacity") {
+            let key = join(&path, "opacity");
+            desc.opacity = number(opacity, &key)?;
+            if desc.opacity < 0. || desc.opacity > 1. {
+                return Err(schema(&key, "must be between 0 and 1"));
+            }
+        }
+        if let Some(threshold) = material.get("alpha_cutoff") {
+            let key = join(&path, "alpha_cutoff");
+            let threshold = number(threshold, &key)?;
+            if threshold < 0. || threshold > 1. {
+                return Err(schema(&key, "must be between 0 and 1"));
+            }
+            desc.alpha_cutoff = Some(threshold);
+        }
+    }
+    if let Some(transform) = value.get("transform") {
+        let path = join(path, "transform");
+        members(transform, &path, &["translate", "rotate", "scale"])?;
+        if let Some(translate) = transform.get("translate") {
+            desc.transform.translation = vec3(translate, &join(&path, "translate"))?;
+        }
+        if let Some(rotate) = transform.get("rotate") {
+            let degrees = vec3(rotate, &join(&path, "rotate"))?;
+            desc.transform.rotation = geo::Vec3f::new(degrees.x.to_radians(), degrees.y.to_radians(),
+                                                      degrees.z.to_radians());
+        }
+        if let Some(scale) = transform.get("scale") {
+            let key = join(&path, "scale");
+            let scale = match scale.as_f64() {
+                Some(s) => geo::Vec3f::new(s, s, s),
+                None    => vec3(scale, &key)
+                    .map_err(|_| schema(&key, &format!("expected a number or an array of 3 numbers, found {}",
+                                                        scale.type_name())))?,
+            };
+            if scale.x == 0. || scale.y == 0. || scale.z == 0. {
+                return Err(schema(&key, "must not be zero"));
+            }
+            desc.transform.scale = scale;
+        }
+    }
+    if let Some(children) = value.get("children") {
+        let key = join(path, "children");
+        for (i, child) in array(children, &key)?.iter().enumerate() {
+            desc.children.push(object(child, &format!("{}[{}]", key, i), base)?);
+        }
+    }
+    Ok(desc)
+}
+
+fn schema(key: &str, message: &str) -> SceneError {
+    SceneError::Schema{key: String::from(key), message: String::from(message)}
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        String::from(key)
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+/// Check that `value` is an object with only `allowed` keys
+fn members<'v>(value: &'v Value, path: &str, allowed: &[&str]) -> Result<&'v [(String, Value)], SceneError> {
+    let key = if path.is_empty() { "<root>" } else { path };
+    let members = value.as_object()
+        .ok_or_else(|| schema(key, &format!("expected an object, found {}", value.type_name())))?;
+    for (k, _) in members.iter() {
+        if !allowed.contains(&k.as_str()) {
+            return Err(schema(&join(path, k), &format!("unknown key, expected one of: {}", allowed.join(", "))));
+        }
+    }
+    Ok(members)
+}
+
+fn array<'v>(value: &'v Value, path: &str) -> Result<&'v [Value], SceneError> {
+    value.as_array().ok_or_else(|| schema(path, &format!("expected an array, found {}", value.type_name())))
+}
+
+fn string<'v>(value: &'v Value, path: &str) -> Result<&'v str, SceneError> {
+    value.as_str().ok_or_else(|| schema(path, &format!("expected a string, found {}", value.type_name())))
+}
+
+fn number(value: &Value, path: &str) -> Result<f64, SceneError> {
+    value.as_f64().ok_or_else(|| schema(path, &format!("expected a number, found {}", value.type_name())))
+}
+
+fn boolean(value: &Value, path: &str) -> Result<bool, SceneError> {
+    value.as_bool().ok_or_else(|| schema(path, &format!("expected a boolean, found {}", value.type_name())))
+}
+
+fn dimension(value: &Value, path: &str) -> Result<u32, SceneError> {
+    let n = number(value, path)?;
+    if n < 1. || n.fract() != 0. || n > u32::max_value() as f64 {
+        return Err(schema(path, "expected a positive integer"));
+    }
+    Ok(n as u32)
+}
+
+fn vec3(value: &Value, path: &str) -> Result<geo::Vec3f, SceneError> {
+    let values = array(value, path)?;
+    if values.len() != 3 {
+        return Err(schema(path, &format!("expected 3 numbers, found {}", values.len())));
+    }
+    let mut v = [0.; 3];
+    for (i, value) in values.iter().enumerate() {
+        v[i] = number(value, &format!("{}[{}]", path, i))?;
+    }
+    Ok(geo::Vec3f::from(&v))
+}
+
+fn color(value: &Value, path: &str) -> Result<[u8; 3], SceneError> {
+    let v = vec3(value, path)?;
+    let mut color = [0u8; 3];
+    for (i, c) in [v.x, v.y, v.z].iter().enumerate() {
+        if *c < 0. || *c > 255. || c.fract() != 0. {
+            return Err(schema(&format!("{}[{}]", path, i), "expected an integer between 0 and 255"));
+        }
+        color[i] = *c as u8;
+    }
+    Ok(color)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema_key(text: &str) -> String {
+        match parse(text, Path::new("")) {
+            Err(SceneError::Schema{key, ..}) => key,
+            other                             => panic!("expected schema error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_scene() {
+        let scene = parse(r#"{
+            "render": {"width": 320, "height": 240, "shading": "gouraud", "camera": "side",
+                       "background": [10, 20, 30], "outline": {"mesh_edges": true}},
+            "cameras": [{"name": "front"}, {"name": "side", "eye": [3, 0, 0]}],
+            "lights": [{"direction": [0, -1, 0]}],
+            "objects": [{"mesh": "head.obj", "transform": {"translate": [1, 0, 0], "scale": 2}}]
+        }"#, Path::new("assets")).unwrap();
+        assert_eq!((scene.width, scene.height), (320, 240));
+        assert_eq!(scene.shading, Shading::Gouraud);
+        assert_eq!(scene.background, [10, 20, 30]);
+        assert_eq!(scene.outlines.map(|outlines| outlines.mesh_edges), Some(true));
+        assert_eq!(scene.camera.eye, geo::Vec3f::new(3., 0., 0.));
+        assert_eq!(scene.lights, vec![geo::Vec3f::new(0., -1., 0.)]);
+        assert_eq!(scene.objects[0].mesh, Some(Path::new("assets/head.obj").to_path_buf()));
+        assert_eq!(scene.objects[0].transform.matrix().transform_point(&geo::Vec3f::new(1., 1., 1.)),
+                   geo::Vec3f::new(3., 2., 2.));
+    }
+
+    #[test]
+    fn schema_errors() {
+        assert_eq!(schema_key(r#"{"objects": [{"mesh": "a.obj"}, {"mesh": 3}]}"#), "objects[1].mesh");
+        assert_eq!(schema_key(r#"{"objects": [{"mesh": "a.obj", "transfrom": {}}]}"#), "objects[0].transfrom");
+        assert_eq!(schema_key(r#"{"objects": [{"mesh": "a.obj", "transform": {"scale": [1, 2]}}]}"#),
+                   "objects[0].transform.scale");
+        assert_eq!(schema_key(r#"{"render": {"background": [0, 256, 0]}}"#), "render.background[1]");
+        assert_eq!(schema_key(r#"{"render": {"camera": "top"}}"#), "render.camera");
+        assert_eq!(schema_key(r#"{"objects": [{"material": {"filter": "cubic"}}]}"#), "objects[0].material.filter");
+        assert_eq!(schema_key(r#"{"lights": [{"direction": [0, 0, "down"]}]}"#), "lights[0].direction[2]");
+        assert_eq!(schema_key(r#"{"objects": [{"children": [{"mesh": "a.obj", "colour": 1}]}]}"#),
+                   "objects[0].children[0].colour");
+        assert_eq!(schema_key(r#"{"objects": [{"material": {"opacity": 1.5}}]}"#), "objects[0].material.opacity");
+        assert_eq!(schema_key(r#"{"render": {"transparency": "additive"}}"#), "render.transparency");
+        assert_eq!(schema_key(r#"{"render": {"outline": {"width": 2}}}"#), "render.outline.width");
+        assert_eq!(schema_key(r#"[]"#), "<root>");
+    }
+
+}
@@ -0,0 +1,233 @@
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::io::prelude::*;
+use std::path::Path;
+use std::str::FromStr;
+use geo;
+use geo::Vector;
+use mesh::{IndexedMesh, Vertex};
+use obj;
+use obj::Obj;
+use output::write_f32;
+
+/// Encodings of STL files
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StlFormat {
+    Ascii,
+    /// Little-endian, as the format prescribes
+    Binary,
+}
+
+impl FromStr for StlFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<StlFormat, String> {
+        match s {
+            "ascii"  => Ok(StlFormat::Ascii),
+            "binary" => Ok(StlFormat::Binary),
+            _        => Err(format!("unknown STL encoding '{}'", s)),
+        }
+    }
+}
+
+/// Load an STL file in either encoding. Corners at the same position are joined into shared
+/// vertices and smooth normals are computed as for OBJ files without them.
+pub fn load(path: &Path) -> io::Result<Obj> {
+    let mut bytes = Vec::new();
+    File::open(path)?.read_to_end(&mut bytes)?;
+    parse(&bytes).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), err)))
+}
+
+/// Parse the contents of an STL file, telling the encodings apart by the size binary files must have
+pub fn parse(bytes: &[u8]) -> Result<Obj, String> {
+    let corners = if is_binary(bytes) { parse_binary(bytes)? } else { parse_ascii(bytes)? };
+    let vertices = corners.into_iter().map(Vertex::new).collect::<Vec<_>>();
+    let triangles = (0..vertices.len()/3).map(|i| [3*i, 3*i + 1, 3*i + 2]).collect();
+    let mut mesh = IndexedMesh::new(vertices, triangles);
+    mesh.weld(0.);
+    mesh.remove_degenerate();
+    mesh.remove_unreferenced();
+    let mut obj = mesh.to_obj();
+    obj.compute_normals(obj::NormalWeighting::Angle, obj::DEFAULT_CREASE_ANGLE);
+    Ok(obj)
+}
+
+pub fn save(obj: &Obj, path: &Path, format: StlFormat) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    match format {
+        StlFormat::Ascii  => write_ascii(obj, &mut file),
+        StlFormat::Binary => write_binary(obj, &mut file),
+    }?;
+    file.flush()
+}
+
+pub fn write_ascii<W: Write>(obj: &Obj, w: &mut W) -> io::Result<()> {
+    writeln!(w, "solid mesh")?;
+    for i in 0..obj.faces.len() {
+        let corners = corners(obj, i);
+        let n = facet_normal(&corners);
+        writeln!(w, "  facet normal {:e} {:e} {:e}\n    outer loop", n.x, n.y, n.z)?;
+        for v in corners.iter() {
+            writeln!(w, "      vertex {:e} {:e} {:e}", v.x, v.y, v.z)?;
+        }
+        writeln!(w, "    endloop\n  endfacet")?;
+    }
+    writeln!(w, "endsolid mesh")
+}
+
+pub fn write_binary<W: Write>(obj: &Obj, w: &mut W) -> io::Result<()> {
+    let mut header = [0u8; 80];
+    header[..14].copy_from_slice(b"rustyrenderer ");
+    w.write_all(&header)?;
+    let count = obj.faces.len() as u32;
+    w.write_all(&[count as u8, (count >> 8) as u8, (count >> 16) as u8, (count >> 24) as u8])?;
+    for i in 0..obj.faces.len() {
+        let corners = corners(obj, i);
+        for v in [facet_normal(&corners), corners[0], corners[1], corners[2]].iter() {
+            write_f32(v.x, w)?;
+            write_f32(v.y, w)?;
+            write_f32(v.z, w)?;
+        }
+        // attribute byte count, unused
+        w.write_all(&[0, 0])?;
+    }
+    Ok(())
+}
+
+fn corners(obj: &Obj, i: usize) -> [geo::Vec3f; 3] {
+    let face = obj.faces[i];
+    [obj.vertices[face.x as usize], obj.vertices[face.y as usize], obj.vertices[face.z as usize]]
+}
+
+/// Outward unit normal of a counter-clockwise facet, zero for degenerate ones
+fn facet_normal(corners: &[geo::Vec3f; 3]) -> geo::Vec3f {
+    let n = (&corners[1] - &corners[0]).cross(&(&corners[2] - &corners[0]));
+    if n.norm() > 0. { n.normalize() } else { n }
+}
+
+/// Binary files are an 80 byte header, a triangle count and 50 bytes per triangle. ASCII files
+/// start with "solid", but so do the headers of many binary ones.
+fn is_binary(bytes: &[u8]) -> bool {
+    bytes.len() >= 84 && 84 + 50*read_u32(&bytes[80..84]) as usize == bytes.len()
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    bytes[0] as u32 | (bytes[1] as u32) << 8 | (bytes[2] as u32) << 16 | (bytes[3] as u32) << 24
+}
+
+fn read_f32(bytes: &[u8]) -> f64 {
+    f32::from_bits(read_u32(bytes)) as f64
+}
+
+fn parse_binary(bytes: &[u8]) -> Result<Vec<geo::Vec3f>, String> {
+    let count = read_u32(&bytes[80..84]) as usize;
+    let mut corners = Vec::with_capacity(3*count);
+    for facet in bytes[84..].chunks(50) {
+        // the stored normal is recomputed from the winding instead
+        for k in 1..4 {
+            let v = &facet[12*k..12*k + 12];
+            corners.push(geo::Vec3f::new(read_f32(&v[0..4]), read_f32(&v[4..8]), read_f32(&v[8..12])));
+        }
+    }
+    if corners.iter().any(|v| !(v.x.is_finite() && v.y.is_finite() && v.z.is_finite())) {
+        return Err(String::from("vertex with a coordinate that is not a finite number"));
+    }
+    Ok(corners)
+}
+
+fn parse_ascii(bytes: &[u8]) -> Result<Vec<geo::Vec3f>, String> {
+    let text = ::std::str::from_utf8(bytes).map_err(|_| String::from("neither binary STL nor ASCII text"))?;
+    if text.split_whitespace().next() != Some("solid") {
+        return Err(String::from("ASCII STL must start with 'solid'"));
+    }
+    let mut corners = Vec::new();
+    let mut in_loop = 0;
+    for (n, line) in text.lines().enumerate() {
+        let error = |message: &str| format!("line {}: {}", n + 1, message);
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("outer")   => in_loop = 0,
+            Some("vertex")  => {
+                let values: Vec<f64> = words.map(|w| w.parse().map_err(|_| error(&format!("invalid number '{}'", w))))
+                    .collect::<Result<_, _>>()?;
+                if values.len() != 3 {
+                    return Err(error("vertex needs three coordinates"));
+                }
+                if values.iter().any(|v| !v.is_finite()) {
+                    return Err(error("vertex with a coordinate that is not a finite number"));
+                }
+                corners.push(geo::Vec3f::new(values[0], values[1], values[2]));
+                in_loop += 1;
+            },
+            Some("endloop") => {
+                if in_loop != 3 {
+                    return Err(error(&format!("facet with {} vertices, expected 3", in_loop)));
+                }
+            },
+            _ => continue,
+        }
+    }
+    if corners.len() % 3 != 0 {
+        return Err(String::from("unterminated facet at the end of the file"));
+    }
+    Ok(corners)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A tetrahedron with outward facing, counter-clockwise faces
+    fn tetrahedron() -> Obj {
+        let vertices = vec![geo::Vec3f::new(0., 0., 0.), geo::Vec3f::new(1., 0., 0.), geo::Vec3f::new(0., 1., 0.),
+                            geo::Vec3f::new(0., 0., 1.)];
+        let faces = vec![geo::Vec3i::new(0, 2, 1), geo::Vec3i::new(0, 1, 3), geo::Vec3i::new(0, 3, 2),
+                         geo::Vec3i::new(1, 2, 3)];
+        Obj::new(vertices, faces)
+    }
+
+    fn assert_tetrahedron(obj: &Obj) {
+        assert_eq!((obj.vertices.len(), obj.faces.len()), (4, 4));
+        assert_eq!(obj.vertices[obj.faces[3].z as usize], geo::Vec3f::new(0., 0., 1.));
+        // the normals point away from the inside
+        let inside = geo::Vec3f::new(0.1, 0.1, 0.1);
+        for i in 0..obj.faces.len() {
+            let normals = obj.face_vertex_normals(i).unwrap();
+            let corner = obj.vertices[obj.faces[i].x as usize];
+            assert!(normals[0].dot(&(&corner - &inside)) > 0.);
+        }
+    }
+
+    #[test]
+    fn ascii_round_trip() {
+        let mut text = Vec::new();
+        write_ascii(&tetrahedron(), &mut text).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        assert!(text.starts_with("solid mesh\n  facet normal 0e0 0e0 -1e0\n    outer loop\n      vertex 0e0 0e0 0e0\n"));
+        assert_tetrahedron(&parse(text.as_bytes()).unwrap());
+        assert_eq!(parse(b"solid x\nfacet normal 0 0 1\nouter loop\nvertex 0 0 0\nvertex 1 0 0\nendloop\n").err(),
+                   Some(String::from("line 6: facet with 2 vertices, expected 3")));
+        assert!(parse(b"solid x\nouter loop\nvertex 0 0 zero\n").is_err());
+        assert_eq!(parse(b"solid x\nouter loop\nvertex nan inf 0\n").err(),
+                   Some(String::from("line 3: vertex with a coordinate that is not a finite number")));
+        assert_eq!("ascii".parse(), Ok(StlFormat::Ascii));
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let mut bytes = Vec::new();
+        write_binary(&tetrahedron(), &mut bytes).unwrap();
+        assert_eq!(bytes.len(), 84 + 4*50);
+        assert_eq!(&bytes[80..84], &[4, 0, 0, 0]);
+        // little-endian floats: the first facet's normal is (0, 0, -1), its second corner (0, 1, 0)
+        assert_eq!(&bytes[92..96], &[0x00, 0x00, 0x80, 0xbf]);
+        assert_eq!(&bytes[112..116], &[0x00, 0x00, 0x80, 0x3f]);
+        assert_tetrahedron(&parse(&bytes).unwrap());
+        // a binary header may begin like an ASCII file
+        bytes[..5].copy_from_slice(b"solid");
+        assert_tetrahedron(&parse(&bytes).unwrap());
+        assert!(parse(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+}
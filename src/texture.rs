@@ -0,0 +1,392 @@
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use image;
+use image::GenericImage;
+use geo;
+use tga;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Filter {
+    /// Closest texel of the full-resolution image
+    Nearest,
+    /// Weighted average of the four closest texels of the full-resolution image
+    Bilinear,
+    /// Bilinear samples from the two mip levels closest to the pixel footprint, blended
+    Trilinear,
+    /// Several trilinear samples along the major axis of the pixel footprint, at most the given number
+    Anisotropic(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Wrap {
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Filter, String> {
+        match s {
+            "nearest"   => Ok(Filter::Nearest),
+            "bilinear"  => Ok(Filter::Bilinear),
+            "trilinear" => Ok(Filter::Trilinear),
+            "anisotropic" => Ok(Filter::Anisotropic(16)),
+            _ if s.starts_with("anisotropic:") => {
+                match s["anisotropic:".len()..].parse() {
+                    Ok(ratio) if ratio >= 2 && ratio <= 16 => Ok(Filter::Anisotropic(ratio)),
+                    _ => Err(format!("anisotropic ratio in '{}' must be between 2 and 16", s)),
+                }
+            },
+            _           => Err(format!("unknown texture filter '{}'", s)),
+        }
+    }
+}
+
+impl FromStr for Wrap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Wrap, String> {
+        match s {
+            "repeat" => Ok(Wrap::Repeat),
+            "clamp"  => Ok(Wrap::Clamp),
+            "mirror" => Ok(Wrap::Mirror),
+            _        => Err(format!("unknown wrap mode '{}'", s)),
+        }
+    }
+}
+
+/// One level of the mip chain, RGBA in [0, 1] with row 0 at v = 0
+struct Level {
+    width: u32,
+    height: u32,
+    texels: Vec<[f32; 4]>,
+}
+
+impl Level {
+
+    fn texel(&self, x: i64, y: i64, wrap: Wrap) -> [f32; 4] {
+        let x = Level::wrap(x, self.width as i64, wrap);
+        let y = Level::wrap(y, self.height as i64, wrap);
+        self.texels[(x + y*self.width as i64) as usize]
+    }
+
+    fn wrap(i: i64, n: i64, wrap: Wrap) -> i64 {
+        match wrap {
+            Wrap::Repeat => ((i % n) + n) % n,
+            Wrap::Clamp  => i.max(0).min(n - 1),
+            Wrap::Mirror => {
+                let m = ((i % (2*n)) + 2*n) % (2*n);
+                if m < n { m } else { 2*n - 1 - m }
+            },
+        }
+    }
+
+    fn nearest(&self, uv: &geo::Vec2f, wrap: Wrap) -> [f64; 4] {
+        let x = (uv.x*self.width as f64).floor() as i64;
+        let y = (uv.y*self.height as f64).floor() as i64;
+        let texel = self.texel(x, y, wrap);
+        [texel[0] as f64, texel[1] as f64, texel[2] as f64, texel[3] as f64]
+    }
+
+    fn bilinear(&self, uv: &geo::Vec2f, wrap: Wrap) -> [f64; 4] {
+        let x = uv.x*self.width as f64 - 0.5;
+        let y = uv.y*self.height as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let corners = [(x0, y0, (1. - fx)*(1. - fy)), (x0 + 1, y0, fx*(1. - fy)),
+                       (x0, y0 + 1, (1. - fx)*fy), (x0 + 1, y0 + 1, fx*fy)];
+        let mut color = [0.; 4];
+        for &(x, y, weight) in corners.iter() {
+            let texel = self.texel(x, y, wrap);
+            for c in 0..4 {
+                color[c] += texel[c] as f64*weight;
+            }
+        }
+        color
+    }
+
+    /// Half-resolution copy, averaging blocks of 2x2 texels. Along an odd side the last texel of
+    /// the level is folded into the last block, which then spans three texels.
+    fn downsample(&self) -> Level {
+        let width = (self.width/2).max(1);
+        let height = (self.height/2).max(1);
+        // the source texels covered by texel i of a level with n texels
+        let span = |i: u32, n: u32, size: u32| (2*i)..if i == n - 1 { size.max(2*i + 1) } else { 2*i + 2 };
+        let mut texels = Vec::with_capacity((width*height) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let (xs, ys) = (span(x, width, self.width), span(y, height, self.height));
+                let weight = 1./((xs.end - xs.start)*(ys.end - ys.start)) as f32;
+                let mut sum = [0.; 4];
+                for sy in ys {
+                    for sx in xs.clone() {
+                        let texel = self.texel(sx as i64, sy as i64, Wrap::Clamp);
+                        for c in 0..4 {
+                            sum[c] += texel[c]*weight;
+                        }
+                    }
+                }
+                texels.push(sum);
+            }
+        }
+        Level{width, height, texels}
+    }
+
+}
+
+/// A texture image with a precomputed mip chain
+pub struct Texture {
+    levels: Vec<Level>,
+    has_alpha: bool,
+    pub filter: Filter,
+    pub wrap: Wrap,
+}
+
+impl Texture {
+
+    pub fn new(img: &image::DynamicImage) -> Texture {
+        let (width, height) = img.dimensions();
+        let mut texels = Vec::with_capacity((width*height) as usize);
+        // images are stored top row first, textures with v pointing up
+        for y in (0..height).rev() {
+            for x in 0..width {
+                let pixel = img.get_pixel(x, y).data;
+                texels.push([pixel[0] as f32/255., pixel[1] as f32/255., pixel[2] as f32/255.,
+                             pixel[3] as f32/255.]);
+            }
+        }
+        Texture::from_texels(width, height, texels)
+    }
+
+    /// Texture from a TGA image, whose rows already run from the bottom like v
+    pub fn from_tga(img: &tga::Image) -> Texture {
+        let texels = img.data.chunks(img.channels).map(|pixel| match pixel.len() {
+            1 => [pixel[0] as f32/255., pixel[0] as f32/255., pixel[0] as f32/255., 1.],
+            3 => [pixel[0] as f32/255., pixel[1] as f32/255., pixel[2] as f32/255., 1.],
+            _ => [pixel[0] as f32/255., pixel[1] as f32/255., pixel[2] as f32/255., pixel[3] as f32/255.],
+        }).collect();
+        Texture::from_texels(img.width, img.height, texels)
+    }
+
+    /// Load a texture, decoding TGA files natively and other formats with the image crate. Images
+    /// without texels are rejected, as there is nothing to sample.
+    pub fn load(path: &Path) -> io::Result<Texture> {
+        let empty = || io::Error::new(io::ErrorKind::InvalidData, format!("{}: empty image", path.display()));
+        if tga::is_tga(path) {
+            let img = tga::load(path)?;
+            if img.width == 0 || img.height == 0 {
+                return Err(empty());
+            }
+            return Ok(Texture::from_tga(&img));
+        }
+        let img = image::open(path).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?;
+        if img.width() == 0 || img.height() == 0 {
+            return Err(empty());
+        }
+        Ok(Texture::new(&img))
+    }
+
+    fn from_texels(width: u32, height: u32, texels: Vec<[f32; 4]>) -> Texture {
+        let has_alpha = texels.iter().any(|texel| texel[3] < 1.);
+        let mut levels = vec![Level{width, height, texels}];
+        while levels.last().map(|l| l.width > 1 || l.height > 1).unwrap() {
+            let next = levels.last().unwrap().downsample();
+            levels.push(next);
+        }
+        Texture{levels, has_alpha, filter: Filter::Trilinear, wrap: Wrap::Repeat}
+    }
+
+    pub fn with_filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    pub fn with_wrap(mut self, wrap: Wrap) -> Self {
+        self.wrap = wrap;
+        self
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.levels[0].width, self.levels[0].height)
+    }
+
+    /// Whether any texel is not fully opaque
+    pub fn has_alpha(&self) -> bool {
+        self.has_alpha
+    }
+
+    pub fn mip_levels(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// Level of detail for a pixel whose footprint in uv space is spanned by `duv_dx` and `duv_dy`
+    pub fn lod(&self, duv_dx: &geo::Vec2f, duv_dy: &geo::Vec2f) -> f64 {
+        let (width, height) = self.dimensions();
+        let scale = |d: &geo::Vec2f| ((d.x*width as f64).powi(2) + (d.y*height as f64).powi(2)).sqrt();
+        scale(duv_dx).max(scale(duv_dy)).max(1e-12).log2()
+    }
+
+    /// Filtered RGBA color at `uv` for a pixel footprint spanned by `duv_dx` and `duv_dy`
+    pub fn sample(&self, uv: &geo::Vec2f, duv_dx: &geo::Vec2f, duv_dy: &geo::Vec2f) -> [f64; 4] {
+        match self.filter {
+            Filter::Nearest   => self.levels[0].nearest(uv, self.wrap),
+            Filter::Bilinear  => self.levels[0].bilinear(uv, self.wrap),
+            Filter::Trilinear => self.sample_lod(uv, self.lod(duv_dx, duv_dy)),
+            Filter::Anisotropic(max_ratio) => self.sample_anisotropic(uv, duv_dx, duv_dy, max_ratio),
+        }
+    }
+
+    /// Average of trilinear samples spread along the longer footprint axis. The mip level is picked
+    /// from the shorter axis, unless that needs more than `max_ratio` samples.
+    fn sample_anisotropic(&self, uv: &geo::Vec2f, duv_dx: &geo::Vec2f, duv_dy: &geo::Vec2f,
+                          max_ratio: u32) -> [f64; 4] {
+        let (width, height) = self.dimensions();
+        let scale = |d: &geo::Vec2f| ((d.x*width as f64).powi(2) + (d.y*height as f64).powi(2)).sqrt();
+        let (px, py) = (scale(duv_dx), scale(duv_dy));
+        let (major, pmax, pmin) = if px >= py { (duv_dx, px, py) } else { (duv_dy, py, px) };
+        let samples = (pmax/pmin.max(1e-12)).ceil().min(max_ratio.max(1) as f64).max(1.);
+        let lod = (pmax/samples).max(1e-12).log2();
+        let n = samples as usize;
+        let mut color = [0.; 4];
+        for i in 0..n {
+            let t = (i as f64 + 0.5)/samples - 0.5;
+            let texel = self.sample_lod(&geo::Vec2f::new(uv.x + major.x*t, uv.y + major.y*t), lod);
+            for c in 0..4 {
+                color[c] += texel[c]/samples;
+            }
+        }
+        color
+    }
+
+    /// Trilinear sample at a fractional mip level
+    pub fn sample_lod(&self, uv: &geo::Vec2f, lod: f64) -> [f64; 4] {
+        let lod = lod.max(0.).min((self.levels.len() - 1) as f64);
+        let lower = lod.floor() as usize;
+        let upper = (lower + 1).min(self.levels.len() - 1);
+        let t = lod - lower as f64;
+        let a = self.levels[lower].bilinear(uv, self.wrap);
+        let b = self.levels[upper].bilinear(uv, self.wrap);
+        [a[0] + (b[0] - a[0])*t, a[1] + (b[1] - a[1])*t, a[2] + (b[2] - a[2])*t, a[3] + (b[3] - a[3])*t]
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Texture whose lower half is black and upper half white
+    fn stripes(width: u32, height: u32) -> Texture {
+        let mut img = image::RgbaImage::new(width, height);
+        for (_, y, pixel) in img.enumerate_pixels_mut() {
+            let v = if y < height/2 { 255 } else { 0 };
+            *pixel = image::Rgba([v, v, v, 255]);
+        }
+        Texture::new(&image::ImageRgba8(img))
+    }
+
+    fn uv(u: f64, v: f64) -> geo::Vec2f {
+        geo::Vec2f::new(u, v)
+    }
+
+    #[test]
+    fn mip_chain() {
+        let texture = stripes(8, 4);
+        assert_eq!(texture.mip_levels(), 4);
+        assert_eq!(texture.levels[1].width, 4);
+        assert_eq!(texture.levels[3].height, 1);
+        assert!((texture.levels[3].texels[0][0] - 0.5).abs() < 1e-6);
+        // the last column of an odd level is not dropped
+        let img = image::RgbaImage::from_fn(3, 1, |x, _| image::Rgba([if x == 2 { 255 } else { 0 }, 0, 0, 255]));
+        let texture = Texture::new(&image::ImageRgba8(img));
+        assert_eq!((texture.levels[1].width, texture.levels[1].height), (1, 1));
+        assert!((texture.levels[1].texels[0][0] - 1./3.).abs() < 1e-6);
+    }
+
+    #[test]
+    fn filters() {
+        let texture = stripes(4, 4).with_filter(Filter::Nearest).with_wrap(Wrap::Clamp);
+        let zero = uv(0., 0.);
+        assert_eq!(texture.sample(&uv(0.5, 0.1), &zero, &zero)[0], 0.);
+        assert_eq!(texture.sample(&uv(0.5, 0.9), &zero, &zero)[0], 1.);
+        let texture = texture.with_filter(Filter::Bilinear);
+        assert!((texture.sample(&uv(0.5, 0.5), &zero, &zero)[0] - 0.5).abs() < 1e-6);
+        let texture = texture.with_filter(Filter::Trilinear);
+        let footprint = uv(0., 1.);
+        assert!((texture.sample(&uv(0.5, 0.9), &footprint, &footprint)[0] - 0.5).abs() < 1e-6);
+        assert_eq!(texture.lod(&uv(0.5, 0.), &uv(0., 0.)), 1.);
+    }
+
+    #[test]
+    fn wrap_modes() {
+        assert_eq!(Level::wrap(5, 4, Wrap::Repeat), 1);
+        assert_eq!(Level::wrap(-1, 4, Wrap::Repeat), 3);
+        assert_eq!(Level::wrap(5, 4, Wrap::Clamp), 3);
+        assert_eq!(Level::wrap(-1, 4, Wrap::Clamp), 0);
+        assert_eq!(Level::wrap(4, 4, Wrap::Mirror), 3);
+        assert_eq!(Level::wrap(-1, 4, Wrap::Mirror), 0);
+        assert_eq!(Level::wrap(9, 4, Wrap::Mirror), 1);
+    }
+
+    #[test]
+    fn empty_image() {
+        let path = std::env::temp_dir().join("rustyrenderer_empty.tga");
+        tga::save(&tga::Image::new(0, 0, 3), &path, false).unwrap();
+        let err = Texture::load(&path).err().unwrap();
+        assert!(err.to_string().ends_with("empty image"));
+    }
+
+    /// Box-filtered average of the full-resolution texture over the footprint parallelogram
+    fn supersampled(texture: &Texture, uv: &geo::Vec2f, duv_dx: &geo::Vec2f, duv_dy: &geo::Vec2f) -> f64 {
+        let n = 64;
+        let mut sum = 0.;
+        for i in 0..n {
+            for j in 0..n {
+                let a = (i as f64 + 0.5)/n as f64 - 0.5;
+                let b = (j as f64 + 0.5)/n as f64 - 0.5;
+                let p = geo::Vec2f::new(uv.x + duv_dx.x*a + duv_dy.x*b, uv.y + duv_dx.y*a + duv_dy.y*b);
+                sum += texture.levels[0].bilinear(&p, texture.wrap)[0];
+            }
+        }
+        sum/(n*n) as f64
+    }
+
+    #[test]
+    fn anisotropic_matches_reference() {
+        // one-texel columns along u, four-texel stripes along v
+        let mut img = image::RgbaImage::new(64, 64);
+        for (x, y, pixel) in img.enumerate_pixels_mut() {
+            let v = if x % 2 == 0 && (y/4) % 2 == 0 { 255 } else { 0 };
+            *pixel = image::Rgba([v, v, v, 255]);
+        }
+        let texture = Texture::new(&image::ImageRgba8(img));
+        // a floor pixel at a grazing angle: 16 texels wide, one texel deep
+        let (duv_dx, duv_dy) = (uv(16./64., 0.), uv(0., 1./64.));
+        let error = |texture: &Texture| {
+            (0..8).map(|i| {
+                let p = uv(0.3 + i as f64*0.05, (4.*i as f64 + 2.)/64.);
+                (texture.sample(&p, &duv_dx, &duv_dy)[0] - supersampled(texture, &p, &duv_dx, &duv_dy)).abs()
+            }).fold(0., f64::max)
+        };
+        let texture = texture.with_filter(Filter::Trilinear);
+        assert!(error(&texture) > 0.2);
+        let texture = texture.with_filter(Filter::Anisotropic(2));
+        let coarse = error(&texture);
+        let texture = texture.with_filter(Filter::Anisotropic(16));
+        assert!(error(&texture) < 0.05);
+        assert!(error(&texture) < coarse);
+        // a ratio of zero takes a single sample like trilinear filtering
+        let p = uv(0.3, 0.1);
+        let texture = texture.with_filter(Filter::Anisotropic(0));
+        let single = texture.sample(&p, &duv_dx, &duv_dy);
+        let texture = texture.with_filter(Filter::Trilinear);
+        assert_eq!(single, texture.sample(&p, &duv_dx, &duv_dy));
+        assert_eq!("anisotropic:8".parse(), Ok(Filter::Anisotropic(8)));
+        assert!("anisotropic:32".parse::<Filter>().is_err());
+    }
+
+}
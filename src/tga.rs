@@ -0,0 +1,267 @@
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::io::prelude::*;
+use std::path::Path;
+
+/// An 8-bit image with 1 (gray), 3 (RGB) or 4 (RGBA) channels. Row 0 is at the bottom, as in
+/// textures, where v points up, and in the framebuffer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub channels: usize,
+    pub data: Vec<u8>,
+}
+
+impl Image {
+
+    pub fn new(width: u32, height: u32, channels: usize) -> Image {
+        Image{width, height, channels, data: vec![0; width as usize*height as usize*channels]}
+    }
+
+    /// Channels of the pixel at (x, y), counting rows from the bottom
+    pub fn pixel(&self, x: u32, y: u32) -> &[u8] {
+        let i = (x as usize + y as usize*self.width as usize)*self.channels;
+        &self.data[i..i + self.channels]
+    }
+
+    pub fn pixel_mut(&mut self, x: u32, y: u32) -> &mut [u8] {
+        let i = (x as usize + y as usize*self.width as usize)*self.channels;
+        &mut self.data[i..i + self.channels]
+    }
+
+}
+
+pub fn is_tga(path: &Path) -> bool {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) => ext.eq_ignore_ascii_case("tga"),
+        None      => false,
+    }
+}
+
+pub fn load(path: &Path) -> io::Result<Image> {
+    let mut bytes = Vec::new();
+    File::open(path)?.read_to_end(&mut bytes)?;
+    decode(&bytes).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), err)))
+}
+
+/// Decode an uncompressed or run-length encoded gray, RGB or RGBA image. The origin bits of the
+/// header decide the order of the stored rows and columns.
+pub fn decode(bytes: &[u8]) -> Result<Image, String> {
+    if bytes.len() < 18 {
+        return Err(String::from("truncated header"));
+    }
+    let (id_length, map_type, kind) = (bytes[0] as usize, bytes[1], bytes[2]);
+    let (map_length, map_bits) = (read_u16(&bytes[5..7]) as usize, bytes[7] as usize);
+    let (width, height) = (read_u16(&bytes[12..14]) as u32, read_u16(&bytes[14..16]) as u32);
+    let (depth, descriptor) = (bytes[16], bytes[17]);
+    let (rle, gray) = match kind {
+        2  => (false, false),
+        3  => (false, true),
+        10 => (true, false),
+        11 => (true, true),
+        1 | 9 => return Err(String::from("color-mapped images are not supported")),
+        _  => return Err(format!("unsupported image type {}", kind)),
+    };
+    let channels = match (gray, depth) {
+        (true, 8)   => 1,
+        (false, 24) => 3,
+        (false, 32) => 4,
+        _ => return Err(format!("unsupported depth of {} bits for a {} image", depth,
+                                if gray { "grayscale" } else { "true-color" })),
+    };
+    // true-color images may still carry a color map, which is skipped
+    let start = 18 + id_length + if map_type == 1 { map_length*((map_bits + 7)/8) } else { 0 };
+    let data = bytes.get(start..).ok_or_else(|| String::from("truncated color map"))?;
+    let size = width as usize*height as usize*channels;
+    let mut pixels = if rle {
+        decode_rle(data, channels, size)?
+    } else {
+        data.get(..size).ok_or_else(|| String::from("truncated pixel data"))?.to_vec()
+    };
+    // pixels are stored as BGR(A)
+    if channels > 1 {
+        for pixel in pixels.chunks_mut(channels) {
+            pixel.swap(0, 2);
+        }
+    }
+    let mut image = Image{width, height, channels, data: pixels};
+    let (right_to_left, top_to_bottom) = (descriptor & 0x10 != 0, descriptor & 0x20 != 0);
+    if right_to_left || top_to_bottom {
+        let stored = image.clone();
+        for y in 0..height {
+            for x in 0..width {
+                let sx = if right_to_left { width - 1 - x } else { x };
+                let sy = if top_to_bottom { height - 1 - y } else { y };
+                image.pixel_mut(x, y).copy_from_slice(stored.pixel(sx, sy));
+            }
+        }
+    }
+    Ok(image)
+}
+
+/// Expand packets until `size` bytes are decoded. Packets may cross rows; a last one running past
+/// the end of the image is cut off.
+fn decode_rle(data: &[u8], channels: usize, size: usize) -> Result<Vec<u8>, String> {
+    let truncated = || String::from("truncated run-length encoded data");
+    // a packet of at most 1 + channels bytes expands to at most 128 pixels, so a header claiming
+    // more than the data can hold does not reserve more than that
+    let mut pixels = Vec::with_capacity(size.min(data.len()*128));
+    let mut i = 0;
+    while pixels.len() < size {
+        let header = *data.get(i).ok_or_else(truncated)?;
+        let count = (header & 0x7f) as usize + 1;
+        i += 1;
+        if header & 0x80 != 0 {
+            let pixel = data.get(i..i + channels).ok_or_else(truncated)?;
+            for _ in 0..count {
+                pixels.extend_from_slice(pixel);
+            }
+            i += channels;
+        } else {
+            pixels.extend_from_slice(data.get(i..i + count*channels).ok_or_else(truncated)?);
+            i += count*channels;
+        }
+    }
+    pixels.truncate(size);
+    Ok(pixels)
+}
+
+pub fn save(image: &Image, path: &Path, rle: bool) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    encode(image, &mut file, rle)?;
+    file.flush()
+}
+
+/// Encode with the origin at the bottom left, so rows are written in the order they are stored.
+/// Run-length packets do not cross rows, as the format recommends.
+pub fn encode<W: Write>(image: &Image, w: &mut W, rle: bool) -> io::Result<()> {
+    if image.width > 0xffff || image.height > 0xffff {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "TGA images are at most 65535 pixels wide and high"));
+    }
+    let kind = match (image.channels, rle) {
+        (1, false)     => 3,
+        (1, true)      => 11,
+        (3, false) | (4, false) => 2,
+        (3, true)  | (4, true)  => 10,
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "TGA images have 1, 3 or 4 channels")),
+    };
+    let mut header = [0u8; 18];
+    header[2] = kind;
+    header[12..14].copy_from_slice(&[image.width as u8, (image.width >> 8) as u8]);
+    header[14..16].copy_from_slice(&[image.height as u8, (image.height >> 8) as u8]);
+    header[16] = 8*image.channels as u8;
+    // the number of alpha bits
+    header[17] = if image.channels == 4 { 8 } else { 0 };
+    w.write_all(&header)?;
+    let stride = (image.width as usize*image.channels).max(1);
+    for row in image.data.chunks(stride) {
+        let mut row = row.to_vec();
+        if image.channels > 1 {
+            for pixel in row.chunks_mut(image.channels) {
+                pixel.swap(0, 2);
+            }
+        }
+        if rle {
+            encode_rle(&row, image.channels, w)?;
+        } else {
+            w.write_all(&row)?;
+        }
+    }
+    Ok(())
+}
+
+/// Runs of two or more equal pixels become run-length packets, everything in between raw packets
+fn encode_rle<W: Write>(row: &[u8], channels: usize, w: &mut W) -> io::Result<()> {
+    let pixels: Vec<&[u8]> = row.chunks(channels).collect();
+    let mut i = 0;
+    while i < pixels.len() {
+        let mut run = 1;
+        while i + run < pixels.len() && run < 128 && pixels[i + run] == pixels[i] {
+            run += 1;
+        }
+        if run > 1 {
+            w.write_all(&[0x80 | (run - 1) as u8])?;
+            w.write_all(pixels[i])?;
+            i += run;
+            continue;
+        }
+        // a raw packet ends where the next run begins
+        let mut count = 1;
+        while i + count < pixels.len() && count < 128
+            && !(i + count + 1 < pixels.len() && pixels[i + count] == pixels[i + count + 1]) {
+            count += 1;
+        }
+        w.write_all(&[(count - 1) as u8])?;
+        w.write_all(&row[i*channels..(i + count)*channels])?;
+        i += count;
+    }
+    Ok(())
+}
+
+fn read_u16(bytes: &[u8]) -> u16 {
+    bytes[0] as u16 | (bytes[1] as u16) << 8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A gradient with a few repeated pixels, so that both kinds of packets occur
+    fn image(channels: usize) -> Image {
+        let mut image = Image::new(200, 3, channels);
+        for y in 0..3 {
+            for x in 0..200 {
+                let value = if x < 150 { (x/3 + 40*y) as u8 } else { 7 };
+                for (c, byte) in image.pixel_mut(x, y).iter_mut().enumerate() {
+                    *byte = value.wrapping_add(50*c as u8);
+                }
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn round_trip() {
+        for &channels in [1, 3, 4].iter() {
+            for &rle in [false, true].iter() {
+                let mut bytes = Vec::new();
+                encode(&image(channels), &mut bytes, rle).unwrap();
+                assert_eq!(bytes[16] as usize, 8*channels);
+                if rle {
+                    assert!(bytes.len() < 18 + 600*channels);
+                } else {
+                    assert_eq!(bytes.len(), 18 + 600*channels);
+                }
+                assert_eq!(decode(&bytes), Ok(image(channels)));
+            }
+        }
+        // red is stored after green and blue
+        let mut bytes = Vec::new();
+        encode(&Image{width: 1, height: 1, channels: 3, data: vec![1, 2, 3]}, &mut bytes, false).unwrap();
+        assert_eq!(&bytes[18..], &[3, 2, 1]);
+    }
+
+    #[test]
+    fn origin_and_packets() {
+        // a 2x2 RGB image stored top row first and right to left, with a run-length packet
+        // crossing from the top row into the bottom one
+        let mut bytes = vec![0, 0, 10, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 0, 2, 0, 24, 0x30];
+        bytes.extend(&[0x82, 0, 0, 255]);
+        bytes.extend(&[0x00, 0, 255, 0]);
+        let image = decode(&bytes).unwrap();
+        // the pixel stored last is at the bottom left
+        assert_eq!(image.pixel(0, 0), &[0, 255, 0]);
+        assert_eq!(image.pixel(1, 0), &[255, 0, 0]);
+        assert_eq!(image.pixel(0, 1), &[255, 0, 0]);
+        assert_eq!(decode(&bytes[..bytes.len() - 1]).err(), Some(String::from("truncated run-length encoded data")));
+        bytes[2] = 1;
+        assert!(decode(&bytes).is_err());
+        // a 65535 by 65535 RGBA header in front of a single packet
+        let mut bytes = vec![0, 0, 10, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 0xff, 0xff, 32, 8];
+        bytes.extend(&[0xff, 1, 2, 3, 4]);
+        assert_eq!(decode(&bytes).err(), Some(String::from("truncated run-length encoded data")));
+    }
+
+}
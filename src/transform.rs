@@ -0,0 +1,94 @@
+use geo;
+use geo::Vector;
+
+/// Placement of an object: scaled first, then rotated about x, y and z in turn, then translated
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub translation: geo::Vec3f,
+    /// Rotation angles about the x, y and z axes, in radians
+    pub rotation: geo::Vec3f,
+    pub scale: geo::Vec3f,
+}
+
+impl Transform {
+
+    pub fn new(translation: geo::Vec3f, rotation: geo::Vec3f, scale: geo::Vec3f) -> Transform {
+        Transform{translation, rotation, scale}
+    }
+
+    pub fn identity() -> Transform {
+        Transform::new(geo::Vec3f::new(0., 0., 0.), geo::Vec3f::new(0., 0., 0.), geo::Vec3f::new(1., 1., 1.))
+    }
+
+    pub fn matrix(&self) -> geo::Mat4 {
+        let mut mat = geo::Mat4::translation(&self.translation);
+        mat = &mat*&geo::Mat4::rotation_z(self.rotation.z);
+        mat = &mat*&geo::Mat4::rotation_y(self.rotation.y);
+        mat = &mat*&geo::Mat4::rotation_x(self.rotation.x);
+        &mat*&geo::Mat4::scale(&self.scale)
+    }
+
+    /// Split an affine matrix without shear into scale, rotation and translation. A mirroring
+    /// matrix gets a negative x scale.
+    pub fn from_matrix(m: &geo::Mat4) -> Transform {
+        let m = m.m;
+        let translation = geo::Vec3f::new(m[0][3], m[1][3], m[2][3]);
+        let column = |j: usize| geo::Vec3f::new(m[0][j], m[1][j], m[2][j]);
+        let mut scale = geo::Vec3f::new(column(0).norm(), column(1).norm(), column(2).norm());
+        if column(0).dot(&column(1).cross(&column(2))) < 0. {
+            scale.x = -scale.x;
+        }
+        let r = |i: usize, j: usize| {
+            let s = [scale.x, scale.y, scale.z][j];
+            if s != 0. { m[i][j]/s } else { 0. }
+        };
+        // the matrix is rz*ry*rx, so r(2, 0) is -sin(y)
+        let y = (-r(2, 0)).max(-1.).min(1.).asin();
+        let rotation = if r(2, 0).abs() < 1. - 1e-12 {
+            geo::Vec3f::new(r(2, 1).atan2(r(2, 2)), y, r(1, 0).atan2(r(0, 0)))
+        } else {
+            // gimbal lock: only the difference of the x and z angles matters
+            geo::Vec3f::new(0., y, (-r(0, 1)).atan2(r(1, 1)))
+        };
+        Transform{translation, rotation, scale}
+    }
+
+}
+
+impl Default for Transform {
+    fn default() -> Transform {
+        Transform::identity()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn transform_order() {
+        let transform = Transform::new(geo::Vec3f::new(1., 0., 0.), geo::Vec3f::new(0., 0., PI/2.),
+                                       geo::Vec3f::new(2., 2., 2.));
+        let p = transform.matrix().transform_point(&geo::Vec3f::new(1., 0., 0.));
+        assert!((&p - &geo::Vec3f::new(1., 2., 0.)).norm() < 1e-12);
+    }
+
+    #[test]
+    fn decompose_matrix() {
+        let transforms = [
+            Transform::new(geo::Vec3f::new(1., -2., 3.), geo::Vec3f::new(0.3, -1.2, 2.5), geo::Vec3f::new(2., 1., 0.5)),
+            Transform::new(geo::Vec3f::new(0., 0., 0.), geo::Vec3f::new(0.4, PI/2., 0.1), geo::Vec3f::new(1., 1., 1.)),
+            Transform::new(geo::Vec3f::new(0., 1., 0.), geo::Vec3f::new(0., 0., 0.), geo::Vec3f::new(-1., 3., 1.)),
+        ];
+        let p = geo::Vec3f::new(0.5, -1., 2.);
+        for transform in transforms.iter() {
+            let decomposed = Transform::from_matrix(&transform.matrix());
+            let (a, b) = (transform.matrix().transform_point(&p), decomposed.matrix().transform_point(&p));
+            assert!((&a - &b).norm() < 1e-9, "{:?} became {:?}", transform, decomposed);
+        }
+        let decomposed = Transform::from_matrix(&transforms[0].matrix());
+        assert!((&decomposed.rotation - &transforms[0].rotation).norm() < 1e-9);
+    }
+
+}
@@ -0,0 +1,169 @@
+use std::f64::consts::PI;
+use std::path::Path;
+use std::str::FromStr;
+use geo;
+use render::Scene;
+use transform::Transform;
+
+/// What moves between the frames of a turntable
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Motion {
+    /// The camera circles the scene; lights stay fixed in the world, so the lighting changes
+    Orbit,
+    /// The scene graph turns in front of the camera and the lights, as on a real turntable
+    Rotate,
+}
+
+impl FromStr for Motion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Motion, String> {
+        match s {
+            "orbit"  => Ok(Motion::Orbit),
+            "rotate" => Ok(Motion::Rotate),
+            _        => Err(format!("unknown turntable motion '{}'", s)),
+        }
+    }
+}
+
+/// A full turn about the vertical axis through the center of the scene bounds, in `frames` steps
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Turntable {
+    pub frames: usize,
+    pub motion: Motion,
+}
+
+impl Turntable {
+
+    pub fn new(frames: usize, motion: Motion) -> Turntable {
+        Turntable{frames, motion}
+    }
+
+    /// Angle the scene appears turned by in frame `i`, in radians
+    pub fn angle(&self, i: usize) -> f64 {
+        2.*PI*i as f64/self.frames as f64
+    }
+
+    /// Draw every frame and pass it to `frame` along with its index. The loaded meshes and textures
+    /// are shared by all frames; the camera and the root transform are restored afterwards.
+    pub fn render<F, E>(&self, scene: &mut Scene, mut frame: F) -> Result<(), E>
+        where F: FnMut(usize, &Scene) -> Result<(), E>
+    {
+        let center = scene.bounds().map(|bounds| bounds.center()).unwrap_or(geo::Vec3f::new(0., 0., 0.));
+        let camera = scene.active_camera();
+        let camera_node = scene.camera_node().map(String::from);
+        let root = scene.root().transform;
+        let mut result = Ok(());
+        for i in 0..self.frames {
+            match self.motion {
+                Motion::Orbit => {
+                    // the camera goes the other way round for the scene to turn like with Rotate
+                    let m = turn(&center, -self.angle(i));
+                    let mut moved = camera;
+                    moved.eye = m.transform_point(&camera.eye);
+                    moved.center = m.transform_point(&camera.center);
+                    moved.up = m.transform_vector(&camera.up);
+                    scene.set_camera(moved);
+                },
+                Motion::Rotate => {
+                    let m = &turn(&center, self.angle(i))*&root.matrix();
+                    scene.root_mut().transform = Transform::from_matrix(&m);
+                },
+            }
+            scene.draw();
+            result = frame(i, scene);
+            if result.is_err() {
+                break;
+            }
+        }
+        scene.root_mut().transform = root;
+        match camera_node {
+            Some(name) => scene.use_camera_node(&name).unwrap(),
+            None       => scene.set_camera(camera),
+        }
+        result
+    }
+
+}
+
+/// Rotation by `angle` about the vertical axis through `center`
+fn turn(center: &geo::Vec3f, angle: f64) -> geo::Mat4 {
+    let back = geo::Vec3f::new(-center.x, -center.y, -center.z);
+    &(&geo::Mat4::translation(center)*&geo::Mat4::rotation_y(angle))*&geo::Mat4::translation(&back)
+}
+
+/// `path` with the frame number before its extension, padded to at least three digits:
+/// head.png becomes head_007.png
+pub fn frame_path(path: &str, i: usize, frames: usize) -> String {
+    let digits = (frames.max(1) - 1).to_string().len().max(3);
+    let p = Path::new(path);
+    let stem = p.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let name = match p.extension() {
+        Some(ext) => format!("{}_{:03$}.{}", stem, i, ext.to_string_lossy(), digits),
+        None      => format!("{}_{:02$}", stem, i, digits),
+    };
+    p.with_file_name(name).to_string_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+    use framebuffer::{Format, Framebuffer};
+    use graph::Node;
+    use obj::Obj;
+    use camera::Camera;
+
+    /// A scene with a single off-center triangle to the right of the bounds center
+    fn scene() -> Scene {
+        let vertices = vec![geo::Vec3f::new(1., 0., 0.), geo::Vec3f::new(2., 0., 0.), geo::Vec3f::new(1.5, 1., 0.),
+                            geo::Vec3f::new(0., 0., 0.)];
+        let faces = vec![geo::Vec3i::new(0, 1, 2)];
+        let mut scene = Scene::new(Vec::new(), Framebuffer::new(32, 32, Format::Rgb8));
+        scene.add_node(Node::new("mesh").with_mesh(Rc::new(Obj::new(vertices, faces))));
+        scene.set_camera(Camera::new(geo::Vec3f::new(1., 0.5, 4.), geo::Vec3f::new(1., 0.5, 0.),
+                                     geo::Vec3f::new(0., 1., 0.)).with_extent(1.5));
+        scene
+    }
+
+    fn image(scene: &Scene) -> Vec<u8> {
+        scene.framebuffer().to_image().raw_pixels()
+    }
+
+    #[test]
+    fn full_turn() {
+        let mut still = scene();
+        still.draw();
+        for &motion in [Motion::Orbit, Motion::Rotate].iter() {
+            let mut scene = scene();
+            let camera = scene.active_camera();
+            let mut images = Vec::new();
+            Turntable::new(4, motion).render(&mut scene, |i, scene| -> Result<(), ()> {
+                images.push((i, image(scene)));
+                Ok(())
+            }).unwrap();
+            assert_eq!(images.iter().map(|&(i, _)| i).collect::<Vec<_>>(), vec![0, 1, 2, 3]);
+            // the first frame is the scene as it was, the others show it turned
+            assert!(images[0].1 == image(&still));
+            assert!(images[1].1 != images[0].1 && images[2].1 != images[0].1);
+            assert_eq!(scene.active_camera(), camera);
+            assert_eq!(scene.root().transform, Transform::identity());
+        }
+        // an error stops the turn
+        let mut count = 0;
+        let result = Turntable::new(4, Motion::Rotate).render(&mut scene(), |i, _| {
+            count += 1;
+            if i == 1 { Err("full disk") } else { Ok(()) }
+        });
+        assert_eq!((result, count), (Err("full disk"), 2));
+    }
+
+    #[test]
+    fn frame_paths() {
+        assert_eq!(frame_path("out/head.png", 7, 36), "out/head_007.png");
+        assert_eq!(frame_path("head.tga", 12, 1440), "head_0012.tga");
+        assert_eq!(frame_path("frames", 0, 1), "frames_000");
+        assert_eq!("rotate".parse(), Ok(Motion::Rotate));
+    }
+
+}